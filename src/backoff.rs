@@ -3,6 +3,7 @@ use core::cell::Cell;
 use core::sync::atomic;
 
 const SPIN_LIMIT: u32 = 6;
+const YIELD_LIMIT: u32 = 10;
 
 /// Performs exponential backoff in spin loops.
 ///
@@ -41,4 +42,27 @@ impl Backoff {
             self.step.set(self.step.get() + 1);
         }
     }
+
+    /// Backs off in a blocking loop.
+    ///
+    /// This method should be used when we need to wait for another thread to make progress.
+    ///
+    /// The processor may yield using the *YIELD* or *PAUSE* instruction and the current thread
+    /// may yield by giving up a timeslice to the OS scheduler.
+    #[inline]
+    pub fn snooze(&self) {
+        if self.step.get() <= SPIN_LIMIT {
+            for _ in 0..1 << self.step.get() {
+                // `hint::spin_loop` requires Rust 1.49.
+                #[allow(deprecated)]
+                atomic::spin_loop_hint();
+            }
+        } else {
+            std::thread::yield_now();
+        }
+
+        if self.step.get() <= YIELD_LIMIT {
+            self.step.set(self.step.get() + 1);
+        }
+    }
 }
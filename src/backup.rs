@@ -0,0 +1,552 @@
+use std::{
+    convert::TryFrom,
+    fs::{self, File},
+    io::{BufRead, BufReader, Read, Seek, SeekFrom, Write},
+    ops::Range,
+    path::Path,
+};
+
+use crate::{
+    pagecache::{read_segment_header, slots_written_since},
+    *,
+};
+
+const MANIFEST_NAME: &str = "backup_manifest";
+const COPY_CHUNK_SIZE: usize = 1024 * 1024;
+
+/// A description of a backup created by `Db::backup_to` or
+/// `Db::backup_incremental`, which is stored in the backup
+/// directory alongside the files it describes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BackupManifest {
+    /// Every write that was made stable at or before this LSN
+    /// is contained in the backup.
+    pub stable_lsn: Lsn,
+    /// For an incremental backup, the `stable_lsn` of the
+    /// backup that it was taken on top of. `None` for a
+    /// full backup.
+    pub previous_stable_lsn: Option<Lsn>,
+    /// The files that make up the backup.
+    pub files: Vec<BackupFile>,
+}
+
+/// A single file in a backup.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BackupFile {
+    /// The path of the file, relative to the backup directory.
+    pub name: String,
+    /// The length of the original file in bytes.
+    pub len: u64,
+    /// The CRC32 of the contents of the file in the backup.
+    pub crc32: u32,
+    /// For files in an incremental backup, the byte ranges of
+    /// the original file that were copied, stored one after the
+    /// other in the backed up file. `None` if the whole file
+    /// was copied.
+    pub ranges: Option<Vec<Range<u64>>>,
+}
+
+/// The progress of a running `Db::backup_to_with_progress` call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BackupProgress {
+    /// The number of bytes that have been copied so far.
+    pub bytes_copied: u64,
+    /// The total number of bytes that will be copied.
+    pub bytes_total: u64,
+}
+
+impl BackupManifest {
+    /// Reads the manifest of the backup stored in the provided
+    /// directory.
+    pub fn read<P: AsRef<Path>>(backup_path: P) -> Result<BackupManifest> {
+        let mut bytes = vec![];
+        File::open(backup_path.as_ref().join(MANIFEST_NAME))?
+            .read_to_end(&mut bytes)?;
+
+        let mut stable_lsn_opt = None;
+        let mut previous_stable_lsn = None;
+        let mut files: Vec<BackupFile> = vec![];
+
+        for line_res in BufReader::new(&*bytes).lines() {
+            let line = line_res?;
+            let mut split = line.splitn(2, ": ");
+            match (split.next(), split.next()) {
+                (Some("stable_lsn"), Some(raw)) => {
+                    stable_lsn_opt = Some(raw.parse().map_err(|_| {
+                        error!("failed to parse stable_lsn value: {}", raw);
+                        Error::corruption(None)
+                    })?);
+                }
+                (Some("previous_stable_lsn"), Some(raw)) => {
+                    previous_stable_lsn = Some(raw.parse().map_err(|_| {
+                        error!(
+                            "failed to parse previous_stable_lsn value: {}",
+                            raw
+                        );
+                        Error::corruption(None)
+                    })?);
+                }
+                (Some("file"), Some(raw)) => {
+                    files.push(BackupFile::parse(raw)?);
+                }
+                (Some("ranges"), Some(raw)) => {
+                    if let Some(file) = files.last_mut() {
+                        file.ranges = Some(parse_ranges(raw)?);
+                    } else {
+                        error!("backup manifest has ranges before any file");
+                        return Err(Error::corruption(None));
+                    }
+                }
+                _ => {
+                    error!("failed to parse backup manifest line: {}", line);
+                    return Err(Error::corruption(None));
+                }
+            }
+        }
+
+        if let Some(stable_lsn) = stable_lsn_opt {
+            Ok(BackupManifest { stable_lsn, previous_stable_lsn, files })
+        } else {
+            error!("backup manifest is missing its stable_lsn");
+            Err(Error::corruption(None))
+        }
+    }
+
+    /// Re-reads every file of the backup stored in the provided
+    /// directory, returning `Error::Corruption` if any of them
+    /// does not match its length and checksum in this manifest.
+    pub fn verify<P: AsRef<Path>>(&self, backup_path: P) -> Result<()> {
+        for file in &self.files {
+            let path = backup_path.as_ref().join(&file.name);
+
+            let mut hasher = crc32fast::Hasher::new();
+            let mut len = 0;
+            let mut buf = vec![0; COPY_CHUNK_SIZE];
+            let mut f = File::open(&path)?;
+            loop {
+                let read = f.read(&mut buf)?;
+                if read == 0 {
+                    break;
+                }
+                hasher.update(&buf[..read]);
+                len += read as u64;
+            }
+
+            let crc32 = hasher.finalize();
+            if len != file.stored_len() || crc32 != file.crc32 {
+                error!(
+                    "backup file {:?} does not match the manifest. \
+                     expected len {} crc {}, found len {} crc {}",
+                    path, file.len, file.crc32, len, crc32
+                );
+                return Err(Error::corruption(None));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn write(&self, backup_path: &Path) -> Result<()> {
+        let mut out = vec![];
+        writeln!(&mut out, "stable_lsn: {}", self.stable_lsn)?;
+        if let Some(previous_stable_lsn) = self.previous_stable_lsn {
+            writeln!(&mut out, "previous_stable_lsn: {}", previous_stable_lsn)?;
+        }
+        for file in &self.files {
+            writeln!(
+                &mut out,
+                "file: {} {} {}",
+                file.len, file.crc32, file.name
+            )?;
+            if let Some(ranges) = &file.ranges {
+                let formatted: Vec<String> = ranges
+                    .iter()
+                    .map(|range| format!("{}-{}", range.start, range.end))
+                    .collect();
+                writeln!(&mut out, "ranges: {}", formatted.join(" "))?;
+            }
+        }
+
+        // write the manifest last and atomically, so that a
+        // backup is only ever considered complete if every
+        // one of its files has been written
+        let generating =
+            backup_path.join(format!("{}.generating", MANIFEST_NAME));
+        let mut f = File::create(&generating)?;
+        f.write_all(&out)?;
+        f.sync_all()?;
+        fs::rename(&generating, backup_path.join(MANIFEST_NAME))?;
+        maybe_fsync_directory(backup_path)?;
+
+        Ok(())
+    }
+}
+
+impl BackupFile {
+    fn parse(raw: &str) -> Result<BackupFile> {
+        let mut split = raw.splitn(3, ' ');
+        let parsed = (
+            split.next().and_then(|len| len.parse().ok()),
+            split.next().and_then(|crc32| crc32.parse().ok()),
+            split.next(),
+        );
+
+        if let (Some(len), Some(crc32), Some(name)) = parsed {
+            Ok(BackupFile { name: name.to_owned(), len, crc32, ranges: None })
+        } else {
+            error!("failed to parse backup manifest file entry: {}", raw);
+            Err(Error::corruption(None))
+        }
+    }
+
+    // the number of bytes that are stored in the backup directory
+    fn stored_len(&self) -> u64 {
+        match &self.ranges {
+            Some(ranges) => ranges.iter().map(|r| r.end - r.start).sum(),
+            None => self.len,
+        }
+    }
+}
+
+fn parse_ranges(raw: &str) -> Result<Vec<Range<u64>>> {
+    let mut ranges = vec![];
+
+    for item in raw.split_whitespace() {
+        let mut split = item.splitn(2, '-');
+        let parsed = (
+            split.next().and_then(|start| start.parse().ok()),
+            split.next().and_then(|end| end.parse().ok()),
+        );
+
+        if let (Some(start), Some(end)) = parsed {
+            ranges.push(start..end);
+        } else {
+            error!("failed to parse backup manifest range: {}", item);
+            return Err(Error::corruption(None));
+        }
+    }
+
+    Ok(ranges)
+}
+
+pub(crate) fn backup<F>(
+    context: &Context,
+    previous: Option<&BackupManifest>,
+    backup_path: &Path,
+    mut progress: F,
+) -> Result<BackupManifest>
+where
+    F: FnMut(BackupProgress),
+{
+    ensure_empty(backup_path, "back up into")?;
+    fs::create_dir_all(backup_path.join("heap"))?;
+
+    let manifest = context.pagecache.with_paused_reclamation(|| {
+        // everything written before the backup started
+        // must be part of it.
+        context.pagecache.flush()?;
+        let stable_lsn = context.pagecache.log.stable_offset();
+
+        let previous_stable_lsn = previous.map(|p| p.stable_lsn);
+        if let Some(since) = previous_stable_lsn {
+            if since > stable_lsn {
+                return Err(Error::Unsupported(format!(
+                    "cannot take an incremental backup on top of a backup \
+                     with stable_lsn {}, which is ahead of the stable_lsn \
+                     {} of this database",
+                    since, stable_lsn
+                )));
+            }
+        }
+
+        let source_path = context.get_path();
+
+        let mut files = vec![];
+        for name in source_files(context)? {
+            let len = fs::metadata(source_path.join(&name))?.len();
+            let ranges = if let Some(since) = previous_stable_lsn {
+                changed_ranges(context, &name, len, since)?
+            } else {
+                None
+            };
+            files.push(BackupFile { name, len, crc32: 0, ranges });
+        }
+
+        let bytes_total = files.iter().map(BackupFile::stored_len).sum();
+        let mut bytes_copied = 0;
+        progress(BackupProgress { bytes_copied, bytes_total });
+
+        for file in &mut files {
+            let whole_file = 0..file.len;
+            let ranges = file
+                .ranges
+                .as_deref()
+                .unwrap_or_else(|| std::slice::from_ref(&whole_file));
+            file.crc32 = copy_ranges(
+                &source_path.join(&file.name),
+                &backup_path.join(&file.name),
+                ranges,
+                |copied| {
+                    bytes_copied += copied;
+                    progress(BackupProgress { bytes_copied, bytes_total });
+                },
+            )?;
+        }
+
+        Ok(BackupManifest { stable_lsn, previous_stable_lsn, files })
+    })?;
+
+    maybe_fsync_directory(backup_path.join("heap"))?;
+    manifest.write(backup_path)?;
+    manifest.verify(backup_path)?;
+
+    Ok(manifest)
+}
+
+/// Reassembles a chain of backups into a database in the
+/// provided directory, which must be empty or not exist yet.
+///
+/// The chain starts with a full backup created by
+/// `Db::backup_to`, followed by any number of incremental
+/// backups created by `Db::backup_incremental`, each taken on
+/// top of the one before it. Every backup is verified against
+/// its `BackupManifest` before it is applied. The restored
+/// database can be opened by pointing `Config::path` at it.
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let config = sled::Config::new().temporary(true);
+/// # let db = config.open()?;
+/// # let dir = std::env::temp_dir().join("sled_restore_backup_doctest");
+/// # let _ = std::fs::remove_dir_all(&dir);
+/// let full_path = dir.join("full");
+/// let incremental_path = dir.join("incremental");
+/// let restore_path = dir.join("restored");
+///
+/// db.insert(b"a", b"1")?;
+/// let full = db.backup_to(&full_path)?;
+///
+/// db.insert(b"b", b"2")?;
+/// db.backup_incremental(&full, &incremental_path)?;
+///
+/// sled::restore_backup(&[&full_path, &incremental_path], &restore_path)?;
+///
+/// let restored = sled::Config::new().path(&restore_path).open()?;
+/// assert_eq!(restored.get(b"a")?, Some(sled::IVec::from(b"1")));
+/// assert_eq!(restored.get(b"b")?, Some(sled::IVec::from(b"2")));
+/// # drop(restored);
+/// # std::fs::remove_dir_all(&dir)?;
+/// # Ok(()) }
+/// ```
+pub fn restore_backup<P, Q>(backup_paths: &[P], restore_path: Q) -> Result<()>
+where
+    P: AsRef<Path>,
+    Q: AsRef<Path>,
+{
+    let restore_dir = restore_path.as_ref();
+
+    if backup_paths.is_empty() {
+        return Err(Error::Unsupported(
+            "cannot restore an empty chain of backups".to_owned(),
+        ));
+    }
+
+    let mut manifests = vec![];
+    let mut previous_stable_lsn = None;
+    for backup in backup_paths {
+        let backup_path = backup.as_ref();
+        let manifest = BackupManifest::read(backup_path)?;
+        if manifest.previous_stable_lsn != previous_stable_lsn {
+            return Err(Error::Unsupported(format!(
+                "the backup in {:?} was not taken on top of the backup \
+                 before it in the chain",
+                backup_path
+            )));
+        }
+        manifest.verify(backup_path)?;
+        previous_stable_lsn = Some(manifest.stable_lsn);
+        manifests.push(manifest);
+    }
+
+    ensure_empty(restore_dir, "restore into")?;
+    fs::create_dir_all(restore_dir.join("heap"))?;
+
+    let mut previous_manifest: Option<&BackupManifest> = None;
+    for (backup, manifest) in backup_paths.iter().zip(&manifests) {
+        for file in &manifest.files {
+            apply_file(
+                &backup.as_ref().join(&file.name),
+                &restore_dir.join(&file.name),
+                file,
+            )?;
+        }
+
+        // drop files that are no longer part of the database,
+        // such as older snapshots
+        if let Some(previous) = previous_manifest {
+            for file in &previous.files {
+                if !manifest.files.iter().any(|f| f.name == file.name) {
+                    fs::remove_file(restore_dir.join(&file.name))?;
+                }
+            }
+        }
+
+        previous_manifest = Some(manifest);
+    }
+
+    maybe_fsync_directory(restore_dir.join("heap"))?;
+    maybe_fsync_directory(restore_dir)?;
+
+    Ok(())
+}
+
+fn ensure_empty(path: &Path, action: &str) -> Result<()> {
+    if path.exists() && path.read_dir()?.next().is_some() {
+        Err(Error::Unsupported(format!(
+            "cannot {} {:?} because it is not empty",
+            action, path
+        )))
+    } else {
+        Ok(())
+    }
+}
+
+// Returns the paths of every file needed to recover the database,
+// relative to its directory. The log file is copied before the heap
+// files because heap slots are always written before the log
+// messages that refer to them.
+fn source_files(context: &Context) -> Result<Vec<String>> {
+    let mut names = vec!["conf".to_owned()];
+
+    let mut snapshots = context.get_snapshot_files()?;
+    snapshots.sort();
+    if let Some(snapshot) = snapshots.pop() {
+        let file_name = snapshot.file_name().unwrap().to_string_lossy();
+        names.push(file_name.into_owned());
+    }
+
+    names.push("db".to_owned());
+
+    let mut slabs = vec![];
+    for entry in context.get_path().join("heap").read_dir()? {
+        let file_name = entry?.file_name().to_string_lossy().into_owned();
+        slabs.push(format!("heap/{}", file_name));
+    }
+    slabs.sort();
+    names.append(&mut slabs);
+
+    Ok(names)
+}
+
+// Returns the byte ranges of the first `len` bytes of a source file
+// that may have changed since the provided LSN, or `None` if the
+// whole file needs to be copied. Log segments and heap slots that
+// were completely written before it are left in place by the
+// database until they are freed and rewritten with a newer LSN, so
+// the previous backups in the chain already contain them.
+fn changed_ranges(
+    context: &Context,
+    name: &str,
+    len: u64,
+    since: Lsn,
+) -> Result<Option<Vec<Range<u64>>>> {
+    let file = File::open(context.get_path().join(name))?;
+
+    if name == "db" {
+        let segment_size = context.segment_size as u64;
+        let mut ranges: Vec<Range<u64>> = vec![];
+
+        for segment_base in (0..len).step_by(context.segment_size) {
+            let header = read_segment_header(&file, segment_base)?;
+            let segment_end =
+                header.lsn.saturating_add(Lsn::try_from(segment_size).unwrap());
+            if header.ok && segment_end <= since {
+                continue;
+            }
+
+            let end = std::cmp::min(segment_base + segment_size, len);
+            match ranges.last_mut() {
+                Some(last) if last.end == segment_base => last.end = end,
+                _ => ranges.push(segment_base..end),
+            }
+        }
+
+        Ok(Some(ranges))
+    } else if let Ok(slab) = Path::new(name).strip_prefix("heap") {
+        let slab_id = slab.to_string_lossy().parse().map_err(|_| {
+            error!("unexpected file in heap directory: {}", name);
+            Error::corruption(None)
+        })?;
+        Ok(Some(slots_written_since(&file, slab_id, len, since)?))
+    } else {
+        Ok(None)
+    }
+}
+
+// Copies the provided byte ranges of a file one after the other,
+// calling `on_copy` with the number of bytes copied after each
+// chunk. Returns the CRC32 of the copied bytes.
+fn copy_ranges<F>(
+    from: &Path,
+    to: &Path,
+    ranges: &[Range<u64>],
+    mut on_copy: F,
+) -> Result<u32>
+where
+    F: FnMut(u64),
+{
+    let mut source = File::open(from)?;
+    let mut dest = File::create(to)?;
+    let mut hasher = crc32fast::Hasher::new();
+    let mut buf = vec![0; COPY_CHUNK_SIZE];
+
+    for range in ranges {
+        source.seek(SeekFrom::Start(range.start))?;
+        let mut chunk = (&mut source).take(range.end - range.start);
+        loop {
+            let read = chunk.read(&mut buf)?;
+            if read == 0 {
+                break;
+            }
+            hasher.update(&buf[..read]);
+            dest.write_all(&buf[..read])?;
+            on_copy(read as u64);
+        }
+    }
+
+    dest.sync_all()?;
+
+    Ok(hasher.finalize())
+}
+
+// Writes a file from a backup into the database that is being
+// restored, either replacing it or patching in the copied ranges.
+fn apply_file(from: &Path, to: &Path, file: &BackupFile) -> Result<()> {
+    let ranges = if let Some(ranges) = &file.ranges {
+        ranges
+    } else {
+        let whole_file = 0..file.len;
+        copy_ranges(from, to, std::slice::from_ref(&whole_file), |_| {})?;
+        return Ok(());
+    };
+
+    let mut source = File::open(from)?;
+    let mut dest = fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .truncate(false)
+        .open(to)?;
+
+    for range in ranges {
+        dest.seek(SeekFrom::Start(range.start))?;
+        let mut chunk = (&mut source).take(range.end - range.start);
+        std::io::copy(&mut chunk, &mut dest)?;
+    }
+
+    dest.set_len(file.len)?;
+    dest.sync_all()?;
+
+    Ok(())
+}
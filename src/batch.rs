@@ -1,5 +1,7 @@
 #![allow(unused_results)]
 
+use std::ops::{Bound, RangeBounds};
+
 use super::*;
 
 /// A batch of updates that will
@@ -31,9 +33,18 @@ use super::*;
 #[derive(Debug, Default, Clone, PartialEq, Eq)]
 pub struct Batch {
     pub(crate) writes: Map<IVec, Option<IVec>>,
+    pub(crate) removed_ranges: Vec<(Bound<IVec>, Bound<IVec>)>,
+    // keys that a range removal already removed from their leaves,
+    // which are only reported to subscribers. Their values are
+    // always `None`, so they can be iterated like `writes`.
+    pub(crate) removed_keys: Map<IVec, Option<IVec>>,
 }
 
 impl Batch {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.writes.is_empty() && self.removed_ranges.is_empty()
+    }
+
     /// Set a key to a new value
     pub fn insert<K, V>(&mut self, key: K, value: V)
     where
@@ -51,10 +62,83 @@ impl Batch {
         self.writes.insert(key.into(), None);
     }
 
+    /// Remove every key that falls within the provided range.
+    ///
+    /// Range removals are applied before the other writes in
+    /// the `Batch`, so a key that is inserted after calling
+    /// this method will still be present once the `Batch` is
+    /// applied, and a key that was inserted before calling it
+    /// will be removed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// db.insert(&[1], vec![1])?;
+    /// db.insert(&[2], vec![2])?;
+    /// db.insert(&[3], vec![3])?;
+    ///
+    /// let mut batch = sled::Batch::default();
+    /// batch.remove_range::<&[u8], _>(&[1][..]..&[3][..]);
+    /// batch.insert(&[2], vec![20]);
+    ///
+    /// db.apply_batch(batch)?;
+    /// assert_eq!(db.get(&[1])?, None);
+    /// assert_eq!(db.get(&[2])?, Some(sled::IVec::from(vec![20])));
+    /// assert_eq!(db.get(&[3])?, Some(sled::IVec::from(vec![3])));
+    /// # Ok(()) }
+    /// ```
+    pub fn remove_range<K, R>(&mut self, range: R)
+    where
+        K: AsRef<[u8]>,
+        R: RangeBounds<K>,
+    {
+        let bounds = ivec_bounds(&range);
+
+        let shadowed: Vec<IVec> = self
+            .writes
+            .keys()
+            .filter(|k| bounds.contains(*k))
+            .cloned()
+            .collect();
+
+        for key in shadowed {
+            self.writes.remove(&key);
+        }
+
+        self.removed_ranges.push(bounds);
+    }
+
     /// Get a value if it is present in the `Batch`.
-    /// `Some(None)` means it's present as a deletion.
+    /// `Some(None)` means it's present as a deletion,
+    /// either directly or by falling within a removed range.
     pub fn get<K: AsRef<[u8]>>(&self, k: K) -> Option<Option<&IVec>> {
-        let inner = self.writes.get(k.as_ref())?;
-        Some(inner.as_ref())
+        if let Some(inner) = self.writes.get(k.as_ref()) {
+            return Some(inner.as_ref());
+        }
+        let key = IVec::from(k.as_ref());
+        if self.removed_ranges.iter().any(|range| range.contains(&key)) {
+            Some(None)
+        } else {
+            None
+        }
     }
 }
+
+/// Converts the bounds of a `RangeBounds` over byte-like
+/// keys into owned `IVec` bounds.
+pub(crate) fn ivec_bounds<K, R>(range: &R) -> (Bound<IVec>, Bound<IVec>)
+where
+    K: AsRef<[u8]>,
+    R: RangeBounds<K>,
+{
+    let convert = |bound: Bound<&K>| match bound {
+        Bound::Included(k) => Bound::Included(IVec::from(k.as_ref())),
+        Bound::Excluded(k) => Bound::Excluded(IVec::from(k.as_ref())),
+        Bound::Unbounded => Bound::Unbounded,
+    };
+
+    (convert(range.start_bound()), convert(range.end_bound()))
+}
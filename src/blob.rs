@@ -0,0 +1,402 @@
+use std::{
+    convert::{TryFrom, TryInto},
+    io::{self, Read, Seek, SeekFrom, Write},
+};
+
+use crate::*;
+
+/// The name of the internal tree that stores blobs.
+pub(crate) const BLOBS_TREE_ID: &[u8] = b"__sled__blobs";
+
+// Blobs are split into chunks of this many bytes, which leaves
+// room for the rest of the leaf that each chunk is stored in, so
+// that it fits into a single 64kb slot of the heap.
+const CHUNK_SIZE: usize = 60 * 1024;
+
+// The blob tree stores the chunks of every blob under
+// `CHUNK_PREFIX`, followed by the big-endian id of the blob and
+// the big-endian index of the chunk. The blob that each key of a
+// `Tree` holds is recorded under `OWNER_PREFIX`, followed by the
+// same prefix of the name of the `Tree` that the expiry tree uses
+// and the key, and the value is the id of the blob followed by its
+// length, both big-endian. The id of every blob that is still
+// being written is recorded under `PENDING_PREFIX` until it is
+// finished or abandoned, so that the chunks of blobs that a crash
+// left behind can be found without scanning every chunk.
+const CHUNK_PREFIX: u8 = 0;
+const OWNER_PREFIX: u8 = 1;
+const PENDING_PREFIX: u8 = 2;
+
+fn pending_key(blob_id: u64) -> Vec<u8> {
+    let mut ret = Vec::with_capacity(9);
+    ret.push(PENDING_PREFIX);
+    ret.extend_from_slice(&blob_id.to_be_bytes());
+    ret
+}
+
+fn chunk_key(blob_id: u64, chunk: u64) -> Vec<u8> {
+    let mut ret = chunk_prefix(blob_id);
+    ret.extend_from_slice(&chunk.to_be_bytes());
+    ret
+}
+
+fn chunk_prefix(blob_id: u64) -> Vec<u8> {
+    let mut ret = Vec::with_capacity(17);
+    ret.push(CHUNK_PREFIX);
+    ret.extend_from_slice(&blob_id.to_be_bytes());
+    ret
+}
+
+pub(crate) fn owner_key(tree_id: &[u8], key: &[u8]) -> Vec<u8> {
+    let mut ret = owner_prefix(tree_id);
+    ret.extend_from_slice(key);
+    ret
+}
+
+pub(crate) fn owner_prefix(tree_id: &[u8]) -> Vec<u8> {
+    let mut ret = vec![OWNER_PREFIX];
+    ret.extend_from_slice(&ttl::tree_prefix(tree_id));
+    ret
+}
+
+fn encode_owner(blob_id: u64, len: u64) -> IVec {
+    let mut ret = [0; 16];
+    ret[..8].copy_from_slice(&blob_id.to_be_bytes());
+    ret[8..].copy_from_slice(&len.to_be_bytes());
+    IVec::from(&ret)
+}
+
+// Returns the id and length of the blob recorded in an owner entry.
+pub(crate) fn decode_owner(owner: &[u8]) -> Result<(u64, u64)> {
+    if owner.len() != 16 {
+        return Err(Error::corruption(None));
+    }
+    let (id_bytes, len_bytes) = owner.split_at(8);
+    Ok((
+        u64::from_be_bytes(id_bytes.try_into().unwrap()),
+        u64::from_be_bytes(len_bytes.try_into().unwrap()),
+    ))
+}
+
+// Returns `true` if any key of the provided `Tree` holds a blob.
+pub(crate) fn has_blobs(
+    blobs_opt: Option<&Tree>,
+    tree_id: &[u8],
+) -> Result<bool> {
+    if let Some(blobs) = blobs_opt {
+        let first = blobs.scan_prefix(owner_prefix(tree_id)).next();
+        Ok(first.transpose()?.is_some())
+    } else {
+        Ok(false)
+    }
+}
+
+// Removes every chunk of a blob. Called while the log is pinned
+// for the write that removed the blob, or with the ids of blobs
+// that no key holds.
+pub(crate) fn remove_chunks(
+    blobs: &Tree,
+    blob_id: u64,
+    guard: &mut Guard,
+) -> Result<()> {
+    let mut chunks = blobs.scan_prefix(chunk_prefix(blob_id));
+    while let Some(chunk) = chunks.next_inner() {
+        let (chunk_key, _chunk) = chunk?;
+        while blobs.insert_inner(&chunk_key, None, true, guard)?.is_err() {}
+    }
+    Ok(())
+}
+
+// Atomically removes the record of a blob along with its chunks,
+// once the key that held it is gone.
+pub(crate) fn remove_blob(
+    blobs: &Tree,
+    owner_key: &[u8],
+    blob_id: u64,
+) -> Result<()> {
+    let _cc = concurrency_control::write();
+    let mut guard = pin();
+    let peg = blobs.context.pin_log(&guard)?;
+    while blobs.insert_inner(owner_key, None, true, &mut guard)?.is_err() {}
+    remove_chunks(blobs, blob_id, &mut guard)?;
+    peg.seal_batch()
+}
+
+// Removes the chunks of blobs that were never finished because
+// the process crashed while writing them. Must be called before
+// any `BlobWriter` is opened.
+pub(crate) fn remove_unfinished(blobs: &Tree) -> Result<()> {
+    let mut batch = Batch::default();
+    for pending_key_res in blobs.scan_prefix([PENDING_PREFIX]).keys() {
+        let pending_key = pending_key_res?;
+        if pending_key.len() != 9 {
+            return Err(Error::corruption(None));
+        }
+        let blob_id = u64::from_be_bytes(pending_key[1..].try_into().unwrap());
+        for chunk_key_res in blobs.scan_prefix(chunk_prefix(blob_id)).keys() {
+            batch.remove(chunk_key_res?);
+        }
+        batch.remove(pending_key);
+    }
+
+    if batch.is_empty() { Ok(()) } else { blobs.apply_batch(batch) }
+}
+
+/// Streams a large value into a `Tree` in chunks, without holding
+/// all of it in memory, created with `Tree::open_blob_writer`.
+///
+/// Nothing that is written becomes visible until `finish` is
+/// called, which atomically replaces the value of the key. If the
+/// `BlobWriter` is dropped without calling `finish`, or the process
+/// crashes first, the chunks that were written are removed.
+pub struct BlobWriter {
+    tree: Tree,
+    blobs: Tree,
+    key: IVec,
+    blob_id: u64,
+    buf: Vec<u8>,
+    chunks: u64,
+    len: u64,
+    finished: bool,
+}
+
+impl BlobWriter {
+    pub(crate) fn new(tree: &Tree, key: &[u8]) -> Result<Self> {
+        tree.context.verify_writable()?;
+        let blobs = tree.blobs.as_ref().unwrap().get_or_create(&pin())?;
+        let blob_id = tree.context.generate_id()?;
+
+        // recorded before any chunk is written, so that the chunks
+        // are removed if the process crashes before `finish`
+        let _ = blobs.insert(pending_key(blob_id), vec![])?;
+
+        Ok(Self {
+            tree: tree.clone(),
+            blobs,
+            key: IVec::from(key),
+            blob_id,
+            buf: Vec::with_capacity(CHUNK_SIZE),
+            chunks: 0,
+            len: 0,
+            finished: false,
+        })
+    }
+
+    fn write_chunk(&mut self) -> Result<()> {
+        let chunk = IVec::from(&*self.buf);
+        let _ =
+            self.blobs.insert(chunk_key(self.blob_id, self.chunks), chunk)?;
+        self.buf.clear();
+        self.chunks += 1;
+        Ok(())
+    }
+
+    /// Writes the rest of the blob and atomically makes it the value
+    /// of the key, replacing any value or blob that it had before.
+    /// The value of the key in the `Tree` itself is the length of
+    /// the blob, as a big-endian `u64`.
+    pub fn finish(mut self) -> Result<()> {
+        if !self.buf.is_empty() {
+            self.write_chunk()?;
+        }
+
+        let value = IVec::from(&self.len.to_be_bytes());
+        let owner = encode_owner(self.blob_id, self.len);
+        let owner_key = owner_key(&self.tree.tree_id, &self.key);
+
+        // writers are blocked so that no other write to the key can
+        // happen between replacing its value and recording its blob
+        let _cc = concurrency_control::write();
+        let mut guard = pin();
+
+        let mut batch = Batch::default();
+        batch.insert(self.key.clone(), value.clone());
+        self.tree.indexes.check_unique(&self.tree, &batch)?;
+
+        // the value and the blob that it belongs to are recovered
+        // atomically, along with the removal of its pending record
+        let peg = self.tree.context.pin_log(&guard)?;
+        self.tree.has_blobs.store(true, SeqCst);
+        while self
+            .tree
+            .insert_inner(&self.key, Some(value.clone()), false, &mut guard)?
+            .is_err()
+        {}
+        while self
+            .blobs
+            .insert_inner(&owner_key, Some(owner.clone()), true, &mut guard)?
+            .is_err()
+        {}
+        let pending_key = pending_key(self.blob_id);
+        while self
+            .blobs
+            .insert_inner(&pending_key, None, true, &mut guard)?
+            .is_err()
+        {}
+        peg.seal_batch()?;
+
+        self.finished = true;
+        Ok(())
+    }
+}
+
+impl Write for BlobWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = buf.len().min(CHUNK_SIZE - self.buf.len());
+        self.buf.extend_from_slice(&buf[..len]);
+        self.len += len as u64;
+        if self.buf.len() == CHUNK_SIZE {
+            self.write_chunk()?;
+        }
+        Ok(len)
+    }
+
+    // chunks are written as soon as they are full, and nothing is
+    // visible before `finish` is called, so there is nothing to do
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Drop for BlobWriter {
+    fn drop(&mut self) {
+        if self.finished {
+            return;
+        }
+
+        let mut batch = Batch::default();
+        for chunk in 0..self.chunks {
+            batch.remove(chunk_key(self.blob_id, chunk));
+        }
+        batch.remove(pending_key(self.blob_id));
+        if let Err(e) = self.blobs.apply_batch(batch) {
+            error!("failed to remove the chunks of an unfinished blob: {}", e);
+        }
+    }
+}
+
+/// Streams a value that was written with a `BlobWriter` out of a
+/// `Tree` in chunks, created with `Tree::open_blob_reader`.
+///
+/// Only one chunk of the blob is held in memory at a time. Reading
+/// returns an error if the blob is replaced or removed while it is
+/// being read.
+pub struct BlobReader {
+    blobs: Tree,
+    blob_id: u64,
+    len: u64,
+    pos: u64,
+    chunk: Option<(u64, IVec)>,
+}
+
+impl BlobReader {
+    pub(crate) fn open(tree: &Tree, key: &[u8]) -> Result<Option<Self>> {
+        let blobs = if let Some(blobs) = tree.blobs_tree() {
+            blobs
+        } else {
+            return Ok(None);
+        };
+
+        let mut guard = pin();
+        let _cc = concurrency_control::read();
+        let value = loop {
+            if let Ok(value) = tree.get_inner(key, &mut guard)? {
+                break value;
+            }
+        };
+        if value.is_none() || !tree.has_blobs.load(SeqCst) {
+            return Ok(None);
+        }
+        let owner_key = owner_key(&tree.tree_id, key);
+        let owner_opt = loop {
+            if let Ok(owner_opt) = blobs.get_inner(&owner_key, &mut guard)? {
+                break owner_opt;
+            }
+        };
+
+        if let Some(owner) = owner_opt {
+            let (blob_id, len) = decode_owner(&owner)?;
+            Ok(Some(Self { blobs, blob_id, len, pos: 0, chunk: None }))
+        } else {
+            Ok(None)
+        }
+    }
+
+    /// Returns the length of the blob in bytes.
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    /// Returns `true` if the blob is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+impl Read for BlobReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if self.pos >= self.len || buf.is_empty() {
+            return Ok(0);
+        }
+
+        let chunk_size = CHUNK_SIZE as u64;
+        let index = self.pos / chunk_size;
+        let chunk = match &self.chunk {
+            Some((cached, chunk)) if *cached == index => chunk.clone(),
+            _ => {
+                let chunk = self
+                    .blobs
+                    .get(chunk_key(self.blob_id, index))?
+                    .ok_or_else(|| {
+                        io::Error::new(
+                            io::ErrorKind::NotFound,
+                            "the blob was replaced or removed while \
+                             it was being read",
+                        )
+                    })?;
+                self.chunk = Some((index, chunk.clone()));
+                chunk
+            }
+        };
+
+        let offset = usize::try_from(self.pos % chunk_size).unwrap();
+        let available = chunk.get(offset..).unwrap_or(&[]);
+        if available.is_empty() {
+            return Err(Error::corruption(None).into());
+        }
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.pos += len as u64;
+        Ok(len)
+    }
+}
+
+impl Seek for BlobReader {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let (base, offset) = match pos {
+            SeekFrom::Start(offset) => {
+                self.pos = offset;
+                return Ok(offset);
+            }
+            SeekFrom::End(offset) => (self.len, offset),
+            SeekFrom::Current(offset) => (self.pos, offset),
+        };
+
+        let new_pos = match u64::try_from(offset) {
+            Ok(forward) => base.checked_add(forward),
+            Err(_) => u64::try_from(-i128::from(offset))
+                .ok()
+                .and_then(|back| base.checked_sub(back)),
+        };
+
+        if let Some(target) = new_pos {
+            self.pos = target;
+            Ok(target)
+        } else {
+            Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "invalid seek to a negative or overflowing position",
+            ))
+        }
+    }
+}
@@ -0,0 +1,137 @@
+use std::convert::TryInto;
+
+use crate::*;
+
+/// A single committed write, as recorded in the change feed
+/// that is enabled with `Config::change_feed` and read with
+/// `Db::changes_since`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Change {
+    /// The log sequence number the write was committed at.
+    /// Pass the `lsn` of the last `Change` that was processed
+    /// to `Db::changes_since` to resume reading after it.
+    pub lsn: Lsn,
+    /// The name of the `Tree` that was written to.
+    pub tree: IVec,
+    /// The key that was written.
+    pub key: IVec,
+    /// The new value for the key, or `None` if it was removed.
+    pub value: Option<IVec>,
+}
+
+// The internal tree that committed writes are recorded in,
+// along with the writes whose records may not be in it yet.
+pub(crate) struct ChangeFeed {
+    pub(crate) tree: Tree,
+    // the number of writes in progress that started when each
+    // LSN was the highest one reserved, so that each of them is
+    // committed at a higher LSN
+    in_flight: Mutex<Map<Lsn, usize>>,
+}
+
+impl ChangeFeed {
+    pub(crate) fn new(tree: Tree) -> ChangeFeed {
+        ChangeFeed { tree, in_flight: Mutex::new(Map::default()) }
+    }
+
+    // Registers a write that is about to reserve its LSN, until
+    // the returned `InFlight` is dropped after its record has
+    // been inserted into the feed.
+    pub(crate) fn begin(&self) -> InFlight<'_> {
+        let mut in_flight = self.in_flight.lock();
+        let floor = self.tree.context.pagecache.max_reserved_lsn();
+        *in_flight.entry(floor).or_insert(0) += 1;
+        InFlight { feed: self, floor }
+    }
+
+    // Returns the highest LSN that every write at or below has
+    // its record in the feed for. Records are inserted after the
+    // write that they describe, so they may appear out of order,
+    // and reading past this could skip one that is still missing.
+    pub(crate) fn published_lsn(&self) -> Lsn {
+        // a write that is registered after this load reserves
+        // a higher LSN, and one that is registered before it
+        // is still in `in_flight` until its record is inserted
+        let max_reserved = self.tree.context.pagecache.max_reserved_lsn();
+        let in_flight = self.in_flight.lock();
+        in_flight
+            .keys()
+            .next()
+            .map_or(max_reserved, |floor| max_reserved.min(*floor))
+    }
+}
+
+// A write to a tree with a change feed whose record has not been
+// inserted yet.
+pub(crate) struct InFlight<'a> {
+    feed: &'a ChangeFeed,
+    floor: Lsn,
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        let mut in_flight = self.feed.in_flight.lock();
+        if let Some(count) = in_flight.get_mut(&self.floor) {
+            *count -= 1;
+            if *count == 0 {
+                in_flight.remove(&self.floor);
+            }
+        }
+    }
+}
+
+/// An iterator over the `Change`s recorded in the change feed,
+/// in the order they were committed. Created with
+/// `Db::changes_since`.
+pub struct ChangeIter {
+    pub(crate) inner: Iter,
+}
+
+impl Iterator for ChangeIter {
+    type Item = Result<Change>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|res| res.and_then(decode))
+    }
+
+    fn last(mut self) -> Option<Self::Item> {
+        self.next_back()
+    }
+}
+
+impl DoubleEndedIterator for ChangeIter {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.inner.next_back().map(|res| res.and_then(decode))
+    }
+}
+
+// Change feed records are keyed by the big-endian LSN of the
+// write they describe, so that they sort in commit order.
+pub(crate) fn feed_key(lsn: Lsn) -> [u8; 8] {
+    lsn.to_be_bytes()
+}
+
+pub(crate) fn encode(tree: &IVec, key: &[u8], value: Option<IVec>) -> IVec {
+    let link = match value {
+        Some(new) => Link::Set(key.into(), new),
+        None => Link::Del(key.into()),
+    };
+
+    (tree.clone(), link).serialize().into()
+}
+
+fn decode((k, v): (IVec, IVec)) -> Result<Change> {
+    let lsn_bytes: [u8; 8] =
+        k.as_ref().try_into().map_err(|_| Error::corruption(None))?;
+    let lsn = Lsn::from_be_bytes(lsn_bytes);
+
+    let (tree, link) = <(IVec, Link)>::deserialize(&mut v.as_ref())?;
+
+    let (key, value) = match link {
+        Link::Set(key, value) => (key, Some(value)),
+        Link::Del(key) => (key, None),
+        _ => return Err(Error::corruption(None)),
+    };
+
+    Ok(Change { lsn, tree, key, value })
+}
@@ -162,6 +162,28 @@ impl StorageParameters {
     }
 }
 
+/// The merge operators registered with `Config::merge_operator`,
+/// by name.
+#[derive(Default, Clone)]
+pub(crate) struct MergeOperators(BTreeMap<IVec, Arc<dyn MergeOperator>>);
+
+impl MergeOperators {
+    pub(crate) fn get(&self, name: &[u8]) -> Option<Box<dyn MergeOperator>> {
+        self.0.get(name).map(|registered| -> Box<dyn MergeOperator> {
+            let merge_operator = registered.clone();
+            Box::new(move |key: &[u8], old: Option<&[u8]>, merged: &[u8]| {
+                merge_operator(key, old, merged)
+            })
+        })
+    }
+}
+
+impl Debug for MergeOperators {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.0.keys()).finish()
+    }
+}
+
 /// Top-level configuration for the system.
 ///
 /// # Examples
@@ -209,6 +231,13 @@ pub struct Inner {
     #[doc(hidden)]
     pub snapshot_after_ops: u64,
     #[doc(hidden)]
+    pub change_feed: bool,
+    #[doc(hidden)]
+    pub reap_expired_every_ms: Option<u64>,
+    #[doc(hidden)]
+    pub read_only: bool,
+    pub(crate) merge_operators: MergeOperators,
+    #[doc(hidden)]
     pub version: (usize, usize),
     tmp_path: PathBuf,
     pub(crate) global_error: Arc<Atomic<Error>>,
@@ -229,6 +258,10 @@ impl Default for Inner {
             use_compression: false,
             compression_factor: 5,
             temporary: false,
+            change_feed: false,
+            reap_expired_every_ms: None,
+            read_only: false,
+            merge_operators: MergeOperators::default(),
             version: crate_version(),
 
             // useful in testing
@@ -348,8 +381,10 @@ impl Config {
         let file = config.open_file()?;
 
         let heap_path = config.get_path().join("heap");
-        let heap = Heap::start(&heap_path)?;
-        maybe_fsync_directory(heap_path)?;
+        let heap = Heap::start(&heap_path, config.read_only)?;
+        if !config.read_only {
+            maybe_fsync_directory(heap_path)?;
+        }
 
         // seal config in a Config
         let config = RunningConfig {
@@ -389,6 +424,27 @@ impl Config {
         self
     }
 
+    /// Registers a merge operator under the provided name. A `Tree`
+    /// that is given that name with `Tree::set_named_merge_operator`
+    /// gets this merge operator back each time it is opened.
+    pub fn merge_operator(
+        mut self,
+        name: &str,
+        merge_operator: impl MergeOperator + 'static,
+    ) -> Config {
+        if Arc::strong_count(&self.0) != 1 {
+            error!(
+                "config has already been used to start \
+                 the system and probably should not be \
+                 mutated",
+            );
+        }
+        let m = Arc::make_mut(&mut self.0);
+        let _ =
+            m.merge_operators.0.insert(name.into(), Arc::new(merge_operator));
+        self
+    }
+
     fn gen_temp_path() -> PathBuf {
         use std::time::SystemTime;
 
@@ -463,6 +519,21 @@ impl Config {
             snapshot_after_ops,
             u64,
             "take a fuzzy snapshot of pagecache metadata after this many ops"
+        ),
+        (
+            change_feed,
+            bool,
+            "durably record every committed write so it can be read back with `Db::changes_since`"
+        ),
+        (
+            reap_expired_every_ms,
+            Option<u64>,
+            "how often to remove keys inserted with `Tree::insert_with_ttl` after they expire, in the background while the `Db` is open. Defaults to `None`, which starts no background thread and leaves expired keys in place. Expired keys are never returned by reads either way"
+        ),
+        (
+            read_only,
+            bool,
+            "open an existing database without taking its lock or writing to it, rejecting writes with `Error::Unsupported`"
         )
     );
 
@@ -498,10 +569,33 @@ impl Config {
             self.idgen_persist_interval > 0,
             "idgen_persist_interval must be above 0"
         );
+        if self.read_only {
+            supported!(
+                !self.temporary,
+                "temporary databases cannot be opened in read-only mode"
+            );
+            supported!(
+                !self.create_new,
+                "create_new cannot be used in read-only mode"
+            );
+        }
+        Ok(())
+    }
+
+    // returns an error if the database was opened in read-only mode
+    pub(crate) fn verify_writable(&self) -> Result<()> {
+        supported!(
+            !self.read_only,
+            "cannot write to a database that was opened in read-only mode"
+        );
         Ok(())
     }
 
     fn open_file(&self) -> Result<File> {
+        if self.read_only {
+            return self.open_file_read_only();
+        }
+
         let heap_dir: PathBuf = self.get_path().join("heap");
 
         if !heap_dir.exists() {
@@ -530,6 +624,24 @@ impl Config {
         Ok(file)
     }
 
+    // opens the data file of an existing database without
+    // creating, locking, or writing to anything, so that it
+    // can be inspected while another process is writing to it
+    fn open_file_read_only(&self) -> Result<File> {
+        if self.read_config()?.is_none() {
+            return Err(Error::Unsupported(format!(
+                "cannot open {:?} in read-only mode because it \
+                 does not contain a database",
+                self.get_path()
+            )));
+        }
+
+        self.verify_config()?;
+
+        let file = fs::OpenOptions::new().read(true).open(self.db_path())?;
+        Ok(file)
+    }
+
     fn try_lock(&self, file: File) -> Result<File> {
         #[cfg(all(
             not(miri),
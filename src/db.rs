@@ -1,14 +1,19 @@
-use std::ops::Deref;
+use std::{ops::Deref, path::Path, sync::atomic::AtomicBool};
 
 use crate::*;
 
 const DEFAULT_TREE_ID: &[u8] = b"__sled__default";
+const CHANGE_FEED_TREE_ID: &[u8] = b"__sled__change_feed";
 
 /// The `sled` embedded database! Implements
 /// `Deref<Target = sled::Tree>` to refer to
 /// a default keyspace / namespace / bucket.
 #[derive(Clone)]
 pub struct Db {
+    // declared first so that the reaper thread is joined, and
+    // releases its handles to the trees, before the rest of
+    // the `Db` is dropped
+    _reaper: Option<Arc<ttl::Reaper>>,
     #[doc(hidden)]
     pub context: Context,
     pub(crate) default: Tree,
@@ -62,8 +67,11 @@ impl Db {
             )
         ))]
         {
+            // nothing is ever written in read-only mode
+            let flush_every_ms =
+                if context.read_only { None } else { context.flush_every_ms };
             let flusher_pagecache = context.pagecache.clone();
-            let flusher = context.flush_every_ms.map(move |fem| {
+            let flusher = flush_every_ms.map(move |fem| {
                 flusher::Flusher::new(
                     "log flusher".to_owned(),
                     flusher_pagecache,
@@ -73,31 +81,113 @@ impl Db {
             *context.flusher.lock() = flusher;
         }
 
-        // create or open the default tree
         let guard = pin();
-        let default =
-            meta::open_tree(&context, DEFAULT_TREE_ID.to_vec(), &guard)?;
 
-        let ret = Self {
-            context: context.clone(),
-            default,
-            tenants: Arc::new(RwLock::new(FastMap8::default())),
+        // create or open the tree that records committed writes
+        let change_feed = if context.change_feed {
+            Some(Arc::new(change_feed::ChangeFeed::new(meta::open_tree(
+                &context,
+                CHANGE_FEED_TREE_ID.to_vec(),
+                None,
+                None,
+                None,
+                &guard,
+            )?)))
+        } else {
+            None
         };
 
-        let mut tenants = ret.tenants.write();
+        // open the tree that stores the deadlines of keys that were
+        // inserted with a time-to-live, which is only created once
+        // the first of them is inserted
+        let expiry = Arc::new(meta::LazyTree::open(
+            &context,
+            ttl::EXPIRY_TREE_ID,
+            &guard,
+        )?);
+
+        // open the tree that stores the chunks of values written
+        // with `Tree::open_blob_writer`, which is only created once
+        // the first of them is opened
+        let blobs = Arc::new(meta::LazyTree::open(
+            &context,
+            blob::BLOBS_TREE_ID,
+            &guard,
+        )?);
+
+        // remove the chunks of blobs that were being written when
+        // the process last stopped
+        if !context.read_only {
+            if let Some(blobs_tree) = blobs.tree() {
+                blob::remove_unfinished(&blobs_tree)?;
+            }
+        }
+
+        // create or open the default tree
+        let default = meta::open_tree(
+            &context,
+            DEFAULT_TREE_ID.to_vec(),
+            change_feed.clone(),
+            Some(expiry.clone()),
+            Some(blobs.clone()),
+            &guard,
+        )?;
+
+        let tenants = Arc::new(RwLock::new(FastMap8::default()));
 
         for (id, root) in context.pagecache.get_meta(&guard).tenants() {
+            if id == CHANGE_FEED_TREE_ID
+                || id == ttl::EXPIRY_TREE_ID
+                || id == blob::BLOBS_TREE_ID
+                || index::is_index_tree(&id)
+            {
+                continue;
+            }
+            let has_ttl = ttl::has_deadlines(expiry.tree().as_ref(), &id)?;
+            let has_blobs = blob::has_blobs(blobs.tree().as_ref(), &id)?;
+            // a tree whose merge operator is not registered is still
+            // loaded, and only opening it or merging into it fails
+            let (merge_operator, missing_merge_operator) =
+                meta::merge_operator_for(&context, &id, &guard);
             let tree = Tree(Arc::new(TreeInner {
                 tree_id: id.clone(),
                 subscribers: Subscribers::default(),
                 context: context.clone(),
                 root: AtomicU64::new(root),
-                merge_operator: RwLock::new(None),
+                merge_operator: RwLock::new(merge_operator),
+                missing_merge_operator: RwLock::new(missing_merge_operator),
+                change_feed: change_feed.clone(),
+                expiry: Some(expiry.clone()),
+                has_ttl: AtomicBool::new(has_ttl),
+                blobs: Some(blobs.clone()),
+                has_blobs: AtomicBool::new(has_blobs),
+                indexes: index::Indexes::default(),
+                leases: queue::Leases::default(),
             }));
-            assert!(tenants.insert(id, tree).is_none());
+            assert!(tenants.write().insert(id, tree).is_none());
         }
 
-        drop(tenants);
+        // nothing is ever removed in read-only mode
+        let reap_every_ms = if context.read_only {
+            None
+        } else {
+            context.reap_expired_every_ms
+        };
+        let reaper = reap_every_ms.map(|every_ms| {
+            Arc::new(ttl::Reaper::new(
+                expiry,
+                default.clone(),
+                tenants.clone(),
+                every_ms,
+            ))
+        });
+
+        let ret = Self {
+            _reaper: reaper,
+            context: context.clone(),
+            default,
+            tenants,
+        };
 
         #[cfg(feature = "event_log")]
         {
@@ -114,8 +204,18 @@ impl Db {
     /// accessible from the `Db` via the provided identifier.
     pub fn open_tree<V: AsRef<[u8]>>(&self, name: V) -> Result<Tree> {
         let name_ref = name.as_ref();
+        if name_ref == CHANGE_FEED_TREE_ID
+            || name_ref == ttl::EXPIRY_TREE_ID
+            || name_ref == blob::BLOBS_TREE_ID
+            || index::is_index_tree(name_ref)
+        {
+            return Err(Error::Unsupported(
+                "cannot open the core structures".into(),
+            ));
+        }
         let tenants = self.tenants.read();
         if let Some(tree) = tenants.get(name_ref) {
+            tree.verify_merge_operator()?;
             return Ok(tree.clone());
         }
         drop(tenants);
@@ -127,20 +227,35 @@ impl Db {
         // we need to check this again in case another
         // thread opened it concurrently.
         if let Some(tree) = tenants.get(name_ref) {
+            tree.verify_merge_operator()?;
             return Ok(tree.clone());
         }
 
-        let tree = meta::open_tree(&self.context, name_ref.to_vec(), &guard)?;
+        let tree = meta::open_tree(
+            &self.context,
+            name_ref.to_vec(),
+            self.default.change_feed.clone(),
+            self.default.expiry.clone(),
+            self.default.blobs.clone(),
+            &guard,
+        )?;
 
         assert!(tenants.insert(name_ref.into(), tree.clone()).is_none());
 
+        tree.verify_merge_operator()?;
+
         Ok(tree)
     }
 
     /// Remove a disk-backed collection. This is blocking and fairly slow.
     pub fn drop_tree<V: AsRef<[u8]>>(&self, name: V) -> Result<bool> {
         let name_ref = name.as_ref();
-        if name_ref == DEFAULT_TREE_ID {
+        if name_ref == DEFAULT_TREE_ID
+            || name_ref == CHANGE_FEED_TREE_ID
+            || name_ref == ttl::EXPIRY_TREE_ID
+            || name_ref == blob::BLOBS_TREE_ID
+            || index::is_index_tree(name_ref)
+        {
             return Err(Error::Unsupported(
                 "cannot remove the core structures".into(),
             ));
@@ -155,38 +270,15 @@ impl Db {
             return Ok(false);
         };
 
-        // signal to all threads that this tree is no longer valid
+        // signal to all threads that this tree and its indexes are
+        // no longer valid
         tree.root.store(u64::max_value(), SeqCst);
+        tree.indexes.for_each_tree(|index_tree| {
+            index_tree.root.store(u64::max_value(), SeqCst)
+        });
 
         let guard = pin();
-
-        let mut root_id =
-            Some(self.context.pagecache.meta_pid_for_name(name_ref, &guard)?);
-
-        let mut leftmost_chain: Vec<PageId> = vec![root_id.unwrap()];
-        let mut cursor = root_id.unwrap();
-        while let Some(view) = self.view_for_pid(cursor, &guard)? {
-            if view.is_index {
-                let leftmost_child = view.iter_index_pids().next().unwrap();
-                leftmost_chain.push(leftmost_child);
-                cursor = leftmost_child;
-            } else {
-                break;
-            }
-        }
-
-        loop {
-            let res = self
-                .context
-                .pagecache
-                .cas_root_in_meta(name_ref, root_id, None, &guard)?;
-
-            if let Err(actual_root) = res {
-                root_id = actual_root;
-            } else {
-                break;
-            }
-        }
+        let leftmost_chain = self.unlink_tree(name_ref, &guard)?;
 
         // drop writer lock and asynchronously
         drop(tenants);
@@ -197,54 +289,35 @@ impl Db {
 
         self.gc_pages(leftmost_chain)?;
 
-        Ok(true)
-    }
+        // the indexes of the tree are dropped along with it, even
+        // if they were not created since it was last opened
+        let index_tree_prefix = index::index_tree_prefix(name_ref);
+        let meta = self.context.pagecache.get_meta(&pin()).tenants();
+        for index_tree in
+            meta.keys().filter(|id| id.starts_with(&index_tree_prefix))
+        {
+            let index_guard = pin();
+            let index_chain = self.unlink_tree(index_tree, &index_guard)?;
+            index_guard.flush();
+            drop(index_guard);
+            self.gc_pages(index_chain)?;
+        }
 
-    // Remove all pages for this tree from the underlying
-    // PageCache. This will leave orphans behind if
-    // the tree crashes during gc.
-    fn gc_pages(&self, mut leftmost_chain: Vec<PageId>) -> Result<()> {
-        let mut guard = pin();
-
-        let mut ops = 0;
-        while let Some(mut pid) = leftmost_chain.pop() {
-            loop {
-                ops += 1;
-                if ops % 64 == 0 {
-                    // we re-pin here to avoid memory blow-ups during
-                    // long-running tree removals.
-                    guard = pin();
-                }
-                let cursor_view =
-                    if let Some(view) = self.view_for_pid(pid, &guard)? {
-                        view
-                    } else {
-                        trace!(
-                            "encountered Free node pid {} while GC'ing tree",
-                            pid
-                        );
-                        break;
-                    };
-
-                let ret = self.context.pagecache.free(
-                    pid,
-                    cursor_view.node_view.0,
-                    &guard,
-                )?;
-
-                if ret.is_ok() {
-                    let next_pid = if let Some(next_pid) = cursor_view.next {
-                        next_pid
-                    } else {
-                        break;
-                    };
-                    assert_ne!(pid, next_pid.get());
-                    pid = next_pid.get();
-                }
+        // forget the deadlines of any keys in the dropped tree
+        if let Some(expiry) = self.default.expiry_tree() {
+            ttl::forget_tree(&expiry, name_ref)?;
+        }
+
+        // remove the blobs that keys in the dropped tree held
+        if let Some(blobs) = self.default.blobs_tree() {
+            for owner_res in blobs.scan_prefix(blob::owner_prefix(name_ref)) {
+                let (owner_key, owner) = owner_res?;
+                let (blob_id, _len) = blob::decode_owner(&owner)?;
+                blob::remove_blob(&blobs, &owner_key, blob_id)?;
             }
         }
 
-        Ok(())
+        Ok(true)
     }
 
     /// Returns the trees names saved in this Db.
@@ -253,6 +326,223 @@ impl Db {
         tenants.iter().map(|(name, _)| name.clone()).collect()
     }
 
+    /// Take a consistent, read-only, point-in-time snapshot
+    /// of every `Tree` in the `Db`. Reads through the returned
+    /// `Snapshot` observe all trees exactly as they were at a
+    /// single instant, regardless of any writes that happen
+    /// afterwards.
+    ///
+    /// Taking a snapshot only blocks writers while the root
+    /// of every tree is recorded, and does not copy any data.
+    /// Pages that are rewritten while the snapshot is alive
+    /// keep their previous version in memory until it is
+    /// dropped.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// let orders = db.open_tree(b"orders")?;
+    /// let totals = db.open_tree(b"totals")?;
+    /// orders.insert(b"order_1", b"widget")?;
+    /// totals.insert(b"widget", vec![1])?;
+    ///
+    /// let snapshot = db.snapshot()?;
+    ///
+    /// orders.insert(b"order_2", b"widget")?;
+    /// totals.insert(b"widget", vec![2])?;
+    ///
+    /// let orders = snapshot.open_tree(b"orders")?;
+    /// let totals = snapshot.open_tree(b"totals")?;
+    /// assert_eq!(orders.len(), 1);
+    /// assert_eq!(totals.get(b"widget")?, Some(sled::IVec::from(vec![1])));
+    /// # Ok(()) }
+    /// ```
+    pub fn snapshot(&self) -> Result<Snapshot> {
+        let trees: Vec<Tree> = self.tenants.read().values().cloned().collect();
+        Snapshot::capture(&self.default, &trees)
+    }
+
+    /// Returns every write committed after the provided LSN, in
+    /// commit order, as recorded by the change feed. Requires
+    /// the `Db` to be opened with `Config::change_feed(true)`,
+    /// otherwise `Error::Unsupported` is returned.
+    ///
+    /// The change feed is stored durably alongside the data it
+    /// describes, and each write is recovered atomically with
+    /// its record in the feed. A consumer can save the `lsn` of
+    /// the last `Change` it processed and pass it back in after
+    /// a restart to resume exactly where it stopped. Pass `0`
+    /// to read the feed from the beginning.
+    ///
+    /// A write's record is added to the feed just after the write
+    /// itself, so concurrent writes may be recorded out of order.
+    /// The returned iterator stops before the lowest LSN that may
+    /// still be missing its record, so a change is never returned
+    /// after one with a higher LSN, and resuming from a saved
+    /// cursor never skips one. Changes that are committed after
+    /// this is called may not be returned until it is called
+    /// again. Records are kept until they are removed with
+    /// `Db::truncate_changes`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let config = sled::Config::new().temporary(true).change_feed(true);
+    /// let db = config.open()?;
+    /// let tree = db.open_tree(b"users")?;
+    ///
+    /// tree.insert(b"alice", b"admin")?;
+    /// tree.remove(b"alice")?;
+    ///
+    /// let changes = db.changes_since(0)?.collect::<sled::Result<Vec<_>>>()?;
+    /// assert_eq!(changes.len(), 2);
+    /// assert_eq!(changes[0].tree, b"users");
+    /// assert_eq!(changes[0].value, Some(sled::IVec::from(b"admin")));
+    /// assert_eq!(changes[1].value, None);
+    ///
+    /// // resume after the last change that was processed
+    /// let cursor = changes[1].lsn;
+    /// tree.insert(b"bob", b"guest")?;
+    ///
+    /// let changes = db.changes_since(cursor)?.collect::<sled::Result<Vec<_>>>()?;
+    /// assert_eq!(changes.len(), 1);
+    /// assert_eq!(changes[0].key, b"bob");
+    /// # Ok(()) }
+    /// ```
+    pub fn changes_since(&self, lsn: Lsn) -> Result<ChangeIter> {
+        let change_feed = self.change_feed()?;
+        let start = change_feed::feed_key(lsn.saturating_add(1).max(0));
+        let end = change_feed::feed_key(change_feed.published_lsn().max(0));
+
+        Ok(ChangeIter { inner: change_feed.tree.range(&start[..]..=&end[..]) })
+    }
+
+    /// Discards the records of every change committed at or
+    /// before the provided LSN from the change feed, after
+    /// they have been processed by all consumers. Returns
+    /// `Error::Unsupported` if the change feed is not enabled.
+    pub fn truncate_changes(&self, lsn: Lsn) -> Result<()> {
+        let change_feed = self.change_feed()?;
+        if lsn < 0 {
+            return Ok(());
+        }
+        let end = change_feed::feed_key(lsn);
+
+        change_feed.tree.remove_range(..=&end[..])
+    }
+
+    fn change_feed(&self) -> Result<&change_feed::ChangeFeed> {
+        self.default.change_feed.as_deref().ok_or_else(|| {
+            Error::Unsupported(
+                "the change feed must be enabled with \
+                 Config::change_feed before it can be read"
+                    .to_owned(),
+            )
+        })
+    }
+
+    /// Copies the database into the provided directory, which
+    /// must be empty or not exist yet, while it remains open
+    /// and in use. The copy contains every write that completed
+    /// before this call, and can be opened like any other
+    /// database by pointing `Config::path` at it.
+    ///
+    /// Writes may continue while the backup is running, but
+    /// log segments and heap slots are not reused until it
+    /// completes, so the database may temporarily grow.
+    ///
+    /// Every copied file is checksummed, and the backup is
+    /// re-read and verified against the returned
+    /// `BackupManifest` before this returns. The manifest is
+    /// also stored in the backup directory, and can be loaded
+    /// again with `BackupManifest::read`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// # let backup_path = std::env::temp_dir().join("sled_backup_to_doctest");
+    /// # let _ = std::fs::remove_dir_all(&backup_path);
+    /// db.insert(b"key", b"value")?;
+    ///
+    /// let manifest = db.backup_to(&backup_path)?;
+    /// db.insert(b"key", b"new value")?;
+    ///
+    /// let backup = sled::Config::new().path(&backup_path).open()?;
+    /// assert_eq!(backup.get(b"key")?, Some(sled::IVec::from(b"value")));
+    /// assert_eq!(sled::BackupManifest::read(&backup_path)?, manifest);
+    /// # drop(backup);
+    /// # std::fs::remove_dir_all(&backup_path)?;
+    /// # Ok(()) }
+    /// ```
+    pub fn backup_to<P: AsRef<Path>>(&self, path: P) -> Result<BackupManifest> {
+        self.backup_to_with_progress(path, |_| {})
+    }
+
+    /// Like `Db::backup_to`, but calls the provided function with
+    /// the number of bytes copied so far after every chunk of data
+    /// that is copied into the backup.
+    pub fn backup_to_with_progress<P, F>(
+        &self,
+        path: P,
+        progress: F,
+    ) -> Result<BackupManifest>
+    where
+        P: AsRef<Path>,
+        F: FnMut(BackupProgress),
+    {
+        backup::backup(&self.context, None, path.as_ref(), progress)
+    }
+
+    /// Backs up only the log segments and heap slots that were
+    /// written since the provided backup was taken, into the
+    /// provided directory, which must be empty or not exist yet.
+    /// The previous backup may be a full backup created by
+    /// `Db::backup_to`, or another incremental backup of this
+    /// database.
+    ///
+    /// The returned `BackupManifest` records the `stable_lsn` of
+    /// the previous backup, and the byte ranges of every file
+    /// that were copied. Use `restore_backup` with the full
+    /// backup followed by each incremental backup, in the order
+    /// they were taken, to reassemble them into a database that
+    /// can be opened.
+    ///
+    /// # Errors
+    ///
+    /// Returns `Error::Unsupported` if the previous backup
+    /// contains writes that this database has not made stable,
+    /// which means that it was taken of a different database.
+    pub fn backup_incremental<P: AsRef<Path>>(
+        &self,
+        previous: &BackupManifest,
+        path: P,
+    ) -> Result<BackupManifest> {
+        self.backup_incremental_with_progress(previous, path, |_| {})
+    }
+
+    /// Like `Db::backup_incremental`, but calls the provided
+    /// function with the number of bytes copied so far after every
+    /// chunk of data that is copied into the backup.
+    pub fn backup_incremental_with_progress<P, F>(
+        &self,
+        previous: &BackupManifest,
+        path: P,
+        progress: F,
+    ) -> Result<BackupManifest>
+    where
+        P: AsRef<Path>,
+        F: FnMut(BackupProgress),
+    {
+        backup::backup(&self.context, Some(previous), path.as_ref(), progress)
+    }
+
     /// Returns `true` if the database was
     /// recovered from a previous process.
     /// Note that database state is only
@@ -310,6 +600,8 @@ impl Db {
     /// ```
     /// # use sled as old_sled;
     /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let _ = std::fs::remove_dir_all("my_old__db");
+    /// # let _ = std::fs::remove_dir_all("my_new__db");
     /// let old = old_sled::open("my_old__db")?;
     ///
     /// // may be a different version of sled,
@@ -322,8 +614,8 @@ impl Db {
     /// assert_eq!(old.checksum()?, new.checksum()?);
     /// # drop(old);
     /// # drop(new);
-    /// # std::fs::remove_file("my_old__db");
-    /// # std::fs::remove_file("my_new__db");
+    /// # std::fs::remove_dir_all("my_old__db")?;
+    /// # std::fs::remove_dir_all("my_new__db")?;
     /// # Ok(()) }
     /// ```
     pub fn export(
@@ -376,6 +668,8 @@ impl Db {
     /// ```
     /// # use sled as old_sled;
     /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let _ = std::fs::remove_dir_all("my_old_db");
+    /// # let _ = std::fs::remove_dir_all("my_new_db");
     /// let old = old_sled::open("my_old_db")?;
     ///
     /// // may be a different version of sled,
@@ -388,8 +682,8 @@ impl Db {
     /// assert_eq!(old.checksum()?, new.checksum()?);
     /// # drop(old);
     /// # drop(new);
-    /// # std::fs::remove_file("my_old_db");
-    /// # std::fs::remove_file("my_new_db");
+    /// # std::fs::remove_dir_all("my_old_db")?;
+    /// # std::fs::remove_dir_all("my_new_db")?;
     /// # Ok(()) }
     /// ```
     pub fn import(
@@ -8,7 +8,39 @@
 //!
 //! For convenience, this module contains example encoders for integers and strings.
 //!
-//! TODO: add an example or testcase for serde etc.
+//! sled sorts keys by comparing their bytes, so only encodings that preserve
+//! the order of your values can be used for range scans over them. The integer,
+//! float, bool and byte string encodings in this module implement
+//! [`OrderedEncoding`], and tuples of them can be used as composite keys:
+//!
+//! ```
+//! # fn main() -> Result<(), Box<dyn std::error::Error>> {
+//! use sled::{BytesEncoding, IntegerEncoding};
+//!
+//! type Tenant = IntegerEncoding<u32>;
+//! type Timestamp = IntegerEncoding<i64>;
+//!
+//! let db = sled::Config::default().temporary(true).open()?;
+//! let events = db
+//!     .open_tree("events")?
+//!     .with_encodings::<(Tenant, Timestamp, BytesEncoding), BytesEncoding>();
+//!
+//! events.insert((1, -20, &b"b"[..]), b"before")?;
+//! events.insert((1, 10, &b"a"[..]), b"after")?;
+//! events.insert((2, 0, &b"c"[..]), b"other tenant")?;
+//!
+//! // all events of tenant 1, ordered by their timestamp
+//! let start = (1, i64::min_value(), &b""[..]);
+//! let end = (2, i64::min_value(), &b""[..]);
+//! let mut iter = events.range(start..end);
+//! assert_eq!(iter.next().unwrap()?.0.decode()?, (1, -20, b"b".to_vec()));
+//! assert_eq!(iter.next().unwrap()?.0.decode()?, (1, 10, b"a".to_vec()));
+//! assert!(iter.next().is_none());
+//! # Ok(()) }
+//! ```
+//!
+//! With the `serde` feature, [`SerdeEncoding`] encodes any type that
+//! implements serde's `Serialize` and `DeserializeOwned` traits.
 
 #![allow(missing_copy_implementations)]
 
@@ -58,24 +90,559 @@ impl<'a> Decoder<'a> for StringEncoding {
     }
 }
 
+/// The error returned when bytes can't be decoded by an [`OrderedEncoding`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DecodeError {
+    /// There are fewer bytes than the encoding needs.
+    UnexpectedEnd,
+    /// There are bytes left over after the encoded value.
+    TrailingBytes,
+    /// A byte that the encoding never produces was found.
+    InvalidByte(u8),
+}
+
+impl std::fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            DecodeError::UnexpectedEnd => {
+                write!(f, "unexpected end of encoded bytes")
+            }
+            DecodeError::TrailingBytes => {
+                write!(f, "trailing bytes after encoded value")
+            }
+            DecodeError::InvalidByte(byte) => {
+                write!(f, "invalid encoded byte {:#04x}", byte)
+            }
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// An encoding that preserves the order of the values it encodes, and whose
+/// encoded values are never a prefix of each other. Values encoded this way can
+/// be concatenated without changing their order, which is how tuples of these
+/// encodings are encoded.
+pub trait OrderedEncoding<'a>:
+    Encoder<'a> + Decoder<'a, Error = DecodeError>
+{
+    /// Decode a value from the start of `bytes`, returning it along with the
+    /// bytes that follow it.
+    fn decode_prefix(
+        bytes: &'a [u8],
+    ) -> Result<(Self::Out, &'a [u8]), DecodeError>;
+}
+
+fn decode_all<'a, E: OrderedEncoding<'a>>(
+    bytes: &'a [u8],
+) -> Result<E::Out, DecodeError> {
+    let (out, rest) = E::decode_prefix(bytes)?;
+    if rest.is_empty() { Ok(out) } else { Err(DecodeError::TrailingBytes) }
+}
+
+fn split_array(
+    bytes: &[u8],
+    len: usize,
+) -> Result<(&[u8], &[u8]), DecodeError> {
+    if bytes.len() < len {
+        Err(DecodeError::UnexpectedEnd)
+    } else {
+        Ok(bytes.split_at(len))
+    }
+}
+
 /// Encode an integer T in big endian. This ensures the expected sort order when uses for keys.
+/// Signed integers have their sign bit flipped, so that negative numbers
+/// sort before positive ones.
 pub struct IntegerEncoding<T>(std::marker::PhantomData<T>);
-// TODO: .to_be_bytes() isn't part of any trait. We need to write a macro for this.
-impl<'a> Encoder<'a> for IntegerEncoding<u128> {
-    type In = u128;
-    type Encoded = [u8; std::mem::size_of::<Self::In>()];
+
+// The minimum value of a signed integer has only its sign bit set,
+// and that of an unsigned integer is zero, so that flipping these
+// bits only flips the sign bit of signed integers.
+macro_rules! sign_bit {
+    ($t:ty, $u:ty) => {
+        <$u>::from_be_bytes(<$t>::min_value().to_be_bytes())
+    };
+}
+
+macro_rules! impl_integer_encoding {
+    ($($t:ty => $u:ty),*) => {
+        $(
+            impl<'a> Encoder<'a> for IntegerEncoding<$t> {
+                type In = $t;
+                type Encoded = [u8; std::mem::size_of::<$t>()];
+
+                fn encode(data: Self::In) -> Self::Encoded {
+                    let bits = <$u>::from_be_bytes(data.to_be_bytes());
+                    (bits ^ sign_bit!($t, $u)).to_be_bytes()
+                }
+            }
+
+            impl<'a> Decoder<'a> for IntegerEncoding<$t> {
+                type Out = $t;
+                type Error = DecodeError;
+
+                fn decode(bytes: &'a [u8]) -> Result<Self::Out, Self::Error> {
+                    decode_all::<Self>(bytes)
+                }
+            }
+
+            impl<'a> OrderedEncoding<'a> for IntegerEncoding<$t> {
+                fn decode_prefix(
+                    bytes: &'a [u8],
+                ) -> Result<(Self::Out, &'a [u8]), DecodeError> {
+                    use std::convert::TryInto;
+                    let (encoded, rest) =
+                        split_array(bytes, std::mem::size_of::<$t>())?;
+                    let flipped =
+                        <$u>::from_be_bytes(encoded.try_into().unwrap());
+                    let bits = flipped ^ sign_bit!($t, $u);
+                    Ok((<$t>::from_be_bytes(bits.to_be_bytes()), rest))
+                }
+            }
+        )*
+    };
+}
+
+impl_integer_encoding!(
+    u8 => u8, u16 => u16, u32 => u32, u64 => u64, u128 => u128,
+    i8 => u8, i16 => u16, i32 => u32, i64 => u64, i128 => u128
+);
+
+/// Encode an IEEE 754 float T so that it sorts in numeric order. Negative
+/// floats have all their bits flipped, and the sign bit of positive ones is
+/// set. NaNs with the sign bit cleared sort after positive infinity, those
+/// with it set sort before negative infinity, and `-0.0` sorts before `0.0`.
+pub struct FloatEncoding<T>(std::marker::PhantomData<T>);
+
+macro_rules! impl_float_encoding {
+    ($($t:ty => $u:ty),*) => {
+        $(
+            impl<'a> Encoder<'a> for FloatEncoding<$t> {
+                type In = $t;
+                type Encoded = [u8; std::mem::size_of::<$t>()];
+
+                fn encode(data: Self::In) -> Self::Encoded {
+                    let sign_bit = !(<$u>::max_value() >> 1);
+                    let bits = data.to_bits();
+                    let flipped = if bits & sign_bit == 0 {
+                        bits | sign_bit
+                    } else {
+                        !bits
+                    };
+                    flipped.to_be_bytes()
+                }
+            }
+
+            impl<'a> Decoder<'a> for FloatEncoding<$t> {
+                type Out = $t;
+                type Error = DecodeError;
+
+                fn decode(bytes: &'a [u8]) -> Result<Self::Out, Self::Error> {
+                    decode_all::<Self>(bytes)
+                }
+            }
+
+            impl<'a> OrderedEncoding<'a> for FloatEncoding<$t> {
+                fn decode_prefix(
+                    bytes: &'a [u8],
+                ) -> Result<(Self::Out, &'a [u8]), DecodeError> {
+                    use std::convert::TryInto;
+                    let (encoded, rest) =
+                        split_array(bytes, std::mem::size_of::<$t>())?;
+                    let sign_bit = !(<$u>::max_value() >> 1);
+                    let flipped =
+                        <$u>::from_be_bytes(encoded.try_into().unwrap());
+                    let bits = if flipped & sign_bit == 0 {
+                        !flipped
+                    } else {
+                        flipped ^ sign_bit
+                    };
+                    Ok((<$t>::from_bits(bits), rest))
+                }
+            }
+        )*
+    };
+}
+
+impl_float_encoding!(f32 => u32, f64 => u64);
+
+/// Encode a bool as a single byte, with `false` sorting before `true`.
+pub struct BoolEncoding();
+impl<'a> Encoder<'a> for BoolEncoding {
+    type In = bool;
+    type Encoded = [u8; 1];
+
+    fn encode(data: Self::In) -> Self::Encoded {
+        [u8::from(data)]
+    }
+}
+impl<'a> Decoder<'a> for BoolEncoding {
+    type Out = bool;
+    type Error = DecodeError;
+
+    fn decode(bytes: &'a [u8]) -> Result<Self::Out, Self::Error> {
+        decode_all::<Self>(bytes)
+    }
+}
+impl<'a> OrderedEncoding<'a> for BoolEncoding {
+    fn decode_prefix(
+        bytes: &'a [u8],
+    ) -> Result<(Self::Out, &'a [u8]), DecodeError> {
+        match bytes.split_first() {
+            Some((0, rest)) => Ok((false, rest)),
+            Some((1, rest)) => Ok((true, rest)),
+            Some((byte, _)) => Err(DecodeError::InvalidByte(*byte)),
+            None => Err(DecodeError::UnexpectedEnd),
+        }
+    }
+}
+
+// Byte strings are terminated by `0x00 0x01`, and each `0x00` in them
+// is escaped as `0x00 0xff`. The terminator sorts before any escaped
+// byte, so a byte string sorts before the byte strings that it is a
+// prefix of, regardless of the bytes that follow it.
+const BYTES_ESCAPE: u8 = 0x00;
+const BYTES_ESCAPED_NULL: u8 = 0xff;
+const BYTES_TERMINATOR: u8 = 0x01;
+
+/// Encode a byte string so that it sorts like the bytes themselves, even when
+/// followed by other values in a tuple. Each `0x00` byte is escaped and the
+/// string is terminated, so the encoding is slightly longer than the bytes.
+pub struct BytesEncoding();
+impl<'a> Encoder<'a> for BytesEncoding {
+    type In = &'a [u8];
+    type Encoded = Vec<u8>;
 
     fn encode(data: Self::In) -> Self::Encoded {
-        data.to_be_bytes()
+        let mut ret = Vec::with_capacity(data.len() + 2);
+        for byte in data {
+            ret.push(*byte);
+            if *byte == BYTES_ESCAPE {
+                ret.push(BYTES_ESCAPED_NULL);
+            }
+        }
+        ret.push(BYTES_ESCAPE);
+        ret.push(BYTES_TERMINATOR);
+        ret
     }
 }
-impl<'a> Decoder<'a> for IntegerEncoding<u128> {
-    type Out = u128;
-    type Error = std::array::TryFromSliceError;
+impl<'a> Decoder<'a> for BytesEncoding {
+    type Out = Vec<u8>;
+    type Error = DecodeError;
 
     fn decode(bytes: &'a [u8]) -> Result<Self::Out, Self::Error> {
-        use std::convert::TryInto;
-        let array = bytes.try_into()?;
-        Ok(u128::from_be_bytes(array))
+        decode_all::<Self>(bytes)
+    }
+}
+impl<'a> OrderedEncoding<'a> for BytesEncoding {
+    fn decode_prefix(
+        bytes: &'a [u8],
+    ) -> Result<(Self::Out, &'a [u8]), DecodeError> {
+        let mut ret = Vec::with_capacity(bytes.len());
+        let mut rest = bytes;
+        while let Some((byte, tail)) = rest.split_first() {
+            if *byte == BYTES_ESCAPE {
+                match tail.split_first() {
+                    Some((&BYTES_ESCAPED_NULL, after)) => {
+                        ret.push(BYTES_ESCAPE);
+                        rest = after;
+                    }
+                    Some((&BYTES_TERMINATOR, after)) => {
+                        return Ok((ret, after));
+                    }
+                    Some((other, _)) => {
+                        return Err(DecodeError::InvalidByte(*other));
+                    }
+                    None => break,
+                }
+            } else {
+                ret.push(*byte);
+                rest = tail;
+            }
+        }
+        Err(DecodeError::UnexpectedEnd)
+    }
+}
+
+// Tuples are encoded by concatenating the encodings of their elements,
+// which preserves their lexicographic order because no encoded element
+// is a prefix of another encoded value of the same encoding.
+macro_rules! impl_tuple_encoding {
+    ($($e:ident $v:ident),+) => {
+        impl<'a, $($e: OrderedEncoding<'a>),+> Encoder<'a> for ($($e,)+) {
+            type In = ($($e::In,)+);
+            type Encoded = Vec<u8>;
+
+            fn encode(data: Self::In) -> Self::Encoded {
+                let ($($v,)+) = data;
+                let mut ret = vec![];
+                $(ret.extend_from_slice($e::encode($v).as_ref());)+
+                ret
+            }
+        }
+
+        impl<'a, $($e: OrderedEncoding<'a>),+> Decoder<'a> for ($($e,)+) {
+            type Out = ($($e::Out,)+);
+            type Error = DecodeError;
+
+            fn decode(bytes: &'a [u8]) -> Result<Self::Out, Self::Error> {
+                decode_all::<Self>(bytes)
+            }
+        }
+
+        impl<'a, $($e: OrderedEncoding<'a>),+> OrderedEncoding<'a>
+            for ($($e,)+)
+        {
+            fn decode_prefix(
+                bytes: &'a [u8],
+            ) -> Result<(Self::Out, &'a [u8]), DecodeError> {
+                let mut rest = bytes;
+                $(
+                    let $v = {
+                        let (value, tail) = $e::decode_prefix(rest)?;
+                        rest = tail;
+                        value
+                    };
+                )+
+                Ok((($($v,)+), rest))
+            }
+        }
+    };
+}
+
+impl_tuple_encoding!(A a);
+impl_tuple_encoding!(A a, B b);
+impl_tuple_encoding!(A a, B b, C c);
+impl_tuple_encoding!(A a, B b, C c, D d);
+impl_tuple_encoding!(A a, B b, C c, D d, E e);
+impl_tuple_encoding!(A a, B b, C c, D d, E e, F f);
+impl_tuple_encoding!(A a, B b, C c, D d, E e, F f, G g);
+impl_tuple_encoding!(A a, B b, C c, D d, E e, F f, G g, H h);
+
+/// A serialization format that [`SerdeEncoding`] can use. [`Bincode`] is the
+/// default, and other formats can be used by implementing this trait for a new
+/// type.
+#[cfg(feature = "serde")]
+pub trait SerdeFormat {
+    /// The error returned when deserializing fails.
+    type Error: std::error::Error;
+
+    /// Serialize a value. Like [`Encoder::encode`], this cannot fail, so
+    /// formats should panic if the value can't be serialized.
+    fn serialize<T: serde_crate::Serialize>(value: &T) -> Vec<u8>;
+
+    /// Deserialize a value.
+    fn deserialize<T: serde_crate::de::DeserializeOwned>(
+        bytes: &[u8],
+    ) -> Result<T, Self::Error>;
+}
+
+/// Serializes values with bincode.
+#[cfg(feature = "serde")]
+pub struct Bincode();
+
+#[cfg(feature = "serde")]
+impl SerdeFormat for Bincode {
+    type Error = bincode::Error;
+
+    /// Panics if the value contains a sequence whose length isn't known
+    /// before it is serialized.
+    fn serialize<T: serde_crate::Serialize>(value: &T) -> Vec<u8> {
+        bincode::serialize(value).expect("value can't be serialized by bincode")
+    }
+
+    fn deserialize<T: serde_crate::de::DeserializeOwned>(
+        bytes: &[u8],
+    ) -> Result<T, Self::Error> {
+        bincode::deserialize(bytes)
+    }
+}
+
+/// Encode any type that implements serde's `Serialize` and `DeserializeOwned`
+/// with a [`SerdeFormat`], which is [`Bincode`] by default. Values are inserted
+/// by reference and decoded into owned values.
+///
+/// Most formats don't preserve the order of the values they serialize, so this
+/// encoding is meant for values rather than for keys that are iterated over in
+/// order.
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// use std::collections::BTreeMap;
+///
+/// use sled::{SerdeEncoding, StringEncoding};
+///
+/// type Scores = SerdeEncoding<BTreeMap<String, u64>>;
+///
+/// let db = sled::Config::default().temporary(true).open()?;
+/// let scores =
+///     db.open_tree("scores")?.with_encodings::<StringEncoding, Scores>();
+///
+/// let mut alice = BTreeMap::new();
+/// alice.insert("chess".to_string(), 1200);
+/// scores.insert("alice", &alice)?;
+///
+/// assert_eq!(scores.get("alice")?.unwrap().decode()?, alice);
+/// # Ok(()) }
+/// ```
+#[cfg(feature = "serde")]
+pub struct SerdeEncoding<T, F = Bincode>(std::marker::PhantomData<(T, F)>);
+
+#[cfg(feature = "serde")]
+impl<'a, T, F> Encoder<'a> for SerdeEncoding<T, F>
+where
+    T: serde_crate::Serialize + 'a,
+    F: SerdeFormat,
+{
+    type In = &'a T;
+    type Encoded = Vec<u8>;
+
+    fn encode(data: Self::In) -> Self::Encoded {
+        F::serialize(data)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'a, T, F> Decoder<'a> for SerdeEncoding<T, F>
+where
+    T: serde_crate::de::DeserializeOwned,
+    F: SerdeFormat,
+{
+    type Out = T;
+    type Error = F::Error;
+
+    fn decode(bytes: &'a [u8]) -> Result<Self::Out, Self::Error> {
+        F::deserialize(bytes)
+    }
+}
+
+#[cfg(test)]
+mod qc {
+    use super::*;
+
+    fn prop_ordered<E, T>(a: T, b: T) -> bool
+    where
+        for<'a> E: OrderedEncoding<'a, In = T, Out = T>,
+        T: Ord + Copy + std::fmt::Debug,
+    {
+        let encoded_a = E::encode(a);
+        let encoded_b = E::encode(b);
+        a.cmp(&b) == encoded_a.as_ref().cmp(encoded_b.as_ref())
+            && E::decode(encoded_a.as_ref()) == Ok(a)
+    }
+
+    fn prop_float_ordered<E, T>(a: T, b: T, to_bits: fn(T) -> u64) -> bool
+    where
+        for<'a> E: OrderedEncoding<'a, In = T, Out = T>,
+        T: PartialOrd + Copy,
+    {
+        let encoded_a = E::encode(a);
+        let encoded_b = E::encode(b);
+        let ordered = match a.partial_cmp(&b) {
+            // NaNs and zeros of different signs are ordered by their bits
+            None | Some(std::cmp::Ordering::Equal) => true,
+            Some(ordering) => {
+                ordering == encoded_a.as_ref().cmp(encoded_b.as_ref())
+            }
+        };
+        ordered && E::decode(encoded_a.as_ref()).map(to_bits) == Ok(to_bits(a))
+    }
+
+    type Composite = (IntegerEncoding<i32>, BytesEncoding, BoolEncoding);
+
+    fn prop_composite_ordered(
+        a: (i32, Vec<u8>, bool),
+        b: &(i32, Vec<u8>, bool),
+    ) -> bool {
+        let encoded_a = Composite::encode((a.0, &a.1, a.2));
+        let encoded_b = Composite::encode((b.0, &b.1, b.2));
+        a.cmp(b) == encoded_a.cmp(&encoded_b)
+            && Composite::decode(&encoded_a) == Ok(a)
+    }
+
+    #[test]
+    fn escaped_bytes_order() {
+        let mut items: Vec<&[u8]> =
+            vec![b"ab\x01", b"ab\x00\x00", b"ab", b"", b"ab\x00", b"\x00"];
+        items.sort();
+        for pair in items.windows(2) {
+            assert!(prop_composite_ordered(
+                (0, pair[0].to_vec(), true),
+                &(0, pair[1].to_vec(), false)
+            ));
+        }
+    }
+
+    #[test]
+    fn decode_errors() {
+        assert_eq!(
+            BytesEncoding::decode(b"ab\x00"),
+            Err(DecodeError::UnexpectedEnd)
+        );
+        assert_eq!(
+            BytesEncoding::decode(b"ab\x00\x02"),
+            Err(DecodeError::InvalidByte(2))
+        );
+        assert_eq!(
+            IntegerEncoding::<u32>::decode(&[0; 5]),
+            Err(DecodeError::TrailingBytes)
+        );
+        assert_eq!(
+            <(IntegerEncoding<u32>, BoolEncoding)>::decode(&[0; 4]),
+            Err(DecodeError::UnexpectedEnd)
+        );
+        assert_eq!(
+            BoolEncoding::decode(&[2]),
+            Err(DecodeError::InvalidByte(2))
+        );
+    }
+
+    quickcheck::quickcheck! {
+        #[cfg_attr(miri, ignore)]
+        fn u64_ordered(a: u64, b: u64) -> bool {
+            prop_ordered::<IntegerEncoding<u64>, _>(a, b)
+        }
+
+        #[cfg_attr(miri, ignore)]
+        fn i8_ordered(a: i8, b: i8) -> bool {
+            prop_ordered::<IntegerEncoding<i8>, _>(a, b)
+        }
+
+        #[cfg_attr(miri, ignore)]
+        fn i64_ordered(a: i64, b: i64) -> bool {
+            prop_ordered::<IntegerEncoding<i64>, _>(a, b)
+        }
+
+        #[cfg_attr(miri, ignore)]
+        fn i128_ordered(a: i128, b: i128) -> bool {
+            prop_ordered::<IntegerEncoding<i128>, _>(a, b)
+        }
+
+        #[cfg_attr(miri, ignore)]
+        fn bool_ordered(a: bool, b: bool) -> bool {
+            prop_ordered::<BoolEncoding, _>(a, b)
+        }
+
+        #[cfg_attr(miri, ignore)]
+        fn f32_ordered(a: f32, b: f32) -> bool {
+            let to_bits = |f: f32| u64::from(f.to_bits());
+            prop_float_ordered::<FloatEncoding<f32>, _>(a, b, to_bits)
+        }
+
+        #[cfg_attr(miri, ignore)]
+        fn f64_ordered(a: f64, b: f64) -> bool {
+            prop_float_ordered::<FloatEncoding<f64>, _>(a, b, f64::to_bits)
+        }
+
+        #[cfg_attr(miri, ignore)]
+        fn composite_ordered(
+            a: (i32, Vec<u8>, bool),
+            b: (i32, Vec<u8>, bool)
+        ) -> bool {
+            prop_composite_ordered(a, &b)
+        }
     }
 }
@@ -0,0 +1,578 @@
+use std::ops::{Bound, RangeBounds};
+
+use parking_lot::MutexGuard;
+
+use crate::*;
+
+// The entries of each index are stored in a hidden `Tree` whose
+// name starts with this prefix, followed by the length of the
+// name of the indexed `Tree`, that name and the name of the
+// index, so that the indexes of each `Tree` share a prefix.
+const INDEX_TREE_PREFIX: &[u8] = b"__sled__index";
+
+// Indexes are built in a hidden `Tree` whose name starts with this
+// prefix instead, followed by the same suffix, which replaces the
+// one of the index only once every entry has been written.
+const REBUILD_TREE_PREFIX: &[u8] = b"__sled__rebuild";
+
+pub(crate) fn index_tree_prefix(tree_id: &[u8]) -> Vec<u8> {
+    let mut ret =
+        Vec::with_capacity(INDEX_TREE_PREFIX.len() + 8 + tree_id.len());
+    ret.extend_from_slice(INDEX_TREE_PREFIX);
+    ret.extend_from_slice(&(tree_id.len() as u64).to_be_bytes());
+    ret.extend_from_slice(tree_id);
+    ret
+}
+
+fn index_tree_name(tree_id: &[u8], name: &[u8]) -> Vec<u8> {
+    let mut ret = index_tree_prefix(tree_id);
+    ret.extend_from_slice(name);
+    ret
+}
+
+fn rebuild_tree_name(tree_id: &[u8], name: &[u8]) -> Vec<u8> {
+    let mut ret = REBUILD_TREE_PREFIX.to_vec();
+    ret.extend_from_slice(
+        &index_tree_name(tree_id, name)[INDEX_TREE_PREFIX.len()..],
+    );
+    ret
+}
+
+pub(crate) fn is_index_tree(name: &[u8]) -> bool {
+    name.starts_with(INDEX_TREE_PREFIX) || name.starts_with(REBUILD_TREE_PREFIX)
+}
+
+// Index entries are keyed by the index key, followed by the key
+// of the indexed `Tree`. Zero bytes in the index key are escaped
+// as `[0, 255]` and it is terminated by `[0, 1]`, so that the
+// entries for one index key are adjacent and sort in the same
+// order as the index keys themselves. The value of an entry is
+// the key of the indexed `Tree`.
+fn entry_bound(index_key: &[u8], terminator: u8) -> Vec<u8> {
+    let mut ret = Vec::with_capacity(index_key.len() + 2);
+    for byte in index_key {
+        ret.push(*byte);
+        if *byte == 0 {
+            ret.push(255);
+        }
+    }
+    ret.extend_from_slice(&[0, terminator]);
+    ret
+}
+
+fn entry_prefix(index_key: &[u8]) -> Vec<u8> {
+    entry_bound(index_key, 1)
+}
+
+fn entry_key(index_key: &[u8], key: &[u8]) -> Vec<u8> {
+    let mut ret = entry_prefix(index_key);
+    ret.extend_from_slice(key);
+    ret
+}
+
+fn unique_violation(index: &IndexInner, index_key: &[u8]) -> Error {
+    Error::UniqueViolation {
+        index: index.name.clone(),
+        key: IVec::from(index_key),
+    }
+}
+
+/// Extracts the key that a key and value of a `Tree` are stored
+/// under in an `Index`, or returns `None` to leave them out of it.
+pub trait IndexExtractor:
+    Send + Sync + Fn(&[u8], &[u8]) -> Option<Vec<u8>>
+{
+}
+impl<F> IndexExtractor for F where
+    F: Send + Sync + Fn(&[u8], &[u8]) -> Option<Vec<u8>>
+{
+}
+
+pub(crate) struct IndexInner {
+    name: IVec,
+    tree: Tree,
+    extractor: Box<dyn IndexExtractor>,
+    unique: bool,
+}
+
+impl IndexInner {
+    fn extract(&self, key: &[u8], value: Option<&[u8]>) -> Option<Vec<u8>> {
+        value.and_then(|bytes| (self.extractor)(key, bytes))
+    }
+
+    fn insert_entry(
+        &self,
+        entry_key: &[u8],
+        key: Option<&[u8]>,
+        guard: &mut Guard,
+    ) -> Result<()> {
+        let value = key.map(IVec::from);
+        while self
+            .tree
+            .insert_inner(entry_key, value.clone(), true, guard)?
+            .is_err()
+        {}
+        Ok(())
+    }
+
+    // Returns `true` if a key other than `key`, which is not
+    // overwritten or removed by `batch`, is currently stored under
+    // `index_key`.
+    fn is_claimed(
+        &self,
+        tree: &Tree,
+        index_key: &[u8],
+        key: &[u8],
+        batch: &Batch,
+    ) -> Result<bool> {
+        let mut entries = self.tree.scan_prefix(entry_prefix(index_key));
+        while let Some(entry) = entries.next_inner() {
+            let (_entry_key, other_key) = entry?;
+            if other_key == key
+                || batch.writes.contains_key(&other_key)
+                || batch
+                    .removed_ranges
+                    .iter()
+                    .any(|range| range.contains(&other_key))
+            {
+                continue;
+            }
+
+            // the entries of expired keys remain until they are reaped
+            let mut guard = pin();
+            let value = loop {
+                if let Ok(value) = tree.get_inner(&other_key, &mut guard)? {
+                    break value;
+                }
+            };
+            let current = self.extract(&other_key, value.as_deref());
+            if current.as_deref() == Some(index_key) {
+                return Ok(true);
+            }
+        }
+        Ok(false)
+    }
+
+    // Fills the empty tree of this index with the entries of every
+    // key of `tree`. Must be called while holding the exclusive
+    // concurrency control lock.
+    fn build(&self, tree: &Tree, guard: &mut Guard) -> Result<()> {
+        let mut pairs = tree.iter();
+        while let Some(pair) = pairs.next_inner() {
+            let (key, value) = pair?;
+            if let Some(index_key) = (self.extractor)(&key, &value) {
+                if self.unique
+                    && self
+                        .tree
+                        .scan_prefix(entry_prefix(&index_key))
+                        .next_inner()
+                        .is_some()
+                {
+                    return Err(unique_violation(self, &index_key));
+                }
+                self.insert_entry(
+                    &entry_key(&index_key, &key),
+                    Some(&key),
+                    guard,
+                )?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// The indexes that have been created on a `Tree`.
+#[derive(Default)]
+pub(crate) struct Indexes {
+    registered: RwLock<Vec<Arc<IndexInner>>>,
+    // held by single-key writes, which run concurrently with each
+    // other, from checking the unique indexes until they have been
+    // updated, so that two keys can't claim the same index key.
+    unique_writes: Mutex<()>,
+}
+
+impl Indexes {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.registered.read().is_empty()
+    }
+
+    // Checks that writing `value` to `key` of `tree` keeps each of
+    // its unique indexes unique, returning a lock that must be held
+    // until the write has been linked, if there are any.
+    pub(crate) fn claim(
+        &self,
+        tree: &Tree,
+        key: &[u8],
+        value: Option<&[u8]>,
+    ) -> Result<Option<MutexGuard<'_, ()>>> {
+        if !self.registered.read().iter().any(|index| index.unique) {
+            return Ok(None);
+        }
+
+        let lock = self.unique_writes.lock();
+        if let Some(new_value) = value {
+            let mut batch = Batch::default();
+            batch.insert(key, new_value);
+            self.check_unique(tree, &batch)?;
+        }
+        Ok(Some(lock))
+    }
+
+    // Checks that applying `batch` to `tree` keeps each of its
+    // unique indexes unique, before any of it is written. Must be
+    // called while holding either the lock returned by `claim` or
+    // the exclusive concurrency control lock.
+    pub(crate) fn check_unique(
+        &self,
+        tree: &Tree,
+        batch: &Batch,
+    ) -> Result<()> {
+        for index in self.registered.read().iter().filter(|index| index.unique)
+        {
+            let mut claimed = FastMap8::default();
+            for (key, value) in &batch.writes {
+                let index_key = if let Some(index_key) =
+                    index.extract(key, value.as_deref())
+                {
+                    index_key
+                } else {
+                    continue;
+                };
+                if index.is_claimed(tree, &index_key, key, batch)?
+                    || claimed.insert(index_key.clone(), key).is_some()
+                {
+                    return Err(unique_violation(index, &index_key));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Moves the entries of `key` in each index from its last value
+    // to its new one. Called after each write to the indexed `Tree`
+    // was linked, while the log is pinned, so that both are
+    // recovered atomically.
+    pub(crate) fn update(
+        &self,
+        key: &[u8],
+        last_value: Option<&[u8]>,
+        value: Option<&[u8]>,
+        guard: &mut Guard,
+    ) -> Result<()> {
+        for index in self.registered.read().iter() {
+            let last_index_key = index.extract(key, last_value);
+            let index_key = index.extract(key, value);
+            if last_index_key == index_key {
+                continue;
+            }
+            if let Some(old) = last_index_key {
+                index.insert_entry(&entry_key(&old, key), None, guard)?;
+            }
+            if let Some(new) = index_key {
+                index.insert_entry(&entry_key(&new, key), Some(key), guard)?;
+            }
+        }
+        Ok(())
+    }
+
+    // Calls `f` on the hidden `Tree` of each registered index.
+    pub(crate) fn for_each_tree(&self, mut f: impl FnMut(&Tree)) {
+        for index in self.registered.read().iter() {
+            f(&index.tree);
+        }
+    }
+}
+
+// Builds the entries of the index called `name` of `tree` from
+// every key of `tree` in a new hidden `Tree`, which replaces the
+// one of any index of `tree` with the same name and is registered
+// once it is complete. If building fails, any previous index is
+// left as it was.
+pub(crate) fn create(
+    tree: &Tree,
+    name: &[u8],
+    extractor: Box<dyn IndexExtractor>,
+    unique: bool,
+) -> Result<Index> {
+    tree.context.verify_writable()?;
+
+    // trees flush when they are dropped, which takes the
+    // concurrency control lock, so the trees that are opened or
+    // replaced while holding it are dropped after releasing it,
+    // and so are the pages of the ones that were unlinked
+    let mut opened = vec![];
+    let mut unlinked = vec![];
+    let cc = concurrency_control::write();
+    let created = build_and_replace(
+        tree,
+        name,
+        extractor,
+        unique,
+        &mut opened,
+        &mut unlinked,
+    );
+    drop(cc);
+    drop(opened);
+    for leftmost_chain in unlinked {
+        tree.gc_pages(leftmost_chain)?;
+    }
+
+    created.map(|inner| Index { tree: tree.clone(), inner })
+}
+
+// Must be called while holding the exclusive concurrency control
+// lock.
+fn build_and_replace(
+    tree: &Tree,
+    name: &[u8],
+    extractor: Box<dyn IndexExtractor>,
+    unique: bool,
+    opened: &mut Vec<Tree>,
+    unlinked: &mut Vec<Vec<PageId>>,
+) -> Result<Arc<IndexInner>> {
+    let index_tree_name = index_tree_name(&tree.tree_id, name);
+    let rebuild_tree_name = rebuild_tree_name(&tree.tree_id, name);
+    let pagecache = &tree.context.pagecache;
+    let mut guard = pin();
+
+    // remove what was built before the process crashed while an
+    // index with the same name was being created
+    match pagecache.meta_pid_for_name(&rebuild_tree_name, &guard) {
+        Ok(_) => unlinked.push(tree.unlink_tree(&rebuild_tree_name, &guard)?),
+        Err(Error::CollectionNotFound(_)) => {}
+        Err(other) => return Err(other),
+    }
+
+    let rebuild_tree = meta::open_tree(
+        &tree.context,
+        rebuild_tree_name.clone(),
+        None,
+        None,
+        None,
+        &guard,
+    )?;
+    opened.push(rebuild_tree.clone());
+    let mut building = IndexInner {
+        name: IVec::from(name),
+        tree: rebuild_tree,
+        extractor,
+        unique,
+    };
+
+    let built = building.build(tree, &mut guard);
+
+    // the built tree is unlinked before it replaces the previous
+    // one, so that a crash in between can't leave both names
+    // pointing to the same pages
+    let rebuilt_chain = tree.unlink_tree(&rebuild_tree_name, &guard)?;
+    if let Err(e) = built {
+        unlinked.push(rebuilt_chain);
+        return Err(e);
+    }
+
+    let mut old_root =
+        match pagecache.meta_pid_for_name(&index_tree_name, &guard) {
+            Ok(root_id) => Some(root_id),
+            Err(Error::CollectionNotFound(_)) => None,
+            Err(other) => return Err(other),
+        };
+    while let Err(actual_root) = pagecache.cas_root_in_meta(
+        &index_tree_name,
+        old_root,
+        Some(rebuilt_chain[0]),
+        &guard,
+    )? {
+        old_root = actual_root;
+    }
+    if let Some(old_root_id) = old_root {
+        unlinked.push(tree.leftmost_chain(old_root_id, &guard)?);
+    }
+
+    building.tree = meta::open_tree(
+        &tree.context,
+        index_tree_name,
+        None,
+        None,
+        None,
+        &guard,
+    )?;
+    opened.push(building.tree.clone());
+    let inner = Arc::new(building);
+
+    let mut registered = tree.indexes.registered.write();
+    let position = registered.iter().position(|index| index.name == inner.name);
+    if let Some(i) = position {
+        let replaced = registered.remove(i);
+        // signal to readers of the replaced index that its pages
+        // are no longer valid
+        replaced.tree.root.store(u64::max_value(), SeqCst);
+        opened.push(replaced.tree.clone());
+    }
+    registered.push(inner.clone());
+
+    Ok(inner)
+}
+
+/// A secondary index of a `Tree`, created with `Tree::create_index`
+/// or `Tree::create_unique_index`, which looks up keys and values of
+/// the `Tree` by the keys that its extractor returns for them.
+///
+/// The index is updated atomically with every write to its `Tree`,
+/// including batches and transactions.
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// # let config = sled::Config::new().temporary(true);
+/// # let db = config.open()?;
+/// let users = db.open_tree(b"users")?;
+///
+/// // index users by the first byte of their value, their age
+/// let by_age = users.create_index(b"by_age", |_key: &[u8], value: &[u8]| {
+///     value.get(..1).map(<[u8]>::to_vec)
+/// })?;
+///
+/// users.insert(b"alice", vec![30, b'a'])?;
+/// users.insert(b"bob", vec![25, b'b'])?;
+/// users.insert(b"carol", vec![30, b'c'])?;
+///
+/// let thirty: Vec<_> = by_age.get([30]).keys().collect::<Result<_, _>>()?;
+/// assert_eq!(thirty, vec![sled::IVec::from(b"alice"), sled::IVec::from(b"carol")]);
+///
+/// let (youngest, _value) = by_age.range([0]..[30]).next().unwrap()?;
+/// assert_eq!(youngest, b"bob");
+/// # Ok(()) }
+/// ```
+#[derive(Clone)]
+pub struct Index {
+    tree: Tree,
+    inner: Arc<IndexInner>,
+}
+
+impl Index {
+    /// Returns the name of the index.
+    pub fn name(&self) -> IVec {
+        self.inner.name.clone()
+    }
+
+    /// Iterate over the keys and values of the indexed `Tree`
+    /// that are stored under the provided index key, ordered
+    /// by their keys.
+    pub fn get<K: AsRef<[u8]>>(&self, index_key: K) -> IndexIter {
+        let key_ref = index_key.as_ref();
+        self.range(key_ref..=key_ref)
+    }
+
+    /// Iterate over the keys and values of the indexed `Tree`
+    /// whose index keys fall within the specified range,
+    /// ordered by their index keys and then by their keys.
+    pub fn range<K, R>(&self, range: R) -> IndexIter
+    where
+        K: AsRef<[u8]>,
+        R: RangeBounds<K>,
+    {
+        let lo = match range.start_bound() {
+            Bound::Included(start) => {
+                Bound::Included(entry_prefix(start.as_ref()))
+            }
+            Bound::Excluded(start) => {
+                Bound::Included(entry_bound(start.as_ref(), 2))
+            }
+            Bound::Unbounded => Bound::Unbounded,
+        };
+        let hi = match range.end_bound() {
+            Bound::Included(end) => {
+                Bound::Excluded(entry_bound(end.as_ref(), 2))
+            }
+            Bound::Excluded(end) => Bound::Excluded(entry_prefix(end.as_ref())),
+            Bound::Unbounded => Bound::Unbounded,
+        };
+
+        IndexIter {
+            tree: self.tree.clone(),
+            index: self.inner.clone(),
+            entries: self.inner.tree.range((lo, hi)),
+        }
+    }
+}
+
+/// An iterator over keys and values of a `Tree` that are found
+/// through an `Index`, created with `Index::get` or `Index::range`.
+pub struct IndexIter {
+    tree: Tree,
+    index: Arc<IndexInner>,
+    entries: Iter,
+}
+
+impl IndexIter {
+    /// Iterate over the keys of the indexed `Tree`
+    pub fn keys(self) -> impl DoubleEndedIterator<Item = Result<IVec>> {
+        self.map(|r| r.map(|(k, _v)| k))
+    }
+
+    /// Iterate over the values of the indexed `Tree`
+    pub fn values(self) -> impl DoubleEndedIterator<Item = Result<IVec>> {
+        self.map(|r| r.map(|(_k, v)| v))
+    }
+
+    // Reads the key and value that an entry points to, or returns
+    // `None` if the entry is stale because the key expired or was
+    // written to after the entry was read.
+    fn resolve(
+        &self,
+        entry: Result<(IVec, IVec)>,
+    ) -> Option<Result<(IVec, IVec)>> {
+        let (stored_key, key) = match entry {
+            Ok(pair) => pair,
+            Err(e) => return Some(Err(e)),
+        };
+
+        let mut guard = pin();
+        let value = loop {
+            match self.tree.get_inner(&key, &mut guard) {
+                Ok(Ok(value)) => break value?,
+                Ok(Err(_)) => {}
+                Err(e) => return Some(Err(e)),
+            }
+        };
+
+        let index_key = self.index.extract(&key, Some(&value))?;
+        if stored_key == entry_key(&index_key, &key) {
+            Some(Ok((key, value)))
+        } else {
+            None
+        }
+    }
+}
+
+impl Iterator for IndexIter {
+    type Item = Result<(IVec, IVec)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let _cc = concurrency_control::read();
+        loop {
+            let entry = self.entries.next_inner()?;
+            if let Some(ret) = self.resolve(entry) {
+                return Some(ret);
+            }
+        }
+    }
+
+    fn last(mut self) -> Option<Self::Item> {
+        self.next_back()
+    }
+}
+
+impl DoubleEndedIterator for IndexIter {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let _cc = concurrency_control::read();
+        loop {
+            let entry = self.entries.next_back_inner()?;
+            if let Some(ret) = self.resolve(entry) {
+                return Some(ret);
+            }
+        }
+    }
+}
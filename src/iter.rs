@@ -85,6 +85,26 @@ impl Iter {
     }
 
     pub(crate) fn next_inner(&mut self) -> Option<<Self as Iterator>::Item> {
+        loop {
+            let (key, value) = iter_try!(self.next_including_expired()?);
+            if !iter_try!(self.tree.is_expired(&key)) {
+                return Some(Ok((key, value)));
+            }
+        }
+    }
+
+    pub(crate) fn next_back_inner(
+        &mut self,
+    ) -> Option<<Self as Iterator>::Item> {
+        loop {
+            let (key, value) = iter_try!(self.next_back_including_expired()?);
+            if !iter_try!(self.tree.is_expired(&key)) {
+                return Some(Ok((key, value)));
+            }
+        }
+    }
+
+    fn next_including_expired(&mut self) -> Option<<Self as Iterator>::Item> {
         let guard = pin();
         let (mut pid, mut node) = if let (true, Some((pid, node))) =
             (self.going_forward, self.cached_node.take())
@@ -145,29 +165,11 @@ impl Iter {
             self.lo, self.tree
         );
     }
-}
-
-impl Iterator for Iter {
-    type Item = Result<(IVec, IVec)>;
-
-    fn next(&mut self) -> Option<Self::Item> {
-        #[cfg(feature = "metrics")]
-        let _measure = Measure::new(&M.tree_scan);
-        let _cc = concurrency_control::read();
-        self.next_inner()
-    }
 
-    fn last(mut self) -> Option<Self::Item> {
-        self.next_back()
-    }
-}
-
-impl DoubleEndedIterator for Iter {
-    fn next_back(&mut self) -> Option<Self::Item> {
-        #[cfg(feature = "metrics")]
-        let _measure = Measure::new(&M.tree_reverse_scan);
+    fn next_back_including_expired(
+        &mut self,
+    ) -> Option<<Self as Iterator>::Item> {
         let guard = pin();
-        let _cc = concurrency_control::read();
 
         let (mut pid, mut node) = if let (false, Some((pid, node))) =
             (self.going_forward, self.cached_node.take())
@@ -230,6 +232,30 @@ impl DoubleEndedIterator for Iter {
     }
 }
 
+impl Iterator for Iter {
+    type Item = Result<(IVec, IVec)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        #[cfg(feature = "metrics")]
+        let _measure = Measure::new(&M.tree_scan);
+        let _cc = concurrency_control::read();
+        self.next_inner()
+    }
+
+    fn last(mut self) -> Option<Self::Item> {
+        self.next_back()
+    }
+}
+
+impl DoubleEndedIterator for Iter {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        #[cfg(feature = "metrics")]
+        let _measure = Measure::new(&M.tree_reverse_scan);
+        let _cc = concurrency_control::read();
+        self.next_back_inner()
+    }
+}
+
 #[test]
 fn basic_functionality() {
     assert_eq!(possible_predecessor(b""), None);
@@ -0,0 +1,85 @@
+use std::marker::PhantomData;
+
+use crate::{IVec, Iter, Result};
+
+/// An iterator over the keys and values of a `TypedTree`,
+/// created by `TypedTree::iter`, `TypedTree::range` or
+/// `TypedTree::scan_prefix`.
+///
+/// Keys and values are returned as `IVec`s that carry the
+/// encodings of the `TypedTree`, like `TypedTree::get` returns
+/// values, and are decoded with `IVec::decode`, which returns an
+/// error instead of panicking if the stored bytes can't be
+/// decoded. They are not decoded by the iterator itself because
+/// a `Decoder` may return a value that borrows the bytes it
+/// decodes, such as the `&str` of `StringEncoding`, and an
+/// iterator can't return items that borrow from itself.
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let db = sled::Config::default().temporary(true).open()?;
+/// let tree = db
+///     .open_tree("id_to_name")?
+///     .with_encodings::<sled::IntegerEncoding<u128>, sled::StringEncoding>();
+///
+/// tree.insert(1, "January")?;
+/// tree.insert(2, "February")?;
+/// tree.insert(3, "March")?;
+///
+/// let mut iter = tree.range(2..);
+/// let (key, value) = iter.next().unwrap()?;
+/// assert_eq!(key.decode()?, 2);
+/// assert_eq!(value.decode()?, "February");
+///
+/// let (key, value) = iter.next_back().unwrap()?;
+/// assert_eq!(key.decode()?, 3);
+/// assert_eq!(value.decode()?, "March");
+///
+/// assert!(iter.next().is_none());
+/// # Ok(()) }
+/// ```
+pub struct TypedIter<K, V> {
+    iter: Iter,
+    _k: PhantomData<K>,
+    _v: PhantomData<V>,
+}
+
+impl<K, V> TypedIter<K, V> {
+    pub(crate) fn new(iter: Iter) -> Self {
+        Self { iter, _k: PhantomData, _v: PhantomData }
+    }
+
+    /// Iterate over the keys of this `TypedTree`
+    pub fn keys(self) -> impl DoubleEndedIterator<Item = Result<IVec<K>>> {
+        self.map(|r| r.map(|(k, _v)| k))
+    }
+
+    /// Iterate over the values of this `TypedTree`
+    pub fn values(self) -> impl DoubleEndedIterator<Item = Result<IVec<V>>> {
+        self.map(|r| r.map(|(_k, v)| v))
+    }
+}
+
+impl<K, V> Iterator for TypedIter<K, V> {
+    type Item = Result<(IVec<K>, IVec<V>)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter
+            .next()
+            .map(|res| res.map(|(k, v)| (k.into_encoding(), v.into_encoding())))
+    }
+
+    fn last(mut self) -> Option<Self::Item> {
+        self.next_back()
+    }
+}
+
+impl<K, V> DoubleEndedIterator for TypedIter<K, V> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        self.iter
+            .next_back()
+            .map(|res| res.map(|(k, v)| (k.into_encoding(), v.into_encoding())))
+    }
+}
@@ -179,41 +179,56 @@ macro_rules! testing_assert {
 
 mod atomic_shim;
 mod backoff;
+mod backup;
 mod batch;
+mod blob;
 #[cfg(feature = "experimental_typed_api")]
 mod batch_typed;
 mod cache_padded;
+mod change_feed;
 mod concurrency_control;
 mod config;
 mod context;
 mod db;
 mod dll;
 mod ebr;
+#[cfg(feature = "experimental_typed_api")]
 mod encoding;
 mod fastcmp;
 mod fastlock;
 mod fnv;
 mod histogram;
+mod index;
 mod iter;
+#[cfg(feature = "experimental_typed_api")]
+mod iter_typed;
 mod ivec;
 mod lazy;
 mod lru;
+pub mod merge_operators;
 mod meta;
 #[cfg(feature = "metrics")]
 mod metrics;
 mod node;
 mod oneshot;
 mod pagecache;
+mod queue;
 mod result;
 mod serialization;
+mod snapshot;
 mod stack;
 mod subscriber;
+#[cfg(feature = "experimental_typed_api")]
+mod subscriber_typed;
 mod sys_limits;
 mod threadpool;
 pub mod transaction;
+#[cfg(feature = "experimental_typed_api")]
+mod transaction_typed;
 mod tree;
 #[cfg(feature = "experimental_typed_api")]
 mod tree_typed;
+mod ttl;
 mod varint;
 
 /// Functionality for conditionally triggering failpoints under test.
@@ -294,12 +309,18 @@ pub use self::{
 };
 
 pub use self::{
+    backup::{restore_backup, BackupFile, BackupManifest, BackupProgress},
     batch::Batch,
+    blob::{BlobReader, BlobWriter},
+    change_feed::{Change, ChangeIter},
     config::{Config, Mode},
     db::Db,
+    index::{Index, IndexExtractor, IndexIter},
     iter::Iter,
     ivec::IVec,
+    queue::Lease,
     result::{Error, Result},
+    snapshot::{Snapshot, SnapshotIter, TreeSnapshot},
     subscriber::{Event, Subscriber},
     transaction::Transactional,
     tree::{CompareAndSwapError, Tree},
@@ -308,10 +329,21 @@ pub use self::{
 #[cfg(feature = "experimental_typed_api")]
 pub use self::{
     batch_typed::TypedBatch,
-    encoding::{Decoder, Encoder, IntegerEncoding, StringEncoding},
+    encoding::{
+        BoolEncoding, BytesEncoding, DecodeError, Decoder, Encoder,
+        FloatEncoding, IntegerEncoding, OrderedEncoding, StringEncoding,
+    },
+    iter_typed::TypedIter,
+    subscriber_typed::{
+        TypedChange, TypedEvent, TypedEventError, TypedSubscriber,
+    },
+    transaction_typed::TypedTransactionalTree,
     tree_typed::TypedTree,
 };
 
+#[cfg(feature = "serde")]
+pub use self::encoding::{Bincode, SerdeEncoding, SerdeFormat};
+
 #[cfg(feature = "metrics")]
 use self::{
     histogram::Histogram,
@@ -0,0 +1,574 @@
+//! Ready-made merge operators for common kinds of values, for use
+//! with `Tree::set_merge_operator` or `Config::merge_operator`.
+//!
+//! Each merge operator leaves the stored value unchanged when it
+//! is given an operand that it can't decode, and treats a stored
+//! value that it can't decode as if the key were absent.
+//!
+//! # Examples
+//!
+//! ```
+//! # fn main() -> sled::Result<()> {
+//! use sled::{merge_operators, IVec};
+//!
+//! let db = sled::Config::new().temporary(true).open()?;
+//! db.set_merge_operator(merge_operators::u64_add);
+//!
+//! db.merge(b"hits", 3_u64.to_le_bytes())?;
+//! db.merge(b"hits", 4_u64.to_le_bytes())?;
+//! assert_eq!(db.get(b"hits")?, Some(IVec::from(&7_u64.to_le_bytes())));
+//! # Ok(()) }
+//! ```
+
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    convert::{TryFrom, TryInto},
+};
+
+use crate::IVec;
+
+/// Adds a little-endian `u64` operand to a little-endian `u64`
+/// value, wrapping around on overflow.
+pub fn u64_add(
+    _key: &[u8],
+    old: Option<&[u8]>,
+    merged: &[u8],
+) -> Option<Vec<u8>> {
+    merge_u64(old, merged, u64::wrapping_add)
+}
+
+/// Keeps the larger of a little-endian `u64` operand and a
+/// little-endian `u64` value.
+pub fn u64_max(
+    _key: &[u8],
+    old: Option<&[u8]>,
+    merged: &[u8],
+) -> Option<Vec<u8>> {
+    merge_u64(old, merged, std::cmp::max)
+}
+
+/// Keeps the smaller of a little-endian `u64` operand and a
+/// little-endian `u64` value.
+pub fn u64_min(
+    _key: &[u8],
+    old: Option<&[u8]>,
+    merged: &[u8],
+) -> Option<Vec<u8>> {
+    merge_u64(old, merged, std::cmp::min)
+}
+
+fn merge_u64<F>(old: Option<&[u8]>, merged: &[u8], f: F) -> Option<Vec<u8>>
+where
+    F: Fn(u64, u64) -> u64,
+{
+    let operand = if let Some(operand) = decode_u64(merged) {
+        operand
+    } else {
+        return old.map(<[u8]>::to_vec);
+    };
+
+    let new = match old.and_then(decode_u64) {
+        Some(current) => f(current, operand),
+        None => operand,
+    };
+
+    Some(new.to_le_bytes().to_vec())
+}
+
+fn decode_u64(bytes: &[u8]) -> Option<u64> {
+    bytes.try_into().ok().map(u64::from_le_bytes)
+}
+
+/// Appends the operand to the end of the value.
+pub fn append(
+    _key: &[u8],
+    old: Option<&[u8]>,
+    merged: &[u8],
+) -> Option<Vec<u8>> {
+    let mut ret = old.map(<[u8]>::to_vec).unwrap_or_default();
+    ret.extend_from_slice(merged);
+    Some(ret)
+}
+
+/// Adds the items of a set encoded with `encode_set` to the set
+/// stored as the value.
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> sled::Result<()> {
+/// use sled::{
+///     merge_operators::{decode_set, encode_set, set_union},
+///     IVec,
+/// };
+///
+/// let db = sled::Config::new().temporary(true).open()?;
+/// db.set_merge_operator(set_union);
+///
+/// db.merge(b"tags", encode_set(&[b"b", b"a"]))?;
+/// db.merge(b"tags", encode_set(&[b"c", b"b"]))?;
+///
+/// let tags = decode_set(&db.get(b"tags")?.unwrap()).unwrap();
+/// assert_eq!(tags, vec![IVec::from(b"a"), b"b".into(), b"c".into()]);
+/// # Ok(()) }
+/// ```
+pub fn set_union(
+    _key: &[u8],
+    old: Option<&[u8]>,
+    merged: &[u8],
+) -> Option<Vec<u8>> {
+    let operand = if let Some(items) = decode_set_items(merged) {
+        items
+    } else {
+        return old.map(<[u8]>::to_vec);
+    };
+
+    let mut items: BTreeSet<&[u8]> = old
+        .and_then(decode_set_items)
+        .map(|current| current.into_iter().collect())
+        .unwrap_or_default();
+    items.extend(operand);
+
+    Some(encode_set(items))
+}
+
+/// Encodes a set of items for `set_union`. Each distinct item is
+/// stored once, in sorted order, after its length as a
+/// little-endian `u64`.
+pub fn encode_set<I, T>(items: I) -> Vec<u8>
+where
+    I: IntoIterator<Item = T>,
+    T: AsRef<[u8]>,
+{
+    let mut sorted: Vec<T> = items.into_iter().collect();
+    sorted.sort_by(|a, b| a.as_ref().cmp(b.as_ref()));
+    sorted.dedup_by(|a, b| a.as_ref() == b.as_ref());
+
+    let mut ret = vec![];
+    for item in sorted {
+        let item_bytes = item.as_ref();
+        ret.extend_from_slice(&(item_bytes.len() as u64).to_le_bytes());
+        ret.extend_from_slice(item_bytes);
+    }
+    ret
+}
+
+/// Decodes the items of a set that was encoded with `encode_set`,
+/// or returns `None` if the bytes are not a valid encoding.
+pub fn decode_set(bytes: &[u8]) -> Option<Vec<IVec>> {
+    decode_set_items(bytes)
+        .map(|items| items.into_iter().map(IVec::from).collect())
+}
+
+fn decode_set_items(mut bytes: &[u8]) -> Option<Vec<&[u8]>> {
+    let mut items = vec![];
+    while !bytes.is_empty() {
+        let (item, rest) = split_len_prefixed(bytes)?;
+        items.push(item);
+        bytes = rest;
+    }
+    Some(items)
+}
+
+// Splits bytes that start with a little-endian `u64` length into
+// the item of that length that follows it, and the rest.
+fn split_len_prefixed(bytes: &[u8]) -> Option<(&[u8], &[u8])> {
+    if bytes.len() < 8 {
+        return None;
+    }
+    let (len_bytes, rest) = bytes.split_at(8);
+    let len = usize::try_from(decode_u64(len_bytes)?).ok()?;
+    if len > rest.len() {
+        return None;
+    }
+    Some(rest.split_at(len))
+}
+
+/// A grow-only counter that replicas can increment independently,
+/// and that is merged deterministically with `g_counter`.
+///
+/// Each replica keeps its own count, and the value of the counter
+/// is their sum. Merging keeps the highest count seen for each
+/// replica, so a replica increments the counter by merging its
+/// whole, increased `GCounter`, and merging the same state more
+/// than once, or in any order, has no further effect.
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> sled::Result<()> {
+/// use sled::merge_operators::{g_counter, GCounter};
+///
+/// let db = sled::Config::new().temporary(true).open()?;
+/// db.set_merge_operator(g_counter);
+///
+/// let mut on_a = GCounter::new();
+/// on_a.increment(b"a", 2);
+/// db.merge(b"visits", on_a.to_bytes())?;
+///
+/// let mut on_b = GCounter::new();
+/// on_b.increment(b"b", 5);
+/// db.merge(b"visits", on_b.to_bytes())?;
+/// db.merge(b"visits", on_b.to_bytes())?;
+///
+/// let visits = GCounter::from_bytes(&db.get(b"visits")?.unwrap()).unwrap();
+/// assert_eq!(visits.value(), 7);
+/// # Ok(()) }
+/// ```
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct GCounter {
+    counts: BTreeMap<IVec, u64>,
+}
+
+impl GCounter {
+    /// Returns a `GCounter` whose value is zero.
+    pub fn new() -> GCounter {
+        GCounter::default()
+    }
+
+    /// Adds `by` to the count of `replica`, saturating at
+    /// `u64::MAX`.
+    pub fn increment<R>(&mut self, replica: R, by: u64)
+    where
+        R: AsRef<[u8]>,
+    {
+        let count = self.counts.entry(replica.as_ref().into()).or_insert(0);
+        *count = count.saturating_add(by);
+    }
+
+    /// Returns the count of `replica`.
+    pub fn count<R>(&self, replica: R) -> u64
+    where
+        R: AsRef<[u8]>,
+    {
+        self.counts.get(replica.as_ref()).copied().unwrap_or(0)
+    }
+
+    /// Returns the sum of the counts of every replica, saturating
+    /// at `u64::MAX`.
+    pub fn value(&self) -> u64 {
+        self.counts.values().fold(0, |sum, count| sum.saturating_add(*count))
+    }
+
+    /// Merges `other` into this `GCounter`, keeping the highest
+    /// count of each replica.
+    pub fn merge(&mut self, other: &GCounter) {
+        for (replica, other_count) in &other.counts {
+            let count = self.counts.entry(replica.clone()).or_insert(0);
+            *count = (*count).max(*other_count);
+        }
+    }
+
+    /// Encodes this `GCounter` as a value or merge operand for
+    /// `g_counter`.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut ret = vec![];
+        for (replica, count) in &self.counts {
+            ret.extend_from_slice(&(replica.len() as u64).to_le_bytes());
+            ret.extend_from_slice(replica);
+            ret.extend_from_slice(&count.to_le_bytes());
+        }
+        ret
+    }
+
+    /// Decodes a `GCounter` that was encoded with `to_bytes`, or
+    /// returns `None` if the bytes are not a valid encoding.
+    pub fn from_bytes(mut bytes: &[u8]) -> Option<GCounter> {
+        let mut counts = BTreeMap::new();
+        while !bytes.is_empty() {
+            let (replica, rest) = split_len_prefixed(bytes)?;
+            if rest.len() < 8 {
+                return None;
+            }
+            let (count_bytes, after_count) = rest.split_at(8);
+            let count = decode_u64(count_bytes)?;
+            if counts.insert(IVec::from(replica), count).is_some() {
+                return None;
+            }
+            bytes = after_count;
+        }
+        Some(GCounter { counts })
+    }
+}
+
+/// Merges a `GCounter` operand into a `GCounter` value.
+pub fn g_counter(
+    _key: &[u8],
+    old: Option<&[u8]>,
+    merged: &[u8],
+) -> Option<Vec<u8>> {
+    let operand = if let Some(operand) = GCounter::from_bytes(merged) {
+        operand
+    } else {
+        return old.map(<[u8]>::to_vec);
+    };
+
+    let mut counter = old.and_then(GCounter::from_bytes).unwrap_or_default();
+    counter.merge(&operand);
+
+    Some(counter.to_bytes())
+}
+
+/// A last-writer-wins register, which keeps the value that was
+/// written with the highest timestamp and is merged
+/// deterministically with `lww_register`.
+///
+/// Writes with the same timestamp are ordered by their values, so
+/// that every replica keeps the same one no matter which order
+/// they are merged in.
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> sled::Result<()> {
+/// use sled::merge_operators::{lww_register, LwwRegister};
+///
+/// let db = sled::Config::new().temporary(true).open()?;
+/// db.set_merge_operator(lww_register);
+///
+/// db.merge(b"status", LwwRegister::new(2, "away").to_bytes())?;
+/// db.merge(b"status", LwwRegister::new(1, "online").to_bytes())?;
+///
+/// let status = LwwRegister::from_bytes(&db.get(b"status")?.unwrap()).unwrap();
+/// assert_eq!(status, LwwRegister::new(2, "away"));
+/// # Ok(()) }
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LwwRegister {
+    /// The timestamp the value was written at.
+    pub timestamp: u64,
+    /// The value of the register.
+    pub value: IVec,
+}
+
+impl LwwRegister {
+    /// Returns an `LwwRegister` holding `value`, written at
+    /// `timestamp`.
+    pub fn new<V>(timestamp: u64, value: V) -> LwwRegister
+    where
+        V: Into<IVec>,
+    {
+        LwwRegister { timestamp, value: value.into() }
+    }
+
+    /// Merges `other` into this `LwwRegister`, keeping whichever
+    /// was written last.
+    pub fn merge(&mut self, other: &LwwRegister) {
+        if (other.timestamp, &other.value) > (self.timestamp, &self.value) {
+            *self = other.clone();
+        }
+    }
+
+    /// Encodes this `LwwRegister` as a value or merge operand for
+    /// `lww_register`.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut ret = Vec::with_capacity(8 + self.value.len());
+        ret.extend_from_slice(&self.timestamp.to_le_bytes());
+        ret.extend_from_slice(&self.value);
+        ret
+    }
+
+    /// Decodes an `LwwRegister` that was encoded with `to_bytes`,
+    /// or returns `None` if the bytes are not a valid encoding.
+    pub fn from_bytes(bytes: &[u8]) -> Option<LwwRegister> {
+        if bytes.len() < 8 {
+            return None;
+        }
+        let (timestamp_bytes, value) = bytes.split_at(8);
+        let timestamp = decode_u64(timestamp_bytes)?;
+        Some(LwwRegister::new(timestamp, value))
+    }
+}
+
+/// Merges an `LwwRegister` operand into an `LwwRegister` value.
+pub fn lww_register(
+    _key: &[u8],
+    old: Option<&[u8]>,
+    merged: &[u8],
+) -> Option<Vec<u8>> {
+    let operand = if let Some(operand) = LwwRegister::from_bytes(merged) {
+        operand
+    } else {
+        return old.map(<[u8]>::to_vec);
+    };
+
+    let register =
+        if let Some(mut current) = old.and_then(LwwRegister::from_bytes) {
+            current.merge(&operand);
+            current
+        } else {
+            operand
+        };
+
+    Some(register.to_bytes())
+}
+
+#[cfg(test)]
+mod qc {
+    use quickcheck::{Arbitrary, Gen};
+
+    use super::*;
+
+    impl Arbitrary for GCounter {
+        fn arbitrary<G: Gen>(g: &mut G) -> GCounter {
+            let mut counter = GCounter::new();
+            let increments: Vec<(u8, u32)> = Arbitrary::arbitrary(g);
+            for (replica, by) in increments {
+                counter.increment([replica % 4], u64::from(by));
+            }
+            counter
+        }
+    }
+
+    impl Arbitrary for LwwRegister {
+        fn arbitrary<G: Gen>(g: &mut G) -> LwwRegister {
+            let timestamp: u8 = Arbitrary::arbitrary(g);
+            let value: Vec<u8> = Arbitrary::arbitrary(g);
+            LwwRegister::new(u64::from(timestamp % 4), value)
+        }
+    }
+
+    type MergeFn = fn(&[u8], Option<&[u8]>, &[u8]) -> Option<Vec<u8>>;
+
+    // merges each operand in turn into an absent value
+    fn merge_all(f: MergeFn, operands: &[Vec<u8>]) -> Option<Vec<u8>> {
+        let mut value: Option<Vec<u8>> = None;
+        for operand in operands {
+            value = f(b"k", value.as_deref(), operand);
+        }
+        value
+    }
+
+    #[test]
+    fn u64_operators() {
+        let operands: Vec<Vec<u8>> = vec![
+            5_u64.to_le_bytes().to_vec(),
+            9_u64.to_le_bytes().to_vec(),
+            2_u64.to_le_bytes().to_vec(),
+        ];
+        assert_eq!(
+            merge_all(u64_add, &operands),
+            Some(16_u64.to_le_bytes().to_vec())
+        );
+        assert_eq!(
+            merge_all(u64_max, &operands),
+            Some(9_u64.to_le_bytes().to_vec())
+        );
+        assert_eq!(
+            merge_all(u64_min, &operands),
+            Some(2_u64.to_le_bytes().to_vec())
+        );
+
+        let max = [0xff; 8];
+        assert_eq!(
+            u64_add(b"k", Some(&max), &1_u64.to_le_bytes()),
+            Some(vec![0; 8])
+        );
+    }
+
+    #[test]
+    fn undecodable_operands_are_ignored() {
+        let one = 1_u64.to_le_bytes();
+        assert_eq!(u64_add(b"k", Some(&one), b"short"), Some(one.to_vec()));
+        assert_eq!(u64_add(b"k", None, b"short"), None);
+        assert_eq!(u64_add(b"k", Some(b"short"), &one), Some(one.to_vec()));
+
+        let set = encode_set(vec![b"a"]);
+        assert_eq!(set_union(b"k", Some(&set), &[1]), Some(set.clone()));
+        assert_eq!(
+            set_union(b"k", Some(&set), &5_u64.to_le_bytes()),
+            Some(set)
+        );
+
+        let counter = GCounter::new().to_bytes();
+        assert_eq!(g_counter(b"k", Some(&counter), &[1]), Some(counter));
+
+        let register = LwwRegister::new(1, "a").to_bytes();
+        assert_eq!(lww_register(b"k", Some(&register), &[1]), Some(register));
+    }
+
+    #[test]
+    fn append_and_set_union() {
+        let chunks = vec![b"ab".to_vec(), b"c".to_vec()];
+        assert_eq!(merge_all(append, &chunks), Some(b"abc".to_vec()));
+
+        let sets = vec![
+            encode_set(vec![&b"b"[..], b"a", b"b"]),
+            encode_set(Vec::<Vec<u8>>::new()),
+            encode_set(vec![&b"c"[..], b""]),
+        ];
+        let merged = merge_all(set_union, &sets).unwrap();
+        assert_eq!(merged, encode_set(vec![&b""[..], b"a", b"b", b"c"]));
+        assert_eq!(
+            decode_set(&merged).unwrap(),
+            vec![IVec::from(b""), IVec::from(b"a"), b"b".into(), b"c".into()]
+        );
+    }
+
+    #[test]
+    fn g_counter_counts() {
+        let mut counter = GCounter::new();
+        counter.increment(b"a", 3);
+        counter.increment(b"a", 4);
+        counter.increment(b"b", 1);
+        assert_eq!(counter.count(b"a"), 7);
+        assert_eq!(counter.count(b"c"), 0);
+        assert_eq!(counter.value(), 8);
+        assert_eq!(GCounter::from_bytes(&counter.to_bytes()), Some(counter));
+
+        let mut duplicated = GCounter::new();
+        duplicated.increment(b"a", 1);
+        let mut bytes = duplicated.to_bytes();
+        bytes.extend_from_slice(&duplicated.to_bytes());
+        assert_eq!(GCounter::from_bytes(&bytes), None);
+    }
+
+    fn prop_converges<T, F>(f: MergeFn, to_bytes: F, items: &[T]) -> bool
+    where
+        F: Fn(&T) -> Vec<u8>,
+    {
+        // merging in any order, with any item repeated, gives the
+        // same value as merging each item once
+        let forward: Vec<Vec<u8>> = items.iter().map(&to_bytes).collect();
+        let mut backward = forward.clone();
+        backward.reverse();
+        let mut repeated = forward.clone();
+        repeated.extend(backward.iter().cloned());
+
+        let expected = merge_all(f, &forward);
+        expected == merge_all(f, &backward)
+            && expected == merge_all(f, &repeated)
+    }
+
+    quickcheck::quickcheck! {
+        #[cfg_attr(miri, ignore)]
+        fn set_union_converges(items: Vec<Vec<Vec<u8>>>) -> bool {
+            prop_converges(set_union, |set| encode_set(set), &items)
+        }
+
+        #[cfg_attr(miri, ignore)]
+        fn g_counter_converges(items: Vec<GCounter>) -> bool {
+            prop_converges(g_counter, GCounter::to_bytes, &items)
+        }
+
+        #[cfg_attr(miri, ignore)]
+        fn lww_register_converges(items: Vec<LwwRegister>) -> bool {
+            prop_converges(lww_register, LwwRegister::to_bytes, &items)
+        }
+
+        #[cfg_attr(miri, ignore)]
+        fn g_counter_round_trip(counter: GCounter) -> bool {
+            GCounter::from_bytes(&counter.to_bytes()) == Some(counter)
+        }
+
+        #[cfg_attr(miri, ignore)]
+        fn set_round_trip(items: Vec<Vec<u8>>) -> bool {
+            let encoded = encode_set(&items);
+            let mut expected = items;
+            expected.sort();
+            expected.dedup();
+            decode_set(&encoded)
+                == Some(expected.into_iter().map(IVec::from).collect())
+        }
+    }
+}
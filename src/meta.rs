@@ -1,3 +1,5 @@
+use std::sync::atomic::AtomicBool;
+
 use crate::*;
 
 /// A simple map that can be used to store metadata
@@ -5,6 +7,9 @@ use crate::*;
 #[derive(Clone, Debug, Eq, PartialEq, Default)]
 pub struct Meta {
     pub(crate) inner: BTreeMap<IVec, PageId>,
+    // the name of the merge operator registered with
+    // `Config::merge_operator` that each tree was given
+    pub(crate) merge_operators: BTreeMap<IVec, IVec>,
 }
 
 impl Meta {
@@ -22,37 +27,97 @@ impl Meta {
         self.inner.insert(name, pid)
     }
 
-    /// Remove the page mapping for a given identifier
+    /// Remove the page mapping for a given identifier,
+    /// along with the name of its merge operator
     pub(crate) fn del_root(&mut self, name: &[u8]) -> Option<PageId> {
+        self.merge_operators.remove(name);
         self.inner.remove(name)
     }
 
+    /// Retrieve the name of the merge operator associated
+    /// with an identifier
+    pub(crate) fn get_merge_operator(&self, name: &[u8]) -> Option<&IVec> {
+        self.merge_operators.get(name)
+    }
+
+    /// Set the name of the merge operator associated
+    /// with an identifier
+    pub(crate) fn set_merge_operator(
+        &mut self,
+        name: IVec,
+        merge_operator: IVec,
+    ) -> Option<IVec> {
+        self.merge_operators.insert(name, merge_operator)
+    }
+
     /// Return the current rooted tenants in Meta
     pub(crate) fn tenants(&self) -> BTreeMap<IVec, PageId> {
         self.inner.clone()
     }
 
     pub(crate) fn rss(&self) -> u64 {
-        self.inner
+        let roots: u64 = self
+            .inner
+            .keys()
+            .map(|k| k.len() as u64 + std::mem::size_of::<PageId>() as u64)
+            .sum();
+        let merge_operators: u64 = self
+            .merge_operators
             .iter()
-            .map(|(k, _pid)| {
-                k.len() as u64 + std::mem::size_of::<PageId>() as u64
-            })
-            .sum()
+            .map(|(k, v)| k.len() as u64 + v.len() as u64)
+            .sum();
+        roots + merge_operators
+    }
+}
+
+// Returns the merge operator registered with `Config::merge_operator`
+// under the name that was persisted for the tree, if it was given one,
+// or that name if no merge operator is registered under it.
+pub(crate) fn merge_operator_for(
+    context: &Context,
+    name: &[u8],
+    guard: &Guard,
+) -> (Option<Box<dyn MergeOperator>>, Option<IVec>) {
+    let meta = context.pagecache.get_meta(guard);
+    if let Some(merge_operator_name) = meta.get_merge_operator(name) {
+        if let Some(merge_operator) =
+            context.merge_operators.get(merge_operator_name)
+        {
+            (Some(merge_operator), None)
+        } else {
+            (None, Some(merge_operator_name.clone()))
+        }
+    } else {
+        (None, None)
     }
 }
 
+pub(crate) fn unregistered_merge_operator(name: &[u8]) -> Error {
+    Error::Unsupported(format!(
+        "no merge operator named {:?} is registered with \
+         Config::merge_operator",
+        String::from_utf8_lossy(name)
+    ))
+}
+
 /// Open or create a new disk-backed Tree with its own keyspace,
 /// accessible from the `Db` via the provided identifier.
 pub(crate) fn open_tree<V>(
     context: &Context,
     raw_name: V,
+    change_feed: Option<Arc<change_feed::ChangeFeed>>,
+    expiry: Option<Arc<LazyTree>>,
+    blobs: Option<Arc<LazyTree>>,
     guard: &Guard,
 ) -> Result<Tree>
 where
     V: Into<IVec>,
 {
     let name = raw_name.into();
+    let expiry_tree = expiry.as_ref().and_then(|shared| shared.tree());
+    let has_ttl = ttl::has_deadlines(expiry_tree.as_ref(), &name)?;
+    let blobs_tree = blobs.as_ref().and_then(|shared| shared.tree());
+    let has_blobs = blob::has_blobs(blobs_tree.as_ref(), &name)?;
 
     // we loop because creating this Tree may race with
     // concurrent attempts to open the same one.
@@ -60,14 +125,29 @@ where
         match context.pagecache.meta_pid_for_name(&name, guard) {
             Ok(root_id) => {
                 assert_ne!(root_id, 0);
+                let (merge_operator, missing_merge_operator) =
+                    merge_operator_for(context, &name, guard);
                 return Ok(Tree(Arc::new(TreeInner {
                     tree_id: name,
                     context: context.clone(),
                     subscribers: Subscribers::default(),
                     root: AtomicU64::new(root_id),
-                    merge_operator: RwLock::new(None),
+                    merge_operator: RwLock::new(merge_operator),
+                    missing_merge_operator: RwLock::new(
+                        missing_merge_operator,
+                    ),
+                    change_feed,
+                    expiry,
+                    has_ttl: AtomicBool::new(has_ttl),
+                    blobs,
+                    has_blobs: AtomicBool::new(has_blobs),
+                    indexes: index::Indexes::default(),
+                    leases: queue::Leases::default(),
                 })));
             }
+            Err(Error::CollectionNotFound(_)) if context.read_only => {
+                return Err(Error::CollectionNotFound(name));
+            }
             Err(Error::CollectionNotFound(_)) => {}
             Err(other) => return Err(other),
         }
@@ -117,6 +197,73 @@ where
             context: context.clone(),
             root: AtomicU64::new(root_id),
             merge_operator: RwLock::new(None),
+            missing_merge_operator: RwLock::new(None),
+            change_feed,
+            expiry,
+            has_ttl: AtomicBool::new(has_ttl),
+            blobs,
+            has_blobs: AtomicBool::new(has_blobs),
+            indexes: index::Indexes::default(),
+            leases: queue::Leases::default(),
         })));
     }
 }
+
+/// An internal tree that is shared by every `Tree` of a `Db`, such
+/// as the ones that store deadlines or blobs, which is only created
+/// once it is first written to.
+pub(crate) struct LazyTree {
+    context: Context,
+    name: &'static [u8],
+    tree: RwLock<Option<Tree>>,
+}
+
+impl LazyTree {
+    // Opens the tree with the provided name, if it was ever created.
+    pub(crate) fn open(
+        context: &Context,
+        name: &'static [u8],
+        guard: &Guard,
+    ) -> Result<LazyTree> {
+        let tree = match context.pagecache.meta_pid_for_name(name, guard) {
+            Ok(_) => Some(open_tree(
+                context,
+                name.to_vec(),
+                None,
+                None,
+                None,
+                guard,
+            )?),
+            Err(Error::CollectionNotFound(_)) => None,
+            Err(other) => return Err(other),
+        };
+
+        Ok(LazyTree { context: context.clone(), name, tree: RwLock::new(tree) })
+    }
+
+    pub(crate) fn tree(&self) -> Option<Tree> {
+        self.tree.read().clone()
+    }
+
+    pub(crate) fn get_or_create(&self, guard: &Guard) -> Result<Tree> {
+        if let Some(tree) = self.tree() {
+            return Ok(tree);
+        }
+
+        let mut slot = self.tree.write();
+        if let Some(tree) = &*slot {
+            return Ok(tree.clone());
+        }
+
+        let created = open_tree(
+            &self.context,
+            self.name.to_vec(),
+            None,
+            None,
+            None,
+            guard,
+        )?;
+        *slot = Some(created.clone());
+        Ok(created)
+    }
+}
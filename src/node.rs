@@ -9,7 +9,7 @@ use std::{
     fmt,
     mem::{align_of, size_of},
     num::{NonZeroU16, NonZeroU64},
-    ops::{Bound, Deref, DerefMut},
+    ops::{Bound, Deref, DerefMut, RangeBounds},
     sync::Arc,
 };
 
@@ -565,6 +565,12 @@ impl Deref for Node {
 }
 
 impl Node {
+    /// Cheaply clones this node without consolidating its
+    /// overlay, sharing the backing `Inner` with the original.
+    pub(crate) fn shallow_clone(&self) -> Node {
+        Node { overlay: self.overlay.clone(), inner: self.inner.clone() }
+    }
+
     fn iter(&self) -> Iter<'_> {
         Iter {
             overlay: self.overlay.iter().skip(0),
@@ -678,6 +684,28 @@ impl Node {
         ret
     }
 
+    /// Tombstones every key in this node that falls within
+    /// the provided range, returning the resulting node along
+    /// with the decoded keys that were removed.
+    pub(crate) fn remove_range(
+        &self,
+        range: &(Bound<IVec>, Bound<IVec>),
+    ) -> (Node, Vec<IVec>) {
+        let mut overlay = self.overlay.clone();
+        let mut removed = vec![];
+
+        for (k, _) in self.iter() {
+            let decoded = self.prefix_decode(k);
+            if range.contains(&decoded) {
+                let encoded = IVec::from(self.prefix_encode(&decoded));
+                overlay.insert(encoded, None);
+                removed.push(decoded);
+            }
+        }
+
+        (Node { overlay, inner: self.inner.clone() }, removed)
+    }
+
     fn contains_key(&self, key: &[u8]) -> bool {
         if key < self.lo()
             || if let Some(hi) = self.hi() { key >= hi } else { false }
@@ -5,9 +5,13 @@ use std::{
     fmt::{self, Debug},
     fs::File,
     mem::{transmute, MaybeUninit},
+    ops::Range,
     path::Path,
     sync::{
-        atomic::{AtomicU32, Ordering::Acquire},
+        atomic::{
+            AtomicBool, AtomicU32,
+            Ordering::{Acquire, SeqCst},
+        },
         Arc,
     },
 };
@@ -16,7 +20,7 @@ use crate::{
     ebr::pin,
     pagecache::{pread_exact, pwrite_all, MessageKind},
     stack::Stack,
-    Error, Lsn, Result,
+    Error, Lsn, Mutex, Result,
 };
 
 #[cfg(not(feature = "testing"))]
@@ -90,6 +94,38 @@ fn slab_id_to_size(slab_id: u8) -> u64 {
     1 << (MIN_TRAILING_ZEROS + u64::from(slab_id))
 }
 
+/// Returns the byte ranges of the slots in the first `len` bytes
+/// of a slab file that may have been written at or after the
+/// provided `Lsn`. Slots that were completely written before it
+/// store a lower original LSN in their header, and are skipped.
+pub(crate) fn slots_written_since(
+    file: &File,
+    slab_id: SlabId,
+    len: u64,
+    lsn: Lsn,
+) -> Result<Vec<Range<u64>>> {
+    let bs = slab_id_to_size(slab_id);
+    let mut ranges: Vec<Range<u64>> = vec![];
+
+    for idx in 0..len / bs {
+        let offset = idx * bs;
+        let mut header = [0; 13];
+        pread_exact(file, &mut header, offset)?;
+        let slot_lsn = Lsn::from_le_bytes(header[5..13].try_into().unwrap());
+
+        if slot_lsn < lsn {
+            continue;
+        }
+
+        match ranges.last_mut() {
+            Some(last) if last.end == offset => last.end += bs,
+            _ => ranges.push(offset..offset + bs),
+        }
+    }
+
+    Ok(ranges)
+}
+
 fn size_to_slab_id(size: u64) -> SlabId {
     // find the power of 2 that is at least 64k
     let normalized_size = std::cmp::max(MIN_SZ, size.next_power_of_two());
@@ -182,18 +218,27 @@ pub(crate) struct Heap {
     // smallest slab to 2^48 in
     // the last.
     slabs: [Slab; 32],
+    // slots that were freed while frees were paused
+    paused_frees: Mutex<Option<Vec<HeapId>>>,
+    // set while frees are paused, so that reservations can check
+    // it without taking the lock
+    frees_paused: AtomicBool,
 }
 
 impl Heap {
-    pub fn start<P: AsRef<Path>>(p: P) -> Result<Heap> {
+    pub fn start<P: AsRef<Path>>(p: P, read_only: bool) -> Result<Heap> {
         let mut slabs: [MaybeUninit<Slab>; 32] = unsafe { std::mem::zeroed() };
 
         for slab_id in 0..32 {
-            let slab = Slab::start(&p, slab_id)?;
+            let slab = Slab::start(&p, slab_id, read_only)?;
             slabs[slab_id as usize] = MaybeUninit::new(slab);
         }
 
-        Ok(Heap { slabs: unsafe { transmute(slabs) } })
+        Ok(Heap {
+            slabs: unsafe { transmute(slabs) },
+            paused_frees: Mutex::new(None),
+            frees_paused: AtomicBool::new(false),
+        })
     }
 
     pub fn gc_unknown_items(&self, snapshot: &crate::pagecache::Snapshot) {
@@ -249,14 +294,41 @@ impl Heap {
 
     pub fn free(&self, heap_id: HeapId) {
         log::trace!("Heap::free({:?})", heap_id);
+        if let Some(paused_frees) = &mut *self.paused_frees.lock() {
+            paused_frees.push(heap_id);
+            return;
+        }
         let (slab_id, slab_idx, _) = heap_id.decompose();
         self.slabs[slab_id as usize].free(slab_idx)
     }
 
+    /// Prevents free slots from being reused or punched out
+    /// of their slab files until `resume_frees` is called, so
+    /// that new slots are only reserved from the tip of each slab.
+    pub fn pause_frees(&self) {
+        let mut paused_frees = self.paused_frees.lock();
+        assert!(paused_frees.is_none(), "heap frees already paused");
+        *paused_frees = Some(vec![]);
+        self.frees_paused.store(true, SeqCst);
+    }
+
+    /// Frees every slot that was freed while frees were paused.
+    pub fn resume_frees(&self) {
+        let paused_frees = {
+            let mut paused_frees = self.paused_frees.lock();
+            self.frees_paused.store(false, SeqCst);
+            paused_frees.take().unwrap_or_default()
+        };
+        for heap_id in paused_frees {
+            self.free(heap_id);
+        }
+    }
+
     pub fn reserve(&self, size: u64, original_lsn: Lsn) -> Reservation {
         assert!(size < 1 << 48);
         let slab_id = size_to_slab_id(size);
-        let ret = self.slabs[slab_id as usize].reserve(original_lsn);
+        let ret = self.slabs[slab_id as usize]
+            .reserve(original_lsn, &self.frees_paused);
         log::trace!("Heap::reserve({}) -> {:?}", size, ret.heap_id);
         ret
     }
@@ -271,14 +343,18 @@ struct Slab {
 }
 
 impl Slab {
-    pub fn start<P: AsRef<Path>>(directory: P, slab_id: u8) -> Result<Slab> {
+    pub fn start<P: AsRef<Path>>(
+        directory: P,
+        slab_id: u8,
+        read_only: bool,
+    ) -> Result<Slab> {
         let bs = slab_id_to_size(slab_id);
         let free = Arc::new(Stack::default());
 
         let mut options = std::fs::OpenOptions::new();
-        options.create(true);
+        options.create(!read_only);
         options.read(true);
-        options.write(true);
+        options.write(!read_only);
 
         let file =
             options.open(directory.as_ref().join(format!("{:02}", slab_id)))?;
@@ -347,8 +423,27 @@ impl Slab {
         }
     }
 
-    fn reserve(&self, original_lsn: Lsn) -> Reservation {
-        let (idx, from_tip) = if let Some(idx) = self.free.pop(&pin()) {
+    fn reserve(
+        &self,
+        original_lsn: Lsn,
+        frees_paused: &AtomicBool,
+    ) -> Reservation {
+        let candidate = if frees_paused.load(SeqCst) {
+            None
+        } else {
+            self.free.pop(&pin())
+        };
+        // frees may have been paused after the first check, in which
+        // case the slot is returned so that no slot that was already
+        // free is handed out once `pause_frees` has returned
+        let popped = match candidate {
+            Some(idx) if frees_paused.load(SeqCst) => {
+                self.free.push(idx, &pin());
+                None
+            }
+            other => other,
+        };
+        let (idx, from_tip) = if let Some(idx) = popped {
             log::trace!(
                 "reusing heap index {} in slab for sizes of {}",
                 idx,
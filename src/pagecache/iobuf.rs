@@ -4,7 +4,10 @@ use std::{
     sync::atomic::AtomicPtr,
 };
 
-use crate::{pagecache::*, *};
+use crate::{
+    pagecache::{Snapshot, *},
+    *,
+};
 
 macro_rules! io_fail {
     ($self:expr, $e:expr) => {
@@ -253,16 +256,11 @@ impl StabilityIntervals {
             if high <= self.stable_lsn {
                 // the entire batch has been written to disk
                 // and fsynced, so we can propagate its stability
-                // through the `batch_stable_lsn` variable.
-                if let Some(bsl) = batch_stable_lsn {
-                    assert!(
-                        bsl < high,
-                        "expected batch stable lsn of {} to be less than high of {}",
-                        bsl,
-                        high
-                    );
-                }
-                batch_stable_lsn = Some(high);
+                // through the `batch_stable_lsn` variable. single
+                // writes that pin the log only hold the shared
+                // concurrency control lock, so their batches may
+                // overlap, and one may end before the last one.
+                batch_stable_lsn = batch_stable_lsn.max(Some(high));
                 self.batches.remove(&low).unwrap();
             } else {
                 if low <= self.stable_lsn {
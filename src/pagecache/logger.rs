@@ -168,6 +168,8 @@ impl Log {
         #[cfg(feature = "metrics")]
         let _measure = Measure::new(&M.reserve_lat);
 
+        self.config.verify_writable()?;
+
         let serialized_len = item.serialized_size();
         let max_buf_len =
             u64::try_from(MAX_MSG_HEADER_LEN).unwrap() + serialized_len;
@@ -500,7 +502,8 @@ impl Log {
 impl Drop for Log {
     fn drop(&mut self) {
         // don't do any more IO if we're crashing
-        if self.config.global_error().is_err() {
+        // or if we never wrote anything
+        if self.config.global_error().is_err() || self.config.read_only {
             return;
         }
 
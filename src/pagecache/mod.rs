@@ -20,6 +20,7 @@ mod parallel_io_windows;
 mod reservation;
 mod segment;
 mod snapshot;
+mod versions;
 
 use std::ops::Deref;
 
@@ -44,16 +45,18 @@ use self::{
     iterator::{raw_segment_iter_from, LogIter},
     pagetable::PageTable,
     segment::{SegmentAccountant, SegmentCleaner, SegmentOp},
+    versions::Versions,
 };
 
 pub(crate) use self::{
-    heap::{Heap, HeapId},
+    heap::{slots_written_since, Heap, HeapId},
     logger::{
         read_message, read_segment_header, MessageHeader, SegmentHeader,
         SegmentNumber,
     },
     reservation::Reservation,
     snapshot::{read_snapshot_or_default, PageState, Snapshot},
+    versions::PageVersions,
 };
 
 pub use self::{
@@ -461,7 +464,7 @@ impl Page {
         }
     }
 
-    fn last_lsn(&self) -> Lsn {
+    pub(crate) fn last_lsn(&self) -> Lsn {
         self.cache_infos.last().map(|ci| ci.lsn).unwrap()
     }
 
@@ -524,6 +527,9 @@ pub struct PageCacheInner {
     snapshot_min_lsn: AtomicLsn,
     links: AtomicU64,
     snapshot_lock: Mutex<()>,
+
+    // superseded page versions pinned by `Tree` snapshots
+    versions: Versions,
 }
 
 impl Debug for PageCache {
@@ -581,10 +587,14 @@ impl PageCache {
         // snapshot before loading it.
         let snapshot = read_snapshot_or_default(&config)?;
 
-        config.heap.gc_unknown_items(&snapshot);
+        if !config.read_only {
+            config.heap.gc_unknown_items(&snapshot);
+        }
 
+        // a concurrent writer may change the files between two
+        // recoveries in read-only mode
         #[cfg(feature = "testing")]
-        {
+        if !config.read_only {
             // these checks are in place to catch non-idempotent
             // recovery which could trigger feedback loops and
             // emergent behavior.
@@ -657,6 +667,7 @@ impl PageCache {
             snapshot_min_lsn: AtomicLsn::new(snapshot.stable_lsn.unwrap_or(0)),
             links: AtomicU64::new(0),
             snapshot_lock: Mutex::new(()),
+            versions: Versions::default(),
         };
 
         // now we read it back in
@@ -683,6 +694,17 @@ impl PageCache {
         let mut was_recovered = true;
 
         let guard = pin();
+        if pc.config.read_only
+            && !(pc.inner.contains_pid(META_PID, &guard)
+                && pc.inner.contains_pid(COUNTER_PID, &guard))
+        {
+            return Err(Error::Unsupported(
+                "cannot open a database that has not been \
+                 initialized yet in read-only mode"
+                    .into(),
+            ));
+        }
+
         if !pc.inner.contains_pid(META_PID, &guard) {
             // set up meta
             was_recovered = false;
@@ -725,7 +747,9 @@ impl PageCache {
         pc.idgen.store(idgen_recovery, Release);
         pc.idgen_persists.store(idgen_persists, Release);
 
-        if was_recovered {
+        if pc.config.read_only {
+            // IDs are never generated in read-only mode
+        } else if was_recovered {
             // advance pc.idgen_persists and the counter page by one
             // interval, so that when generate_id() is next called, it
             // will advance them further by another interval, and wait for
@@ -823,6 +847,8 @@ impl PageCache {
             return Ok(short_circuit.map_err(|a| a.map(|b| (b.0, new))));
         }
 
+        self.versions.preserve(pid, &old);
+
         log::trace!(
             "applying link of {:?} to pid {:?} resulted in node {:?}",
             new,
@@ -950,6 +976,25 @@ impl PageCache {
         }
     }
 
+    /// Pins the current version of every page for a `Tree`
+    /// snapshot, returning the versions that superseded
+    /// pages will be preserved in along with the highest
+    /// LSN reserved so far. Must be called while no other
+    /// thread can modify pages, and the snapshot stops
+    /// pinning pages once the returned versions are dropped.
+    pub(crate) fn pin_versions(&self) -> (Arc<PageVersions>, Lsn) {
+        let versions = self.versions.register();
+        let lsn = self.log.iobufs.max_reserved_lsn.load(Acquire);
+        (versions, lsn)
+    }
+
+    /// Returns the highest LSN reserved so far. Every write
+    /// that reserves space in the log after this is called
+    /// is assigned a higher LSN.
+    pub(crate) fn max_reserved_lsn(&self) -> Lsn {
+        self.log.iobufs.max_reserved_lsn.load(Acquire)
+    }
+
     pub(crate) fn take_fuzzy_snapshot(self) -> Result<()> {
         #[cfg(feature = "metrics")]
         let _measure = Measure::new(&M.fuzzy_snapshot);
@@ -1021,13 +1066,71 @@ impl PageCache {
     }
 }
 
+/// Keeps segment and heap frees paused until it is resumed or
+/// dropped, so that they are resumed even if the code running
+/// while they are paused panics.
+struct PausedReclamation<'a> {
+    pagecache: &'a PageCacheInner,
+    resumed: bool,
+}
+
+impl<'a> PausedReclamation<'a> {
+    fn new(pagecache: &'a PageCacheInner) -> PausedReclamation<'a> {
+        pagecache.log.iobufs.with_sa(SegmentAccountant::pause_frees);
+        pagecache.config.heap.pause_frees();
+        PausedReclamation { pagecache, resumed: false }
+    }
+
+    fn resume(mut self) -> Result<()> {
+        self.resumed = true;
+        self.pagecache.config.heap.resume_frees();
+        self.pagecache.log.iobufs.with_sa(SegmentAccountant::resume_frees)
+    }
+}
+
+impl Drop for PausedReclamation<'_> {
+    fn drop(&mut self) {
+        if self.resumed {
+            return;
+        }
+        self.pagecache.config.heap.resume_frees();
+        if let Err(e) =
+            self.pagecache.log.iobufs.with_sa(SegmentAccountant::resume_frees)
+        {
+            error!("failed to resume segment frees: {:?}", e);
+        }
+    }
+}
+
 impl PageCacheInner {
     /// Flushes any pending IO buffers to disk to ensure durability.
     /// Returns the number of bytes written during this call.
     pub(crate) fn flush(&self) -> Result<usize> {
+        if self.config.read_only {
+            return Ok(0);
+        }
         self.log.flush()
     }
 
+    /// Runs the provided closure while no log segment or heap slot
+    /// may be reused and no new snapshot may be written, so that the
+    /// files of the database only change by having new data written
+    /// into unused space. This allows them to be copied while the
+    /// database remains in use.
+    pub(crate) fn with_paused_reclamation<B, F>(&self, f: F) -> Result<B>
+    where
+        F: FnOnce() -> Result<B>,
+    {
+        let _snapshot_lock = self.snapshot_lock.lock();
+        let paused = PausedReclamation::new(self);
+
+        let ret = f();
+
+        paused.resume()?;
+
+        ret
+    }
+
     /// Create a new page, trying to reuse old freed pages if possible
     /// to maximize underlying `PageTable` pointer density. Returns
     /// the page ID and its pointer for use in future atomic `replace`
@@ -1163,6 +1266,7 @@ impl PageCacheInner {
         // transactions with a begin and end message, rather
         // than a single beginning message that needs to
         // be held until we know the final batch LSN.
+        self.config.verify_writable()?;
         self.log.roll_iobuf()?;
 
         let batch_res = self.log.reserve(
@@ -1598,6 +1702,10 @@ impl PageCacheInner {
         let log_kind = log_kind_from_update(&update);
         trace!("cas_page on pid {} has log kind: {:?}", pid, log_kind);
 
+        if !is_rewrite {
+            self.versions.preserve(pid, &old);
+        }
+
         let mut new_page = Some(Owned::new(Page {
             update: Some(update),
             cache_infos: Vec::default(),
@@ -1919,6 +2027,8 @@ impl PageCacheInner {
     /// a blocking flush to fsync the latest counter, ensuring
     /// that we will never give out the same counter twice.
     pub(crate) fn generate_id_inner(&self) -> Result<u64> {
+        self.config.verify_writable()?;
+
         let ret = self.idgen.fetch_add(1, Release);
 
         trace!("generating ID {}", ret);
@@ -2039,6 +2149,48 @@ impl PageCacheInner {
         }
     }
 
+    /// Set the name of the merge operator in the `Meta`
+    /// mapping for a given identifier.
+    pub(crate) fn set_merge_operator_in_meta(
+        &self,
+        name: &[u8],
+        merge_operator: &[u8],
+        guard: &Guard,
+    ) -> Result<()> {
+        loop {
+            let meta_view = self.get_meta(guard);
+
+            if meta_view.get_root(name).is_none() {
+                return Err(Error::CollectionNotFound(name.into()));
+            }
+
+            let mut new_meta = meta_view.deref().clone();
+            new_meta.set_merge_operator(name.into(), merge_operator.into());
+
+            let new_meta_link = Update::Meta(new_meta);
+
+            let res = self.cas_page(
+                META_PID,
+                meta_view.0,
+                new_meta_link,
+                false,
+                guard,
+            )?;
+
+            match res {
+                Ok(_worked) => return Ok(()),
+                Err(Some((_current_pointer, _rejected))) => {}
+                Err(None) => {
+                    return Err(Error::ReportableBug(
+                        "replacing the META page has failed because \
+                         the pagecache does not think it currently exists."
+                            .into(),
+                    ));
+                }
+            }
+        }
+    }
+
     fn page_out(&self, to_evict: Vec<PageId>, guard: &Guard) -> Result<()> {
         #[cfg(feature = "metrics")]
         let _measure = Measure::new(&M.page_out);
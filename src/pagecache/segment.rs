@@ -58,7 +58,7 @@
 
 use std::{collections::BTreeSet, mem};
 
-use super::PageState;
+use super::{PageState, Snapshot};
 
 use crate::pagecache::*;
 use crate::*;
@@ -95,6 +95,8 @@ pub(crate) struct SegmentAccountant {
     segment_cleaner: SegmentCleaner,
     ordering: BTreeMap<Lsn, LogOffset>,
     async_truncations: BTreeMap<LogOffset, OneShot<Result<()>>>,
+    // segments that became free while frees were paused
+    paused_frees: Option<Vec<LogOffset>>,
 }
 
 #[derive(Debug, Clone, Default)]
@@ -488,6 +490,7 @@ impl SegmentAccountant {
             segment_cleaner,
             ordering: BTreeMap::default(),
             async_truncations: BTreeMap::default(),
+            paused_frees: None,
         };
 
         ret.initialize_from_snapshot(snapshot)?;
@@ -671,6 +674,9 @@ impl SegmentAccountant {
 
         for segment_base in to_free {
             self.free_segment(segment_base)?;
+            if self.config.read_only {
+                continue;
+            }
             io_fail!(self.config, "zero garbage segment SA");
             pwrite_all(
                 &self.config.file,
@@ -725,6 +731,12 @@ impl SegmentAccountant {
         assert!(self.segments[idx].is_free());
         assert!(!self.free.contains(&lid), "double-free of a segment occurred");
 
+        if let Some(paused_frees) = &mut self.paused_frees {
+            trace!("deferring free of segment {} until frees resume", lid);
+            paused_frees.push(lid);
+            return Ok(());
+        }
+
         self.free.insert(lid);
 
         // remove the old ordering from our list
@@ -757,6 +769,24 @@ impl SegmentAccountant {
         Ok(())
     }
 
+    /// Prevents free segments from being reused or truncated
+    /// until `resume_frees` is called, so that new segments are
+    /// only allocated from the tip of the file and the contents
+    /// of every existing segment stay in place.
+    pub(super) fn pause_frees(&mut self) {
+        assert!(self.paused_frees.is_none(), "segment frees already paused");
+        self.paused_frees = Some(vec![]);
+    }
+
+    /// Frees every segment that became free while frees were paused.
+    pub(super) fn resume_frees(&mut self) -> Result<()> {
+        let paused_frees = self.paused_frees.take().unwrap_or_default();
+        for lid in paused_frees {
+            self.free_segment(lid)?;
+        }
+        Ok(())
+    }
+
     /// Asynchronously apply a GC-related operation. Used in a flat-combining
     /// style that allows callers to avoid blocking while sending these
     /// messages to this module.
@@ -1115,8 +1145,14 @@ impl SegmentAccountant {
 
         trace!("evaluating free list {:?} in SA::next", &self.free);
 
-        // pop free or add to end
-        let safe = self.free.iter().next().copied();
+        // pop free or add to end. while frees are paused, segments
+        // that were already free are not reused either, so that
+        // the contents of the file only change past its tip.
+        let safe = if self.paused_frees.is_some() {
+            None
+        } else {
+            self.free.iter().next().copied()
+        };
 
         let (lid, from_tip) = if let Some(next) = safe {
             self.free.remove(&next);
@@ -1193,6 +1229,10 @@ impl SegmentAccountant {
 
         assert!(!self.free.contains(&at), "double-free of a segment occurred");
 
+        if self.config.read_only {
+            return Ok(());
+        }
+
         let config = self.config.clone();
 
         io_fail!(&config, "file truncation");
@@ -357,7 +357,10 @@ fn advance_snapshot(
             trace!("bumping snapshot.stable_lsn to {}", bumped);
             (bumped, None)
         } else {
-            if let Some(BasedBuf { offset, .. }) = iter.segment_base {
+            if config.read_only {
+                // the tail is left alone, because nothing will
+                // be written after the recovered tip
+            } else if let Some(BasedBuf { offset, .. }) = iter.segment_base {
                 // either situation 3 or situation 4. we need to zero the
                 // tail of the segment after the recovered tip
                 let shred_len = config.segment_size
@@ -408,6 +411,15 @@ fn advance_snapshot(
         return Err(Error::corruption(None));
     }
 
+    if config.read_only {
+        // torn segments are left alone as well, and will be
+        // skipped again the next time the log is recovered
+        #[cfg(feature = "event_log")]
+        config.event_log.recovered_lsn(snapshot.stable_lsn.unwrap_or(0));
+
+        return Ok(snapshot);
+    }
+
     if snapshot.stable_lsn > old_stable_lsn {
         write_snapshot(config, &snapshot)?;
     }
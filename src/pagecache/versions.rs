@@ -0,0 +1,84 @@
+//! Keeps the versions of pages that are superseded while
+//! a snapshot is alive, so that the snapshot can keep
+//! reading the tree exactly as it was when it was taken.
+
+use std::sync::{atomic::AtomicBool, Weak};
+
+use super::{PageId, PageView, Update};
+use crate::*;
+
+/// The versions of pages that a snapshot pins: for every
+/// page that has been rewritten since the snapshot was
+/// taken, the node it held at that time. Pages that are
+/// missing here are still unchanged in the pagecache.
+#[derive(Default)]
+pub(crate) struct PageVersions {
+    pages: Mutex<FastMap8<PageId, Node>>,
+}
+
+impl PageVersions {
+    /// Returns the node this page held when the snapshot
+    /// was taken, if it has been rewritten since then.
+    pub(crate) fn get(&self, pid: PageId) -> Option<Node> {
+        self.pages.lock().get(&pid).map(Node::shallow_clone)
+    }
+
+    fn preserve(&self, pid: PageId, node: &Node) {
+        // only the first superseded version is the one that
+        // was current when the snapshot was taken
+        self.pages.lock().entry(pid).or_insert_with(|| node.shallow_clone());
+    }
+}
+
+/// The registry of live `PageVersions`, which every
+/// page-rewriting operation consults before it installs
+/// a new version of a page.
+#[derive(Default)]
+pub(in crate::pagecache) struct Versions {
+    // set while at least one snapshot may be alive,
+    // so that writes skip the lock when there are none
+    active: AtomicBool,
+    registered: Mutex<Vec<Weak<PageVersions>>>,
+}
+
+impl Versions {
+    pub(in crate::pagecache) fn register(&self) -> Arc<PageVersions> {
+        let versions = Arc::new(PageVersions::default());
+        let mut registered = self.registered.lock();
+        registered.push(Arc::downgrade(&versions));
+        self.active.store(true, SeqCst);
+        versions
+    }
+
+    /// Must be called with the current version of a page
+    /// before any attempt to install a new one.
+    pub(in crate::pagecache) fn preserve(
+        &self,
+        pid: PageId,
+        old: &PageView<'_>,
+    ) {
+        if !self.active.load(SeqCst) {
+            return;
+        }
+
+        let node = if let Some(Update::Node(ref node)) = old.update {
+            node
+        } else {
+            return;
+        };
+
+        let mut registered = self.registered.lock();
+        registered.retain(|weak| {
+            if let Some(versions) = weak.upgrade() {
+                versions.preserve(pid, node);
+                true
+            } else {
+                false
+            }
+        });
+
+        if registered.is_empty() {
+            self.active.store(false, SeqCst);
+        }
+    }
+}
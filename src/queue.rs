@@ -0,0 +1,281 @@
+use std::{
+    sync::{atomic::Ordering::Relaxed, mpsc::RecvTimeoutError},
+    time::{Duration, Instant},
+};
+
+use parking_lot::Mutex;
+
+use crate::{atomic_shim::AtomicU64, *};
+
+// The items of a `Tree` that are leased by `Tree::lease_min_blocking`,
+// with the deadline of each lease and the id of the `Lease` that
+// holds it. Items that are being popped, or whose lease is being
+// completed, are claimed without a deadline until that is done, so
+// that nothing else takes them in the meantime. Leases are only kept
+// in memory, because the workers holding them cannot outlive the
+// process, so every leased item becomes visible again after a
+// restart.
+#[derive(Debug, Default)]
+pub(crate) struct Leases {
+    held: Mutex<Map<IVec, (Option<Instant>, u64)>>,
+    next_id: AtomicU64,
+}
+
+impl Leases {
+    // Returns the earliest deadline of the leases that have not
+    // expired yet, after which a leased item may become visible.
+    fn next_deadline(&self) -> Option<Instant> {
+        let now = Instant::now();
+        self.held
+            .lock()
+            .values()
+            .filter_map(|(deadline, _id)| *deadline)
+            .filter(|deadline| *deadline > now)
+            .min()
+    }
+
+    // Claims the key until `end` is called with the returned id,
+    // unless it is already held by a lease that has not expired.
+    fn claim(&self, key: &IVec) -> Option<u64> {
+        let mut held = self.held.lock();
+        let is_leased = match held.get(key) {
+            Some((None, _id)) => true,
+            Some((Some(deadline), _id)) => *deadline > Instant::now(),
+            None => false,
+        };
+        if is_leased {
+            return None;
+        }
+        let id = self.next_id.fetch_add(1, Relaxed);
+        let _ = held.insert(key.clone(), (None, id));
+        Some(id)
+    }
+
+    // Sets the deadline of the lease with the provided id, returning
+    // `false` if it no longer holds the key.
+    fn set_deadline(
+        &self,
+        key: &[u8],
+        id: u64,
+        deadline: Option<Instant>,
+    ) -> bool {
+        let mut held = self.held.lock();
+        match held.get_mut(key) {
+            Some((held_deadline, holder)) if *holder == id => {
+                *held_deadline = deadline;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    // Removes the lease with the provided id, unless it expired
+    // and the key was leased again.
+    fn end(&self, key: &[u8], id: u64) {
+        let mut held = self.held.lock();
+        if let Some((_deadline, holder)) = held.get(key) {
+            if *holder == id {
+                let _ = held.remove(key);
+            }
+        }
+    }
+}
+
+// Removes the minimum item under the prefix that is not leased.
+pub(crate) fn pop_min(
+    tree: &Tree,
+    prefix: &[u8],
+) -> Result<Option<(IVec, IVec)>> {
+    take_min(tree, prefix, |key, value, id| {
+        let removed =
+            tree.compare_and_swap::<_, _, &[u8]>(key, Some(value), None);
+        tree.leases.end(key, id);
+        Ok(if removed?.is_ok() {
+            Some((key.clone(), value.clone()))
+        } else {
+            None
+        })
+    })
+}
+
+// Leases the minimum item under the prefix that is not leased.
+pub(crate) fn lease_min(
+    tree: &Tree,
+    prefix: &[u8],
+    lease: Duration,
+) -> Result<Option<Lease>> {
+    take_min(tree, prefix, |key, value, id| {
+        let deadline = if let Some(deadline) = Instant::now().checked_add(lease)
+        {
+            deadline
+        } else {
+            tree.leases.end(key, id);
+            return Err(Error::Unsupported(
+                "the lease duration is too long".to_owned(),
+            ));
+        };
+
+        // the item may have changed after it was read and before
+        // it was claimed, in which case it is not leased
+        let current = tree.get(key);
+        let unchanged = match &current {
+            Ok(current_value) => current_value.as_ref() == Some(value),
+            Err(_) => false,
+        };
+        if !unchanged {
+            tree.leases.end(key, id);
+            return current.map(|_| None);
+        }
+        let _ = tree.leases.set_deadline(key, id, Some(deadline));
+
+        Ok(Some(Lease {
+            tree: tree.clone(),
+            key: key.clone(),
+            value: value.clone(),
+            id,
+            ended: false,
+        }))
+    })
+}
+
+// Claims the minimum item under the prefix that is not leased and
+// calls `take` on it, which must end the claim unless it turns it
+// into a lease. If the item changed before it could be taken, the
+// scan resumes from its key, so that its new value is taken instead
+// if it is still under the prefix.
+fn take_min<T, F>(tree: &Tree, prefix: &[u8], mut take: F) -> Result<Option<T>>
+where
+    F: FnMut(&IVec, &IVec, u64) -> Result<Option<T>>,
+{
+    let mut start = IVec::from(prefix);
+    'scan: loop {
+        for item_res in tree.range::<&[u8], _>(&start[..]..) {
+            let (key, value) = item_res?;
+            if !key.starts_with(prefix) {
+                break;
+            }
+            let id = if let Some(id) = tree.leases.claim(&key) {
+                id
+            } else {
+                continue;
+            };
+            if let Some(taken) = take(&key, &value, id)? {
+                return Ok(Some(taken));
+            }
+            start = key;
+            continue 'scan;
+        }
+        return Ok(None);
+    }
+}
+
+// Calls `try_take` until it returns an item, waiting on `watch_prefix`
+// in between for the prefix to be written to, until the timeout
+// passes or the `Db` shuts down.
+pub(crate) fn wait_for<T, F>(
+    tree: &Tree,
+    prefix: &[u8],
+    timeout: Duration,
+    mut try_take: F,
+) -> Result<Option<T>>
+where
+    F: FnMut() -> Result<Option<T>>,
+{
+    // subscribe before the first attempt, so that no write that
+    // happens after it is missed
+    let subscriber = tree.watch_prefix(prefix);
+    let deadline_opt = Instant::now().checked_add(timeout);
+
+    loop {
+        if let Some(taken) = try_take()? {
+            return Ok(Some(taken));
+        }
+
+        let now = Instant::now();
+        let mut wait = match deadline_opt {
+            Some(deadline) if deadline <= now => return Ok(None),
+            Some(deadline) => deadline - now,
+            None => timeout,
+        };
+
+        // leases expire without any write to the tree, so wake
+        // up in time to take an item whose lease expired
+        if let Some(lease_deadline) = tree.leases.next_deadline() {
+            wait = wait.min(lease_deadline.saturating_duration_since(now));
+        }
+
+        match subscriber.next_timeout(wait) {
+            Ok(_) | Err(RecvTimeoutError::Timeout) => {}
+            Err(RecvTimeoutError::Disconnected) => return try_take(),
+        }
+    }
+}
+
+/// An item that was leased from a `Tree` by
+/// `Tree::lease_min_blocking`, which is hidden from every other
+/// lease and from `Tree::pop_min_blocking` and `Tree::pop_min_async`
+/// until the lease is completed or released, or expires. Other
+/// reads and writes, including `Tree::pop_min` and `Tree::pop_max`,
+/// still see the item, and `Lease::complete` does not remove it if
+/// they changed it.
+///
+/// Dropping a `Lease` without completing it releases it, so that
+/// the item of a worker that panics becomes visible again at once.
+/// Leases only live in memory, so the items of a process that
+/// crashes are visible again as soon as the database is reopened.
+#[derive(Debug)]
+pub struct Lease {
+    tree: Tree,
+    key: IVec,
+    value: IVec,
+    id: u64,
+    ended: bool,
+}
+
+impl Lease {
+    /// The key of the leased item.
+    pub fn key(&self) -> &IVec {
+        &self.key
+    }
+
+    /// The value of the leased item.
+    pub fn value(&self) -> &IVec {
+        &self.value
+    }
+
+    /// Removes the leased item from the `Tree`, returning `false`
+    /// without removing it if the lease expired and the item was
+    /// leased again, or if the item was changed since it was leased.
+    pub fn complete(mut self) -> Result<bool> {
+        self.ended = true;
+        let leases = &self.tree.leases;
+
+        // the item stays claimed until it is removed, so that it is
+        // not leased again in the meantime even if the lease expires
+        if !leases.set_deadline(&self.key, self.id, None) {
+            return Ok(false);
+        }
+        let removed = self.tree.compare_and_swap::<_, _, &[u8]>(
+            &self.key,
+            Some(&self.value),
+            None,
+        );
+        leases.end(&self.key, self.id);
+        Ok(removed?.is_ok())
+    }
+
+    /// Makes the leased item visible again to every pop and lease
+    /// without removing it.
+    pub fn release(mut self) {
+        self.ended = true;
+        self.tree.leases.end(&self.key, self.id);
+    }
+}
+
+impl Drop for Lease {
+    fn drop(&mut self) {
+        if !self.ended {
+            self.tree.leases.end(&self.key, self.id);
+        }
+    }
+}
@@ -40,6 +40,20 @@ pub enum Error {
     Unsupported(String),
     /// An unexpected bug has happened. Please open an issue on github!
     ReportableBug(String),
+    /// A write would have stored two keys of a `Tree` under the
+    /// same key of one of its unique `Index`es, and was rejected.
+    UniqueViolation {
+        /// The name of the unique `Index`.
+        index: IVec,
+        /// The index key that was already taken.
+        key: IVec,
+    },
+    /// `Tree::increment` was called on the provided key, whose
+    /// value is not an 8-byte counter.
+    NotACounter(IVec),
+    /// Adding to the counter stored under the provided key with
+    /// `Tree::increment` would have overflowed an `i64`.
+    CounterOverflow(IVec),
     /// A read or write error has happened when interacting with the file
     /// system.
     Io(io::Error),
@@ -81,6 +95,11 @@ impl Clone for Error {
             CollectionNotFound(name) => CollectionNotFound(name.clone()),
             Unsupported(why) => Unsupported(why.clone()),
             ReportableBug(what) => ReportableBug(what.clone()),
+            UniqueViolation { index, key } => {
+                UniqueViolation { index: index.clone(), key: key.clone() }
+            }
+            NotACounter(key) => NotACounter(key.clone()),
+            CounterOverflow(key) => CounterOverflow(key.clone()),
             Corruption { at, bt } => Corruption { at: *at, bt: bt.clone() },
             #[cfg(feature = "failpoints")]
             FailPoint => FailPoint,
@@ -116,6 +135,27 @@ impl PartialEq for Error {
                     false
                 }
             }
+            UniqueViolation { index: ref li, key: ref lk } => {
+                if let UniqueViolation { index: ref ri, key: ref rk } = *other {
+                    li == ri && lk == rk
+                } else {
+                    false
+                }
+            }
+            NotACounter(ref l) => {
+                if let NotACounter(ref r) = *other {
+                    l == r
+                } else {
+                    false
+                }
+            }
+            CounterOverflow(ref l) => {
+                if let CounterOverflow(ref r) = *other {
+                    l == r
+                } else {
+                    false
+                }
+            }
             #[cfg(feature = "failpoints")]
             FailPoint => {
                 if let FailPoint = *other {
@@ -164,6 +204,21 @@ impl From<Error> for io::Error {
                     what
                 ),
             ),
+            UniqueViolation { index, key } => io::Error::new(
+                ErrorKind::AlreadyExists,
+                format!(
+                    "unique index {:?} already contains key {:?}",
+                    index, key
+                ),
+            ),
+            NotACounter(key) => io::Error::new(
+                ErrorKind::InvalidData,
+                format!("key {:?} does not hold an 8-byte counter", key),
+            ),
+            CounterOverflow(key) => io::Error::new(
+                ErrorKind::InvalidInput,
+                format!("incrementing the counter of key {:?} overflowed", key),
+            ),
             Corruption { .. } => io::Error::new(
                 ErrorKind::InvalidData,
                 format!("corruption encountered: {:?}", error),
@@ -194,6 +249,21 @@ impl Display for Error {
                  PLEASE REPORT THIS BUG!",
                 e
             ),
+            UniqueViolation { ref index, ref key } => write!(
+                f,
+                "Unique index {:?} already contains key {:?}",
+                index, key
+            ),
+            NotACounter(ref key) => {
+                write!(f, "Key {:?} does not hold an 8-byte counter", key)
+            }
+            CounterOverflow(ref key) => {
+                write!(
+                    f,
+                    "Incrementing the counter of key {:?} overflowed",
+                    key
+                )
+            }
             #[cfg(feature = "failpoints")]
             FailPoint => write!(f, "Fail point has been triggered."),
             Io(ref e) => write!(f, "IO error: {}", e),
@@ -1,5 +1,6 @@
 #![allow(clippy::mut_mut)]
 use std::{
+    collections::BTreeMap,
     convert::{TryFrom, TryInto},
     iter::FromIterator,
     marker::PhantomData,
@@ -296,18 +297,38 @@ impl Serialize for Meta {
             })
             .sum();
 
-        len_sz + items_sz
+        let merge_operators_len_sz: u64 =
+            (self.merge_operators.len() as u64).serialized_size();
+        let merge_operators_sz: u64 = self
+            .merge_operators
+            .iter()
+            .map(|(k, v)| k.serialized_size() + v.serialized_size())
+            .sum();
+
+        len_sz + items_sz + merge_operators_len_sz + merge_operators_sz
     }
 
     fn serialize_into(&self, buf: &mut &mut [u8]) {
         (self.inner.len() as u64).serialize_into(buf);
         serialize_2tuple_sequence(self.inner.iter(), buf);
+        (self.merge_operators.len() as u64).serialize_into(buf);
+        serialize_2tuple_sequence(self.merge_operators.iter(), buf);
     }
 
     fn deserialize(buf: &mut &[u8]) -> Result<Self> {
         let len = u64::deserialize(buf)?;
-        let meta = Meta { inner: deserialize_bounded_sequence(buf, len)? };
-        Ok(meta)
+        let inner = deserialize_bounded_sequence(buf, len)?;
+
+        // the names of merge operators were added after the
+        // roots, and are absent from metadata written before
+        let merge_operators = if buf.is_empty() {
+            BTreeMap::new()
+        } else {
+            let merge_operators_len = u64::deserialize(buf)?;
+            deserialize_bounded_sequence(buf, merge_operators_len)?
+        };
+
+        Ok(Meta { inner, merge_operators })
     }
 }
 
@@ -761,11 +782,18 @@ mod qc {
 
     impl Arbitrary for Meta {
         fn arbitrary<G: Gen>(g: &mut G) -> Meta {
-            Meta { inner: Arbitrary::arbitrary(g) }
+            Meta {
+                inner: Arbitrary::arbitrary(g),
+                merge_operators: Arbitrary::arbitrary(g),
+            }
         }
 
         fn shrink(&self) -> Box<dyn Iterator<Item = Meta>> {
-            Box::new(self.inner.shrink().map(|inner| Meta { inner }))
+            let merge_operators = self.merge_operators.clone();
+            Box::new(self.inner.shrink().map(move |inner| Meta {
+                inner,
+                merge_operators: merge_operators.clone(),
+            }))
         }
     }
 
@@ -0,0 +1,564 @@
+use std::ops::{Bound, Deref, RangeBounds};
+
+use crate::{
+    batch::ivec_bounds,
+    pagecache::PageVersions,
+    *,
+};
+
+/// A read-only, point-in-time view of every `Tree` in a `Db`,
+/// created with `Db::snapshot`. Implements
+/// `Deref<Target = sled::TreeSnapshot>` to refer to the
+/// default keyspace, just like `Db` does.
+///
+/// All trees in a `Snapshot` reflect the same instant, which
+/// is identified by the LSN returned from `Snapshot::lsn`:
+/// writes that happen after it was taken are never visible
+/// through it, no matter which tree they touch. A `Snapshot`
+/// reads unchanged pages straight from the page cache, and
+/// pages that are rewritten while it is alive have their
+/// previous version kept in memory until it is dropped, so
+/// long-lived snapshots of heavily modified trees will
+/// increase memory usage.
+#[derive(Clone)]
+pub struct Snapshot {
+    default: TreeSnapshot,
+    trees: Arc<Map<IVec, TreeSnapshot>>,
+    lsn: Lsn,
+}
+
+impl Deref for Snapshot {
+    type Target = TreeSnapshot;
+
+    fn deref(&self) -> &TreeSnapshot {
+        &self.default
+    }
+}
+
+impl Debug for Snapshot {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+    ) -> std::result::Result<(), fmt::Error> {
+        f.debug_struct("Snapshot")
+            .field("trees", &self.trees.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl Snapshot {
+    pub(crate) fn capture(
+        default_tree: &Tree,
+        trees: &[Tree],
+    ) -> Result<Snapshot> {
+        // writers are only excluded while the snapshot registers
+        // itself with the page cache and records the root of
+        // every tree, which does not depend on their size.
+        let _cc = concurrency_control::write();
+        let (versions, lsn) = default_tree.context.pagecache.pin_versions();
+
+        let mut expiry = None;
+        let default =
+            TreeSnapshot::pinned(default_tree, &versions, &mut expiry)?;
+
+        let mut snapshots = Map::default();
+        for tree in trees {
+            let snapshot = TreeSnapshot::pinned(tree, &versions, &mut expiry)?;
+            snapshots.insert(tree.tree_id.clone(), snapshot);
+        }
+
+        Ok(Snapshot { default, trees: Arc::new(snapshots), lsn })
+    }
+
+    /// Returns the highest log sequence number that had been
+    /// reserved when this `Snapshot` was taken. Every write
+    /// visible through it was logged at or below this LSN.
+    pub fn lsn(&self) -> Lsn {
+        self.lsn
+    }
+
+    /// Returns the snapshot of the `Tree` with the provided name,
+    /// as it was when this `Snapshot` was taken.
+    ///
+    /// Returns `Error::CollectionNotFound` if no such tree
+    /// had been opened at that time.
+    pub fn open_tree<V: AsRef<[u8]>>(&self, name: V) -> Result<TreeSnapshot> {
+        self.trees
+            .get(name.as_ref())
+            .cloned()
+            .ok_or_else(|| Error::CollectionNotFound(name.as_ref().into()))
+    }
+
+    /// Returns the names of the trees captured in this `Snapshot`.
+    pub fn tree_names(&self) -> Vec<IVec> {
+        self.trees.keys().cloned().collect()
+    }
+}
+
+/// A read-only, point-in-time view of a single `Tree`,
+/// created with `Tree::snapshot` or `Snapshot::open_tree`.
+/// Keys inserted with `Tree::insert_with_ttl` whose deadline
+/// had passed when it was taken are never returned.
+#[derive(Clone)]
+pub struct TreeSnapshot {
+    name: IVec,
+    root: PageId,
+    // the versions of pages that were rewritten after the
+    // snapshot was taken, as they were when it was taken
+    versions: Arc<PageVersions>,
+    context: Context,
+    // the deadlines of the keys of this tree, if they are hidden
+    // once they pass
+    expiry: Option<Arc<Expiry>>,
+}
+
+// The deadlines of keys inserted with a time-to-live, captured at
+// the same instant as the `TreeSnapshot` that they apply to, along
+// with the time that they are compared to.
+struct Expiry {
+    deadlines: TreeSnapshot,
+    // milliseconds since the unix epoch
+    now: u64,
+}
+
+impl Debug for TreeSnapshot {
+    fn fmt(
+        &self,
+        f: &mut fmt::Formatter<'_>,
+    ) -> std::result::Result<(), fmt::Error> {
+        f.debug_struct("TreeSnapshot")
+            .field("name", &self.name)
+            .field("root", &self.root)
+            .finish()
+    }
+}
+
+impl TreeSnapshot {
+    pub(crate) fn capture(tree: &Tree) -> Result<TreeSnapshot> {
+        let _cc = concurrency_control::write();
+        let (versions, _lsn) = tree.context.pagecache.pin_versions();
+        TreeSnapshot::pinned(tree, &versions, &mut None)
+    }
+
+    // Captures every tree at the same instant, which is what
+    // `Transactional::read_transaction` reads from. Keys whose
+    // deadline had passed at that instant are not returned.
+    pub(crate) fn capture_all(trees: &[&Tree]) -> Result<Vec<TreeSnapshot>> {
+        let first = if let Some(first) = trees.first() {
+            first
+        } else {
+            return Ok(vec![]);
+        };
+        // the exclusive lock is needed even though nothing is
+        // written. Single-key writes only hold the shared one, and
+        // one that is installing a page while the versions are
+        // registered may have already skipped preserving it, or
+        // split a node into a page that is never preserved at all.
+        // They may also write a deadline, index entries or a change
+        // feed record to another tree, and the trees could be
+        // captured with only one of those writes.
+        let _cc = concurrency_control::write();
+        let (versions, _lsn) = first.context.pagecache.pin_versions();
+
+        let mut expiry = None;
+        trees
+            .iter()
+            .map(|tree| TreeSnapshot::pinned(tree, &versions, &mut expiry))
+            .collect()
+    }
+
+    // Must be called while holding the exclusive concurrency
+    // control lock, so that the root and every page below it
+    // are either unchanged or preserved in `versions`. The
+    // deadlines of a tree with a time-to-live are captured into
+    // `expiry` the first time one is pinned, so that every tree
+    // pinned with the same `expiry` hides keys as of one instant.
+    fn pinned(
+        tree: &Tree,
+        versions: &Arc<PageVersions>,
+        expiry: &mut Option<Arc<Expiry>>,
+    ) -> Result<TreeSnapshot> {
+        let root = tree.root.load(Acquire);
+        if root == u64::max_value() {
+            // this collection has been explicitly removed
+            return Err(Error::CollectionNotFound(tree.tree_id.clone()));
+        }
+
+        if expiry.is_none() && tree.has_ttl.load(SeqCst) {
+            if let Some(expiry_tree) = tree.expiry_tree() {
+                // the expiry tree never has a time-to-live itself
+                let deadlines =
+                    TreeSnapshot::pinned(&expiry_tree, versions, &mut None)?;
+                *expiry = Some(Arc::new(Expiry { deadlines, now: ttl::now() }));
+            }
+        }
+
+        Ok(TreeSnapshot {
+            name: tree.tree_id.clone(),
+            root,
+            versions: versions.clone(),
+            context: tree.context.clone(),
+            expiry: if tree.has_ttl.load(SeqCst) {
+                expiry.clone()
+            } else {
+                None
+            },
+        })
+    }
+
+    // Returns `true` if the key has a deadline that had passed
+    // when the snapshot was taken.
+    fn is_expired(&self, key: &[u8]) -> Result<bool> {
+        let expiry = if let Some(expiry) = &self.expiry {
+            expiry
+        } else {
+            return Ok(false);
+        };
+        let expiry_key = ttl::expiry_key(&self.name, key);
+        match expiry.deadlines.get(&expiry_key)? {
+            Some(deadline) => ttl::is_expired_at(&deadline, expiry.now),
+            None => Ok(false),
+        }
+    }
+
+    // Returns the node that the page held when the snapshot
+    // was taken.
+    fn node_for_pid(&self, pid: PageId, guard: &Guard) -> Result<Node> {
+        if let Some(node) = self.versions.get(pid) {
+            return Ok(node);
+        }
+
+        let current = self.context.pagecache.get(pid, guard)?;
+
+        // a page's pinned version is always preserved before a
+        // new one is installed, so if the page was rewritten
+        // since the first check, its pinned version is here now.
+        if let Some(node) = self.versions.get(pid) {
+            return Ok(node);
+        }
+
+        if let Some(view) = current {
+            Ok(view.shallow_clone())
+        } else {
+            Err(Error::corruption(None))
+        }
+    }
+
+    // Returns the leaf that holds the greatest keys within
+    // `bound`, which for an included bound is the leaf whose
+    // range contains it.
+    fn leaf_for(&self, bound: &Bound<IVec>, guard: &Guard) -> Result<Node> {
+        let mut cursor = self.root;
+
+        loop {
+            let node = self.node_for_pid(cursor, guard)?;
+
+            if !below_hi(&node, bound) {
+                // the node was split without its parent
+                // being told about the new right sibling
+                cursor =
+                    node.next.ok_or_else(|| Error::corruption(None))?.get();
+                continue;
+            }
+
+            if !node.is_index {
+                return Ok(node);
+            }
+
+            // index nodes map the low key of each child to its pid
+            let (_lo, pid) = node
+                .predecessor(bound)
+                .ok_or_else(|| Error::corruption(None))?;
+            let pid_bytes = <[u8; 8]>::try_from(pid.as_ref())
+                .map_err(|_| Error::corruption(None))?;
+            cursor = u64::from_le_bytes(pid_bytes);
+        }
+    }
+
+    /// Returns the name of the tree this snapshot was taken of.
+    pub fn name(&self) -> IVec {
+        self.name.clone()
+    }
+
+    /// Retrieve a value as it was when the snapshot was taken.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// db.insert(&[0], vec![0])?;
+    ///
+    /// let snapshot = db.snapshot()?;
+    /// db.insert(&[0], vec![1])?;
+    ///
+    /// assert_eq!(snapshot.get(&[0])?, Some(sled::IVec::from(vec![0])));
+    /// assert_eq!(db.get(&[0])?, Some(sled::IVec::from(vec![1])));
+    /// # Ok(()) }
+    /// ```
+    pub fn get<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<IVec>> {
+        let guard = pin();
+        let bound = Bound::Included(IVec::from(key.as_ref()));
+        let leaf = self.leaf_for(&bound, &guard)?;
+        let value = leaf.node_kv_pair(key.as_ref()).1.map(IVec::from);
+        if value.is_some() && self.is_expired(key.as_ref())? {
+            return Ok(None);
+        }
+        Ok(value)
+    }
+
+    /// Returns `true` if the snapshot contains a value for
+    /// the specified key.
+    pub fn contains_key<K: AsRef<[u8]>>(&self, key: K) -> Result<bool> {
+        self.get(key).map(|v| v.is_some())
+    }
+
+    /// Create a double-ended iterator over the tuples of keys and
+    /// values in this snapshot.
+    pub fn iter(&self) -> SnapshotIter {
+        self.range::<&[u8], _>(..)
+    }
+
+    /// Create a double-ended iterator over tuples of keys and values,
+    /// where the keys fall within the specified range.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// for i in 0..5_u8 {
+    ///     db.insert(&[i], vec![i])?;
+    /// }
+    ///
+    /// let snapshot = db.snapshot()?;
+    /// db.remove(&[2])?;
+    ///
+    /// let start: &[u8] = &[1];
+    /// let end: &[u8] = &[4];
+    /// let keys: Vec<_> =
+    ///     snapshot.range(start..end).keys().map(|k| k.unwrap()).collect();
+    /// assert_eq!(keys, vec![vec![1], vec![2], vec![3]]);
+    /// # Ok(()) }
+    /// ```
+    pub fn range<K, R>(&self, range: R) -> SnapshotIter
+    where
+        K: AsRef<[u8]>,
+        R: RangeBounds<K>,
+    {
+        let (lo, hi) = ivec_bounds(&range);
+        SnapshotIter { tree: self.clone(), lo, hi, leaf: None }
+    }
+
+    /// Create an iterator over tuples of keys and values,
+    /// where the all the keys starts with the given prefix.
+    pub fn scan_prefix<P>(&self, prefix: P) -> SnapshotIter
+    where
+        P: AsRef<[u8]>,
+    {
+        let prefix_ref = prefix.as_ref();
+        let mut upper = prefix_ref.to_vec();
+
+        while let Some(last) = upper.pop() {
+            if last < u8::max_value() {
+                upper.push(last + 1);
+                return self.range(prefix_ref..&upper);
+            }
+        }
+
+        self.range(prefix..)
+    }
+
+    /// Returns the first key and value in the snapshot, or
+    /// `None` if it is empty.
+    pub fn first(&self) -> Result<Option<(IVec, IVec)>> {
+        self.iter().next().transpose()
+    }
+
+    /// Returns the last key and value in the snapshot, or
+    /// `None` if it is empty.
+    pub fn last(&self) -> Result<Option<(IVec, IVec)>> {
+        self.iter().next_back().transpose()
+    }
+
+    /// Returns the number of elements in this snapshot.
+    ///
+    /// Performs a full O(n) scan under the hood.
+    pub fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    /// Returns `true` if the snapshot contains no elements.
+    pub fn is_empty(&self) -> bool {
+        self.first().map_or(true, |first| first.is_none())
+    }
+}
+
+/// An iterator over keys and values in a `TreeSnapshot`.
+pub struct SnapshotIter {
+    tree: TreeSnapshot,
+    lo: Bound<IVec>,
+    hi: Bound<IVec>,
+    // the last leaf visited, which never changes
+    // because the snapshot is immutable
+    leaf: Option<Node>,
+}
+
+impl SnapshotIter {
+    /// Iterate over the keys of this snapshot
+    pub fn keys(
+        self,
+    ) -> impl DoubleEndedIterator<Item = Result<IVec>> + Send + Sync {
+        self.map(|r| r.map(|(k, _v)| k))
+    }
+
+    /// Iterate over the values of this snapshot
+    pub fn values(
+        self,
+    ) -> impl DoubleEndedIterator<Item = Result<IVec>> + Send + Sync {
+        self.map(|r| r.map(|(_k, v)| v))
+    }
+
+    fn bounds_collapsed(&self) -> bool {
+        match (&self.lo, &self.hi) {
+            (Bound::Included(ref start), Bound::Included(ref end)) => {
+                start > end
+            }
+            (Bound::Included(ref start), Bound::Excluded(ref end))
+            | (Bound::Excluded(ref start), Bound::Included(ref end))
+            | (Bound::Excluded(ref start), Bound::Excluded(ref end)) => {
+                start >= end
+            }
+            _ => false,
+        }
+    }
+
+    // Returns the leaf that holds the greatest keys within
+    // `bound`, reusing the last visited leaf when it does.
+    fn leaf_for(&mut self, bound: &Bound<IVec>) -> Result<Node> {
+        if let Some(leaf) = &self.leaf {
+            if below_hi(leaf, bound) && above_lo(leaf, bound) {
+                return Ok(leaf.shallow_clone());
+            }
+        }
+
+        let guard = pin();
+        let leaf = self.tree.leaf_for(bound, &guard)?;
+        self.leaf = Some(leaf.shallow_clone());
+        Ok(leaf)
+    }
+}
+
+impl Iterator for SnapshotIter {
+    type Item = Result<(IVec, IVec)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.bounds_collapsed() {
+                return None;
+            }
+
+            let low_key = match self.lo {
+                Bound::Unbounded => IVec::default(),
+                Bound::Excluded(ref lo) | Bound::Included(ref lo) => lo.clone(),
+            };
+            let leaf = match self.leaf_for(&Bound::Included(low_key)) {
+                Ok(leaf) => leaf,
+                Err(e) => return Some(Err(e)),
+            };
+
+            if let Some((key, value)) = leaf.successor(&self.lo) {
+                let in_bounds = match self.hi {
+                    Bound::Unbounded => true,
+                    Bound::Included(ref h) => *h >= key,
+                    Bound::Excluded(ref h) => *h > key,
+                };
+                if !in_bounds {
+                    return None;
+                }
+                self.lo = Bound::Excluded(key.clone());
+                match self.tree.is_expired(&key) {
+                    // keys whose deadline had passed are skipped
+                    Ok(true) => {}
+                    Ok(false) => return Some(Ok((key, value))),
+                    Err(e) => return Some(Err(e)),
+                }
+            } else if let Some(hi) = leaf.hi() {
+                self.lo = Bound::Included(hi.into());
+            } else {
+                return None;
+            }
+        }
+    }
+
+    fn last(mut self) -> Option<Self::Item> {
+        self.next_back()
+    }
+}
+
+impl DoubleEndedIterator for SnapshotIter {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.bounds_collapsed() {
+                return None;
+            }
+
+            if let Bound::Excluded(ref hi) = self.hi {
+                if hi.is_empty() {
+                    // nothing sorts below the empty key
+                    return None;
+                }
+            }
+
+            let hi = self.hi.clone();
+            let leaf = match self.leaf_for(&hi) {
+                Ok(leaf) => leaf,
+                Err(e) => return Some(Err(e)),
+            };
+
+            if let Some((key, value)) = leaf.predecessor(&self.hi) {
+                let in_bounds = match self.lo {
+                    Bound::Unbounded => true,
+                    Bound::Included(ref l) => *l <= key,
+                    Bound::Excluded(ref l) => *l < key,
+                };
+                if !in_bounds {
+                    return None;
+                }
+                self.hi = Bound::Excluded(key.clone());
+                match self.tree.is_expired(&key) {
+                    // keys whose deadline had passed are skipped
+                    Ok(true) => {}
+                    Ok(false) => return Some(Ok((key, value))),
+                    Err(e) => return Some(Err(e)),
+                }
+            } else if leaf.lo().is_empty() {
+                return None;
+            } else {
+                self.hi = Bound::Excluded(leaf.lo().into());
+            }
+        }
+    }
+}
+
+// Returns whether the keys within `bound` that the
+// node may hold are not all above its high key.
+fn below_hi(node: &Node, bound: &Bound<IVec>) -> bool {
+    match (bound, node.hi()) {
+        (_, None) => true,
+        (Bound::Unbounded, Some(_)) => false,
+        (Bound::Included(key), Some(hi)) => &**key < hi,
+        (Bound::Excluded(key), Some(hi)) => &**key <= hi,
+    }
+}
+
+// Returns whether the node may hold keys within `bound`.
+fn above_lo(node: &Node, bound: &Bound<IVec>) -> bool {
+    match bound {
+        Bound::Unbounded => true,
+        Bound::Included(key) => node.lo() <= &**key,
+        Bound::Excluded(key) => node.lo() < &**key,
+    }
+}
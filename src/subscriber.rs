@@ -29,7 +29,11 @@ impl Event {
     ) -> Event {
         Event::single_batch(
             tree,
-            Batch { writes: vec![(key, value)].into_iter().collect() },
+            Batch {
+                writes: vec![(key, value)].into_iter().collect(),
+                removed_ranges: vec![],
+                removed_keys: Map::default(),
+            },
         )
     }
 
@@ -56,7 +60,11 @@ impl<'a> IntoIterator for &'a Event {
 
     fn into_iter(self) -> Self::IntoIter {
         Box::new(self.batches.iter().flat_map(|(ref tree, ref batch)| {
-            batch.writes.iter().map(move |(k, v_opt)| (tree, k, v_opt))
+            batch
+                .writes
+                .iter()
+                .chain(&batch.removed_keys)
+                .map(move |(k, v_opt)| (tree, k, v_opt))
         }))
     }
 }
@@ -273,7 +281,7 @@ impl Subscribers {
         let mut skip_indices = std::collections::HashSet::new();
         let mut subscribers = vec![];
 
-        for key in batch.writes.keys() {
+        for key in batch.writes.keys().chain(batch.removed_keys.keys()) {
             for (idx, (prefix, subs_rwl)) in r_mu.iter().enumerate() {
                 if key.starts_with(prefix) && !skip_indices.contains(&idx) {
                     skip_indices.insert(idx);
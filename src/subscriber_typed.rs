@@ -0,0 +1,188 @@
+use std::{
+    fmt::{self, Display},
+    future::Future,
+    marker::PhantomData,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use crate::{encoding::Decoder, Event, IVec, Subscriber};
+
+/// A decoded key and optional value from a `TypedEvent`,
+/// or the error that decoding one of them returned.
+pub type TypedChange<'a, K, V> = Result<
+    (<K as Decoder<'a>>::Out, Option<<V as Decoder<'a>>::Out>),
+    TypedEventError<<K as Decoder<'a>>::Error, <V as Decoder<'a>>::Error>,
+>;
+
+/// A key or value of a `TypedEvent` that couldn't be decoded.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TypedEventError<KE, VE> {
+    /// The key couldn't be decoded.
+    Key(KE),
+    /// The value couldn't be decoded.
+    Value(VE),
+}
+
+impl<KE: Display, VE: Display> Display for TypedEventError<KE, VE> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TypedEventError::Key(e) => write!(f, "failed to decode key: {}", e),
+            TypedEventError::Value(e) => {
+                write!(f, "failed to decode value: {}", e)
+            }
+        }
+    }
+}
+
+impl<KE, VE> std::error::Error for TypedEventError<KE, VE>
+where
+    KE: std::error::Error,
+    VE: std::error::Error,
+{
+}
+
+/// An `Event` that happened to keys of a `TypedTree` which
+/// a `TypedSubscriber` is interested in.
+pub struct TypedEvent<K, V> {
+    event: Event,
+    tree_id: IVec,
+    prefix: IVec,
+    _k: PhantomData<K>,
+    _v: PhantomData<V>,
+}
+
+impl<K, V> TypedEvent<K, V>
+where
+    for<'a> K: Decoder<'a>,
+    for<'a> V: Decoder<'a>,
+{
+    /// Iterate over the decoded keys and optional values that
+    /// changed in the watched `TypedTree`. A value of `None`
+    /// means that the key was removed.
+    ///
+    /// Events of batches and transactions may also contain
+    /// keys that don't start with the watched prefix, or that
+    /// belong to other trees, which are skipped.
+    pub fn iter<'a>(
+        &'a self,
+    ) -> impl Iterator<Item = TypedChange<'a, K, V>> + 'a {
+        self.event
+            .iter()
+            .filter(move |(tree, key, _)| {
+                tree.tree_id == self.tree_id && key.starts_with(&self.prefix)
+            })
+            .map(|(_tree, raw_key, raw_value_opt)| {
+                let key = K::decode(raw_key).map_err(TypedEventError::Key)?;
+                let value = raw_value_opt
+                    .as_ref()
+                    .map(|raw_value| V::decode(raw_value))
+                    .transpose()
+                    .map_err(TypedEventError::Value)?;
+                Ok((key, value))
+            })
+    }
+
+    /// Returns the untyped `Event`, which contains the raw
+    /// changes of every tree written to by a transaction.
+    pub fn into_event(self) -> Event {
+        self.event
+    }
+}
+
+/// A subscriber listening on a prefix of the encoded keys of a
+/// `TypedTree`, created by `TypedTree::watch_prefix`.
+///
+/// Like `Subscriber`, `TypedSubscriber` implements both
+/// `Iterator<Item = TypedEvent<K, V>>` and
+/// `Future<Output = Option<TypedEvent<K, V>>>`.
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let db = sled::Config::default().temporary(true).open()?;
+/// let tree = db
+///     .open_tree("name_to_id")?
+///     .with_encodings::<sled::StringEncoding, sled::IntegerEncoding<u128>>();
+///
+/// let subscriber = tree.watch_prefix("Ju");
+///
+/// tree.insert("June", 6)?;
+/// tree.remove("June")?;
+///
+/// for event in subscriber.take(2) {
+///     for change in event.iter() {
+///         match change? {
+///             (key, Some(value)) => assert_eq!((key, value), ("June", 6)),
+///             (key, None) => assert_eq!(key, "June"),
+///         }
+///     }
+/// }
+/// # Ok(()) }
+/// ```
+pub struct TypedSubscriber<K, V> {
+    subscriber: Subscriber,
+    tree_id: IVec,
+    prefix: IVec,
+    _k: PhantomData<K>,
+    _v: PhantomData<V>,
+}
+
+// the encodings are only markers, and the subscriber is never
+// pinned, so this can be moved after being polled regardless of
+// them, like `Subscriber`
+impl<K, V> Unpin for TypedSubscriber<K, V> {}
+
+impl<K, V> TypedSubscriber<K, V> {
+    pub(crate) fn new(
+        subscriber: Subscriber,
+        tree_id: IVec,
+        prefix: IVec,
+    ) -> Self {
+        Self { subscriber, tree_id, prefix, _k: PhantomData, _v: PhantomData }
+    }
+
+    fn typed_event(&self, event: Event) -> TypedEvent<K, V> {
+        TypedEvent {
+            event,
+            tree_id: self.tree_id.clone(),
+            prefix: self.prefix.clone(),
+            _k: PhantomData,
+            _v: PhantomData,
+        }
+    }
+
+    /// Attempts to wait for a value on this `TypedSubscriber`,
+    /// returning an error if no event arrives within the provided
+    /// `Duration` or if the backing `Db` shuts down.
+    pub fn next_timeout(
+        &self,
+        timeout: Duration,
+    ) -> Result<TypedEvent<K, V>, std::sync::mpsc::RecvTimeoutError> {
+        self.subscriber
+            .next_timeout(timeout)
+            .map(|event| self.typed_event(event))
+    }
+}
+
+impl<K, V> Future for TypedSubscriber<K, V> {
+    type Output = Option<TypedEvent<K, V>>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        Pin::new(&mut this.subscriber)
+            .poll(cx)
+            .map(|event_opt| event_opt.map(|event| this.typed_event(event)))
+    }
+}
+
+impl<K, V> Iterator for TypedSubscriber<K, V> {
+    type Item = TypedEvent<K, V>;
+
+    fn next(&mut self) -> Option<TypedEvent<K, V>> {
+        let event = self.subscriber.next()?;
+        Some(self.typed_event(event))
+    }
+}
@@ -79,11 +79,21 @@
 //! # }
 //! ```
 #![allow(clippy::module_name_repetitions)]
-use std::{cell::RefCell, fmt, rc::Rc};
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap},
+    fmt,
+    hash::BuildHasher,
+    marker::PhantomData,
+    ops::{Bound, RangeBounds},
+    rc::Rc,
+    time::{Duration, Instant},
+};
 
 use crate::{
-    concurrency_control, pin, Batch, Error, Event, Guard, IVec, Map, Protector,
-    Result, Tree,
+    batch::ivec_bounds, concurrency_control, pin, tree::add_to_counter, Backoff,
+    Batch, CompareAndSwapError, Error, Event, Guard, IVec, Iter, Map,
+    Protector, Result, SnapshotIter, Tree, TreeSnapshot,
 };
 
 /// A transaction that will
@@ -95,6 +105,16 @@ pub struct TransactionalTree {
     pub(super) writes: Rc<RefCell<Batch>>,
     pub(super) read_cache: Rc<RefCell<Map<IVec, Option<IVec>>>>,
     pub(super) flush_on_commit: Rc<RefCell<bool>>,
+    // the pending writes of every tree in the transaction,
+    // which are restored together by a `Savepoint`
+    pub(super) transaction_writes: Rc<Vec<Rc<RefCell<Batch>>>>,
+    // the name of the tree and the key of the last conflict
+    // reported by the closure, shared by every tree in the
+    // transaction
+    pub(super) conflict: Rc<RefCell<Option<(IVec, IVec)>>>,
+    // the view that a read-only transaction reads from, captured
+    // at the same instant as those of the other trees in it
+    pub(super) snapshot: Option<TreeSnapshot>,
 }
 
 /// An error type that is returned from the closure
@@ -166,7 +186,9 @@ pub enum ConflictableTransactionError<T = Error> {
     /// An internal conflict has occurred and the `transaction` method will
     /// retry the passed-in closure until it succeeds. This should never be
     /// returned directly from the user's closure, as it will create an
-    /// infinite loop that never returns. This is why it is hidden.
+    /// infinite loop that never returns. This is why it is hidden. Closures
+    /// that run within `TransactionOptions` limits return it through
+    /// `TransactionalTree::conflict` instead.
     Conflict,
     /// A serious underlying storage issue has occurred that requires
     /// attention from an operator or a remediating system, such as
@@ -209,6 +231,29 @@ pub enum TransactionError<T = Error> {
     /// attention from an operator or a remediating system, such as
     /// corruption.
     Storage(Error),
+    /// The closure kept conflicting until the limits set by the
+    /// `TransactionOptions` passed to `transaction_with_options`
+    /// were reached.
+    ///
+    /// sled itself never detects a conflict between transactions,
+    /// because a transaction holds exclusive access to the database
+    /// while its closure runs and commits. Every conflict is
+    /// therefore one that the closure reported itself, and `tree`
+    /// and `key` are only `None` if it did so by returning
+    /// `ConflictableTransactionError::Conflict` directly instead of
+    /// using `TransactionalTree::conflict`.
+    Conflict {
+        /// The number of times that the closure was run.
+        attempts: usize,
+        /// The name of the `Tree` that the closure passed to
+        /// `TransactionalTree::conflict` when it last conflicted,
+        /// or `None` if it did not call it.
+        tree: Option<IVec>,
+        /// The key that the closure passed to
+        /// `TransactionalTree::conflict` when it last conflicted,
+        /// or `None` if it did not call it.
+        key: Option<IVec>,
+    },
 }
 
 impl<E: fmt::Display> fmt::Display for TransactionError<E> {
@@ -217,6 +262,19 @@ impl<E: fmt::Display> fmt::Display for TransactionError<E> {
         match self {
             Abort(e) => e.fmt(f),
             Storage(e) => e.fmt(f),
+            Conflict { attempts, tree: Some(tree), key: Some(key) } => {
+                write!(
+                    f,
+                    "Transaction gave up after {} conflicting attempts, \
+                     the last on key {:?} of tree {:?}",
+                    attempts, key, tree
+                )
+            }
+            Conflict { attempts, .. } => write!(
+                f,
+                "Transaction gave up after {} conflicting attempts",
+                attempts
+            ),
         }
     }
 }
@@ -284,6 +342,133 @@ impl TransactionalTree {
         old
     }
 
+    /// Add `delta` to the counter stored under the key, returning
+    /// its new value. Uses the same encoding as `Tree::increment`.
+    pub fn increment<K>(
+        &self,
+        key: K,
+        delta: i64,
+    ) -> UnabortableTransactionResult<i64>
+    where
+        K: AsRef<[u8]> + Into<IVec>,
+    {
+        let current = self.get(key.as_ref())?;
+        let count = add_to_counter(key.as_ref(), current.as_deref(), delta)?;
+        let _old = self.insert(key, &count.to_le_bytes())?;
+        Ok(count)
+    }
+
+    /// Compare and swap, like `Tree::compare_and_swap`, against the
+    /// value that the key has within this transaction.
+    #[allow(clippy::needless_pass_by_value)]
+    pub fn compare_and_swap<K, OV, NV>(
+        &self,
+        key: K,
+        old: Option<OV>,
+        new: Option<NV>,
+    ) -> UnabortableTransactionResult<
+        std::result::Result<(), CompareAndSwapError>,
+    >
+    where
+        K: AsRef<[u8]>,
+        OV: AsRef<[u8]>,
+        NV: Into<IVec>,
+    {
+        let current = self.get(key.as_ref())?;
+        let proposed = new.map(Into::into);
+        if current.as_ref().map(AsRef::as_ref)
+            != old.as_ref().map(AsRef::as_ref)
+        {
+            return Ok(Err(CompareAndSwapError { current, proposed }));
+        }
+        let _old = self.set(key.as_ref(), proposed)?;
+        Ok(Ok(()))
+    }
+
+    /// Merge a new value into the value of the key, like
+    /// `Tree::merge`, using the `MergeOperator` of the `Tree` on
+    /// the value that the key has within this transaction.
+    pub fn merge<K, V>(
+        &self,
+        key: K,
+        value: V,
+    ) -> UnabortableTransactionResult<Option<IVec>>
+    where
+        K: AsRef<[u8]>,
+        V: AsRef<[u8]>,
+    {
+        let merge_operator_opt = self.tree.merge_operator.read();
+        self.tree.verify_merge_operator()?;
+        let merge_operator = merge_operator_opt.as_ref().ok_or_else(|| {
+            Error::Unsupported(
+                "must set a merge operator on this Tree \
+                 before calling merge by calling \
+                 Tree::set_merge_operator"
+                    .to_owned(),
+            )
+        })?;
+
+        let current = self.get(key.as_ref())?;
+        let merged = merge_operator(
+            key.as_ref(),
+            current.as_ref().map(AsRef::as_ref),
+            value.as_ref(),
+        )
+        .map(IVec::from);
+        let _old = self.set(key.as_ref(), merged.clone())?;
+        Ok(merged)
+    }
+
+    /// Replace the value of the key with the one computed from it
+    /// by the provided function, like `Tree::update_and_fetch`,
+    /// returning the new value. The function is called once.
+    pub fn update_and_fetch<K, V, F>(
+        &self,
+        key: K,
+        mut f: F,
+    ) -> UnabortableTransactionResult<Option<IVec>>
+    where
+        K: AsRef<[u8]>,
+        F: FnMut(Option<&[u8]>) -> Option<V>,
+        V: Into<IVec>,
+    {
+        let current = self.get(key.as_ref())?;
+        let next = f(current.as_ref().map(AsRef::as_ref)).map(Into::into);
+        let _old = self.set(key.as_ref(), next.clone())?;
+        Ok(next)
+    }
+
+    /// Replace the value of the key with the one computed from it
+    /// by the provided function, like `Tree::fetch_and_update`,
+    /// returning the previous value. The function is called once.
+    pub fn fetch_and_update<K, V, F>(
+        &self,
+        key: K,
+        mut f: F,
+    ) -> UnabortableTransactionResult<Option<IVec>>
+    where
+        K: AsRef<[u8]>,
+        F: FnMut(Option<&[u8]>) -> Option<V>,
+        V: Into<IVec>,
+    {
+        let current = self.get(key.as_ref())?;
+        let next = f(current.as_ref().map(AsRef::as_ref)).map(Into::into);
+        let _old = self.set(key.as_ref(), next)?;
+        Ok(current)
+    }
+
+    // Inserts the value, or removes the key if there is none.
+    fn set(
+        &self,
+        key: &[u8],
+        value: Option<IVec>,
+    ) -> UnabortableTransactionResult<Option<IVec>> {
+        match value {
+            Some(new_value) => self.insert(key, new_value),
+            None => self.remove(key),
+        }
+    }
+
     /// Get the value associated with a key
     pub fn get<K: AsRef<[u8]>>(
         &self,
@@ -311,11 +496,128 @@ impl TransactionalTree {
         Ok(get)
     }
 
+    /// Create a double-ended iterator over tuples of keys and values,
+    /// where the keys fall within the specified range. The iterator
+    /// reflects both the contents of the underlying `Tree` and any
+    /// writes made earlier in this transaction.
+    ///
+    /// Transactions hold exclusive access to the database while
+    /// their closure runs, so no concurrent writer can insert keys
+    /// into or remove keys from a scanned range before the
+    /// transaction commits, and scans never observe phantoms.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use sled::{transaction::TransactionResult, Config};
+    /// # fn main() -> TransactionResult<()> {
+    /// # let config = Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// db.insert(b"slot_0", b"taken")?;
+    /// db.insert(b"slot_1", b"taken")?;
+    ///
+    /// // claim the first free slot
+    /// let claimed = db.transaction(|tx_db| {
+    ///     let taken = tx_db.scan_prefix(b"slot_").count();
+    ///     let slot = format!("slot_{}", taken);
+    ///     tx_db.insert(slot.as_bytes(), b"taken")?;
+    ///     Ok(slot)
+    /// })?;
+    ///
+    /// assert_eq!(claimed, "slot_2");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn range<K, R>(&self, range: R) -> TransactionalIter<'_>
+    where
+        K: AsRef<[u8]>,
+        R: RangeBounds<K>,
+    {
+        let bounds = ivec_bounds(&range);
+        let writes = self.writes.borrow();
+
+        let pending = writes
+            .writes
+            .iter()
+            .filter(|(k, _)| bounds.contains(*k))
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+
+        TransactionalIter {
+            iter: self.tree.range::<IVec, _>(bounds),
+            pending,
+            removed_ranges: writes.removed_ranges.clone(),
+            next_tree: None,
+            next_back_tree: None,
+            _transaction: PhantomData,
+        }
+    }
+
+    /// Create a double-ended iterator over tuples of keys and values,
+    /// where all the keys start with the given prefix. See `range`
+    /// for details on how pending writes and concurrent writers
+    /// are handled.
+    pub fn scan_prefix<P>(&self, prefix: P) -> TransactionalIter<'_>
+    where
+        P: AsRef<[u8]>,
+    {
+        let prefix_ref = prefix.as_ref();
+        let mut upper = prefix_ref.to_vec();
+
+        while let Some(last) = upper.pop() {
+            if last < u8::max_value() {
+                upper.push(last + 1);
+                return self.range(prefix_ref..&upper);
+            }
+        }
+
+        self.range(prefix..)
+    }
+
+    /// Returns the first key and value in the `Tree`, or
+    /// `None` if it is empty.
+    pub fn first(&self) -> UnabortableTransactionResult<Option<(IVec, IVec)>> {
+        self.range::<&[u8], _>(..).next().transpose()
+    }
+
+    /// Returns the last key and value in the `Tree`, or
+    /// `None` if it is empty.
+    pub fn last(&self) -> UnabortableTransactionResult<Option<(IVec, IVec)>> {
+        self.range::<&[u8], _>(..).next_back().transpose()
+    }
+
+    /// Retrieve the key and value before the provided key,
+    /// if one exists.
+    pub fn get_lt<K>(
+        &self,
+        key: K,
+    ) -> UnabortableTransactionResult<Option<(IVec, IVec)>>
+    where
+        K: AsRef<[u8]>,
+    {
+        self.range(..key).next_back().transpose()
+    }
+
+    /// Retrieve the next key and value from the `Tree` after the
+    /// provided key.
+    pub fn get_gt<K>(
+        &self,
+        key: K,
+    ) -> UnabortableTransactionResult<Option<(IVec, IVec)>>
+    where
+        K: AsRef<[u8]>,
+    {
+        self.range((Bound::Excluded(key), Bound::Unbounded)).next().transpose()
+    }
+
     /// Atomically apply multiple inserts and removals.
     pub fn apply_batch(
         &self,
         batch: &Batch,
     ) -> UnabortableTransactionResult<()> {
+        for range in &batch.removed_ranges {
+            self.writes.borrow_mut().remove_range(range.clone());
+        }
         for (k, v_opt) in &batch.writes {
             if let Some(v) = v_opt {
                 let _old = self.insert(k, v)?;
@@ -326,6 +628,47 @@ impl TransactionalTree {
         Ok(())
     }
 
+    /// Mark the current pending writes of every `Tree` in this
+    /// transaction, so that a failed sub-step can be undone with
+    /// `Savepoint::rollback_to` without aborting the transaction.
+    /// Taking a savepoint copies the pending writes.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use sled::{transaction::TransactionResult, Config, Transactional};
+    /// # fn main() -> TransactionResult<()> {
+    /// # let config = Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// let orders = db.open_tree(b"orders")?;
+    /// let stock = db.open_tree(b"stock")?;
+    ///
+    /// (&orders, &stock).transaction(|(tx_orders, tx_stock)| {
+    ///     tx_orders.insert(b"order_1", b"pending")?;
+    ///
+    ///     let savepoint = tx_orders.savepoint();
+    ///     tx_orders.insert(b"order_1", b"shipped")?;
+    ///     tx_stock.remove(b"widget")?;
+    ///     if tx_stock.get(b"gadget")?.is_none() {
+    ///         // undo the writes to both trees since the savepoint
+    ///         savepoint.rollback_to();
+    ///     }
+    ///     Ok(())
+    /// })?;
+    ///
+    /// assert_eq!(orders.get(b"order_1")?, Some(b"pending".into()));
+    /// # Ok(()) }
+    /// ```
+    pub fn savepoint(&self) -> Savepoint {
+        Savepoint {
+            writes: self
+                .transaction_writes
+                .iter()
+                .map(|writes| (writes.clone(), writes.borrow().clone()))
+                .collect(),
+        }
+    }
+
     /// Flush the database before returning from the transaction.
     pub fn flush(&self) {
         *self.flush_on_commit.borrow_mut() = true;
@@ -345,6 +688,20 @@ impl TransactionalTree {
         self.tree.context.pagecache.generate_id_inner()
     }
 
+    /// Reports that the closure conflicted on the provided key,
+    /// returning the error that it should return to be run again.
+    /// If the limits of the `TransactionOptions` are reached, the
+    /// key and the name of this `Tree` are returned as part of
+    /// `TransactionError::Conflict`.
+    pub fn conflict<K, E>(&self, key: K) -> ConflictableTransactionError<E>
+    where
+        K: AsRef<[u8]>,
+    {
+        *self.conflict.borrow_mut() =
+            Some((self.tree.tree_id.clone(), IVec::from(key.as_ref())));
+        ConflictableTransactionError::Conflict
+    }
+
     fn unstage(&self) {
         unimplemented!()
     }
@@ -365,6 +722,264 @@ impl TransactionalTree {
             writes: Default::default(),
             read_cache: Default::default(),
             flush_on_commit: Default::default(),
+            transaction_writes: Default::default(),
+            conflict: Default::default(),
+            snapshot: None,
+        }
+    }
+}
+
+/// The pending writes of a transaction at the time that
+/// `TransactionalTree::savepoint` was called.
+pub struct Savepoint {
+    writes: Vec<(Rc<RefCell<Batch>>, Batch)>,
+}
+
+impl Savepoint {
+    /// Restore the pending writes of every `Tree` in the
+    /// transaction to what they were when this savepoint was
+    /// taken, discarding every write made since. The transaction
+    /// continues, and may roll back to this savepoint again.
+    pub fn rollback_to(&self) {
+        for (writes, saved) in &self.writes {
+            *writes.borrow_mut() = saved.clone();
+        }
+    }
+}
+
+/// A read-only view of a `Tree` within a transaction started by
+/// `Transactional::read_transaction`, which only provides the
+/// reading methods of `TransactionalTree`. Every read observes
+/// the `Tree` as it was when the transaction started, and keys
+/// whose time-to-live had run out by then are not returned.
+#[derive(Clone)]
+pub struct ReadOnlyTransactionalTree {
+    tree: TreeSnapshot,
+}
+
+impl ReadOnlyTransactionalTree {
+    /// Get the value associated with a key
+    pub fn get<K: AsRef<[u8]>>(
+        &self,
+        key: K,
+    ) -> UnabortableTransactionResult<Option<IVec>> {
+        Ok(self.tree.get(key)?)
+    }
+
+    /// Create a double-ended iterator over tuples of keys and values,
+    /// where the keys fall within the specified range.
+    pub fn range<K, R>(&self, range: R) -> SnapshotIter
+    where
+        K: AsRef<[u8]>,
+        R: RangeBounds<K>,
+    {
+        self.tree.range(range)
+    }
+
+    /// Create a double-ended iterator over tuples of keys and values,
+    /// where all the keys start with the given prefix.
+    pub fn scan_prefix<P>(&self, prefix: P) -> SnapshotIter
+    where
+        P: AsRef<[u8]>,
+    {
+        self.tree.scan_prefix(prefix)
+    }
+
+    /// Returns the first key and value in the `Tree`, or
+    /// `None` if it is empty.
+    pub fn first(&self) -> UnabortableTransactionResult<Option<(IVec, IVec)>> {
+        Ok(self.tree.first()?)
+    }
+
+    /// Returns the last key and value in the `Tree`, or
+    /// `None` if it is empty.
+    pub fn last(&self) -> UnabortableTransactionResult<Option<(IVec, IVec)>> {
+        Ok(self.tree.last()?)
+    }
+
+    /// Retrieve the key and value before the provided key,
+    /// if one exists.
+    pub fn get_lt<K>(
+        &self,
+        key: K,
+    ) -> UnabortableTransactionResult<Option<(IVec, IVec)>>
+    where
+        K: AsRef<[u8]>,
+    {
+        Ok(self.tree.range(..key).next_back().transpose()?)
+    }
+
+    /// Retrieve the next key and value from the `Tree` after the
+    /// provided key.
+    pub fn get_gt<K>(
+        &self,
+        key: K,
+    ) -> UnabortableTransactionResult<Option<(IVec, IVec)>>
+    where
+        K: AsRef<[u8]>,
+    {
+        Ok(self
+            .tree
+            .range((Bound::Excluded(key), Bound::Unbounded))
+            .next()
+            .transpose()?)
+    }
+}
+
+/// The views of a transaction that can be made read-only, to be
+/// passed to the closure of `Transactional::read_transaction`.
+pub trait ReadOnlyView {
+    /// The read-only counterpart of the view.
+    type ReadOnly;
+
+    /// An internal function for restricting a view to reads.
+    fn read_only(&self) -> Self::ReadOnly;
+}
+
+impl ReadOnlyView for TransactionalTree {
+    type ReadOnly = ReadOnlyTransactionalTree;
+
+    fn read_only(&self) -> Self::ReadOnly {
+        let tree = self
+            .snapshot
+            .clone()
+            .expect("read-only views are only made of pinned trees");
+        ReadOnlyTransactionalTree { tree }
+    }
+}
+
+impl ReadOnlyView for Vec<TransactionalTree> {
+    type ReadOnly = Vec<ReadOnlyTransactionalTree>;
+
+    fn read_only(&self) -> Self::ReadOnly {
+        self.iter().map(ReadOnlyView::read_only).collect()
+    }
+}
+
+impl ReadOnlyView for HashMap<IVec, TransactionalTree> {
+    type ReadOnly = HashMap<IVec, ReadOnlyTransactionalTree>;
+
+    fn read_only(&self) -> Self::ReadOnly {
+        self.iter()
+            .map(|(name, tree)| (name.clone(), tree.read_only()))
+            .collect()
+    }
+}
+
+/// An iterator over keys and values in a `TransactionalTree`,
+/// created with `TransactionalTree::range` or
+/// `TransactionalTree::scan_prefix`.
+///
+/// Writes made in the transaction after the iterator was
+/// created are not reflected by it.
+pub struct TransactionalIter<'a> {
+    iter: Iter,
+    // pending writes within the iterated range, which take
+    // precedence over the contents of the tree
+    pending: BTreeMap<IVec, Option<IVec>>,
+    removed_ranges: Vec<(Bound<IVec>, Bound<IVec>)>,
+    next_tree: Option<(IVec, IVec)>,
+    next_back_tree: Option<(IVec, IVec)>,
+    _transaction: PhantomData<&'a TransactionalTree>,
+}
+
+impl TransactionalIter<'_> {
+    fn is_removed(&self, key: &IVec) -> bool {
+        self.removed_ranges.iter().any(|range| range.contains(key))
+    }
+}
+
+impl Iterator for TransactionalIter<'_> {
+    type Item = UnabortableTransactionResult<(IVec, IVec)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.next_tree.is_none() {
+                // the transaction already holds the exclusive
+                // concurrency control lock, so we must not
+                // use the locking `Iter::next`.
+                self.next_tree = match self.iter.next_inner() {
+                    Some(Ok(kv)) => Some(kv),
+                    Some(Err(e)) => return Some(Err(e.into())),
+                    None => self.next_back_tree.take(),
+                };
+            }
+
+            let pending_key = self.pending.keys().next().cloned();
+
+            let take_pending = match (&pending_key, &self.next_tree) {
+                (None, None) => return None,
+                (Some(pk), Some((tk, _))) => pk <= tk,
+                (Some(_), None) => true,
+                (None, Some(_)) => false,
+            };
+
+            if take_pending {
+                let key = pending_key.unwrap();
+                if self
+                    .next_tree
+                    .as_ref()
+                    .map_or(false, |(tk, _)| *tk == key)
+                {
+                    // shadowed by the pending write
+                    self.next_tree = None;
+                }
+                if let Some(value) = self.pending.remove(&key).unwrap() {
+                    return Some(Ok((key, value)));
+                }
+            } else {
+                let (key, value) = self.next_tree.take().unwrap();
+                if !self.is_removed(&key) {
+                    return Some(Ok((key, value)));
+                }
+            }
+        }
+    }
+
+    fn last(mut self) -> Option<Self::Item> {
+        self.next_back()
+    }
+}
+
+impl DoubleEndedIterator for TransactionalIter<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.next_back_tree.is_none() {
+                self.next_back_tree = match self.iter.next_back_inner() {
+                    Some(Ok(kv)) => Some(kv),
+                    Some(Err(e)) => return Some(Err(e.into())),
+                    None => self.next_tree.take(),
+                };
+            }
+
+            let pending_key = self.pending.keys().next_back().cloned();
+
+            let take_pending = match (&pending_key, &self.next_back_tree) {
+                (None, None) => return None,
+                (Some(pk), Some((tk, _))) => pk >= tk,
+                (Some(_), None) => true,
+                (None, Some(_)) => false,
+            };
+
+            if take_pending {
+                let key = pending_key.unwrap();
+                if self
+                    .next_back_tree
+                    .as_ref()
+                    .map_or(false, |(tk, _)| *tk == key)
+                {
+                    // shadowed by the pending write
+                    self.next_back_tree = None;
+                }
+                if let Some(value) = self.pending.remove(&key).unwrap() {
+                    return Some(Ok((key, value)));
+                }
+            } else {
+                let (key, value) = self.next_back_tree.take().unwrap();
+                if !self.is_removed(&key) {
+                    return Some(Ok((key, value)));
+                }
+            }
         }
     }
 }
@@ -375,6 +990,40 @@ pub struct TransactionalTrees {
 }
 
 impl TransactionalTrees {
+    fn new(mut inner: Vec<TransactionalTree>) -> Self {
+        let transaction_writes: Rc<Vec<_>> =
+            Rc::new(inner.iter().map(|tree| tree.writes.clone()).collect());
+        let conflict = Rc::new(RefCell::new(None));
+        for tree in &mut inner {
+            tree.transaction_writes = transaction_writes.clone();
+            tree.conflict = conflict.clone();
+        }
+        TransactionalTrees { inner }
+    }
+
+    // Captures every tree at the same instant for a read-only
+    // transaction to read from.
+    fn pin_snapshots(&mut self) -> Result<()> {
+        let trees: Vec<&Tree> = self.inner.iter().map(|tt| &tt.tree).collect();
+        let snapshots = TreeSnapshot::capture_all(&trees)?;
+        for (tree, snapshot) in self.inner.iter_mut().zip(snapshots) {
+            tree.snapshot = Some(snapshot);
+        }
+        Ok(())
+    }
+
+    // Returns the error that the transaction fails with if it gives
+    // up after `attempts`.
+    fn conflict_error<E>(&self, attempts: usize) -> TransactionError<E> {
+        let conflict =
+            self.inner.first().and_then(|tree| tree.conflict.borrow().clone());
+        let (tree, key) = match conflict {
+            Some((tree, key)) => (Some(tree), Some(key)),
+            None => (None, None),
+        };
+        TransactionError::Conflict { attempts, tree, key }
+    }
+
     fn stage(&self) -> Protector<'_> {
         concurrency_control::write()
     }
@@ -395,7 +1044,36 @@ impl TransactionalTrees {
     }
 
     fn commit(&self, guard: &Guard) -> Result<()> {
-        let peg = self.inner[0].tree.context.pin_log(guard)?;
+        // a transaction over an empty set of trees has nothing to write
+        let first = if let Some(first) = self.inner.first() {
+            first
+        } else {
+            return Ok(());
+        };
+
+        // transactions that only read may still run in read-only mode
+        if first.tree.context.read_only
+            && self.inner.iter().all(|tree| tree.writes.borrow().is_empty())
+        {
+            return Ok(());
+        }
+
+        // unique indexes are checked before anything is written, so
+        // that a violation in any tree leaves every tree untouched
+        for tree in &self.inner {
+            tree.tree
+                .indexes
+                .check_unique(&tree.tree, &tree.writes.borrow())?;
+        }
+
+        let peg = first.tree.context.pin_log(guard)?;
+
+        // range removals are applied up-front so that the keys
+        // they remove can be included in the subscriber event
+        for tree in &self.inner {
+            tree.tree
+                .remove_ranges_inner(&mut tree.writes.borrow_mut(), guard)?;
+        }
 
         let batches = self
             .inner
@@ -432,6 +1110,95 @@ impl TransactionalTrees {
     }
 }
 
+/// Limits how many times `Transactional::transaction_with_options`
+/// runs a closure that conflicts, and how long it waits in between.
+/// Once a limit is reached the transaction fails with
+/// `TransactionError::Conflict`. By default the closure is retried
+/// indefinitely without waiting, like `Transactional::transaction`.
+///
+/// sled does not detect conflicts itself, so only the conflicts
+/// that the closure reports count towards these limits. A closure
+/// that reports one with `TransactionalTree::conflict` names the
+/// tree and key it conflicted on, which the returned
+/// `TransactionError::Conflict` then includes, and otherwise they
+/// are `None`.
+///
+/// # Examples
+///
+/// ```
+/// # use sled::{transaction::{TransactionOptions, TransactionResult}, Config};
+/// # fn main() -> TransactionResult<()> {
+/// use std::time::Duration;
+///
+/// # let config = Config::new().temporary(true);
+/// # let db = config.open()?;
+/// let options = TransactionOptions::default()
+///     .max_retries(10)
+///     .backoff(true)
+///     .timeout(Duration::from_millis(100));
+///
+/// db.transaction_with_options(&options, |tx| {
+///     tx.insert(b"k1", b"cats")?;
+///     Ok(())
+/// })?;
+/// # Ok(()) }
+/// ```
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TransactionOptions {
+    max_retries: Option<usize>,
+    backoff: bool,
+    timeout: Option<Duration>,
+}
+
+impl TransactionOptions {
+    /// Give up after re-running a conflicting closure this many
+    /// times.
+    pub const fn max_retries(mut self, max_retries: usize) -> Self {
+        self.max_retries = Some(max_retries);
+        self
+    }
+
+    /// Back off exponentially before re-running a conflicting
+    /// closure, first spinning and then yielding the thread.
+    pub const fn backoff(mut self, backoff: bool) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    /// Give up once this much time has passed since the transaction
+    /// started, if the closure still conflicts.
+    pub const fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    // Waits before a conflicting closure is run again, or returns
+    // the error that the transaction fails with if it may not be.
+    fn before_retry<E>(
+        &self,
+        tt: &TransactionalTrees,
+        attempts: usize,
+        start: Instant,
+        backoff: &Backoff,
+    ) -> TransactionResult<(), E> {
+        let retries_exhausted = match self.max_retries {
+            Some(max_retries) => attempts > max_retries,
+            None => false,
+        };
+        let timed_out = match self.timeout {
+            Some(timeout) => start.elapsed() >= timeout,
+            None => false,
+        };
+        if retries_exhausted || timed_out {
+            return Err(tt.conflict_error(attempts));
+        }
+        if self.backoff {
+            backoff.snooze();
+        }
+        Ok(())
+    }
+}
+
 /// A simple constructor for `Err(TransactionError::Abort(_))`
 pub fn abort<A, T>(t: T) -> ConflictableTransactionResult<A, T> {
     Err(ConflictableTransactionError::Abort(t))
@@ -460,32 +1227,112 @@ pub trait Transactional<E = ()> {
     where
         F: Fn(&Self::View) -> ConflictableTransactionResult<A, E>,
     {
+        self.transaction_with_options(&TransactionOptions::default(), f)
+    }
+
+    /// Runs a transaction like `transaction`, but only retries the
+    /// passed-in closure within the limits set by the provided
+    /// `TransactionOptions`, returning `TransactionError::Conflict`
+    /// once they are reached.
+    fn transaction_with_options<F, A>(
+        &self,
+        options: &TransactionOptions,
+        f: F,
+    ) -> TransactionResult<A, E>
+    where
+        F: Fn(&Self::View) -> ConflictableTransactionResult<A, E>,
+    {
+        let start = Instant::now();
+        let backoff = Backoff::new();
+        let mut attempts = 0;
         loop {
+            attempts += 1;
             let tt = self.make_overlay()?;
             let view = Self::view_overlay(&tt);
 
             // NB locks must exist until this function returns.
             let locks = tt.stage();
             let ret = f(&view);
-            if !tt.validate() {
+            if tt.validate() {
+                match ret {
+                    Ok(r) => {
+                        let guard = pin();
+                        tt.commit(&guard)?;
+                        drop(locks);
+                        tt.flush_if_configured()?;
+                        return Ok(r);
+                    }
+                    Err(ConflictableTransactionError::Abort(e)) => {
+                        return Err(TransactionError::Abort(e));
+                    }
+                    Err(ConflictableTransactionError::Conflict) => {}
+                    Err(ConflictableTransactionError::Storage(other)) => {
+                        return Err(TransactionError::Storage(other));
+                    }
+                }
+            } else {
                 tt.unstage();
-                continue;
             }
-            match ret {
-                Ok(r) => {
-                    let guard = pin();
-                    tt.commit(&guard)?;
-                    drop(locks);
-                    tt.flush_if_configured()?;
-                    return Ok(r);
-                }
-                Err(ConflictableTransactionError::Abort(e)) => {
-                    return Err(TransactionError::Abort(e));
-                }
-                Err(ConflictableTransactionError::Conflict) => continue,
-                Err(ConflictableTransactionError::Storage(other)) => {
-                    return Err(TransactionError::Storage(other));
-                }
+
+            // other transactions may run while this one waits
+            drop(locks);
+            options.before_retry(&tt, attempts, start, &backoff)?;
+        }
+    }
+
+    /// Runs a transaction that may only read, passing the closure a
+    /// read-only view of every `Tree` involved. The closure is run
+    /// exactly once, and does not block writers while it runs.
+    ///
+    /// Every read observes all of the trees as they were at the
+    /// same instant, when the transaction started, as with
+    /// `Db::snapshot`: no write made after it, whether by a
+    /// transaction, a batch or a single-key operation, is visible.
+    /// Recording that instant takes the same exclusive lock as
+    /// other transactions, so it waits for writes in progress to
+    /// finish and blocks new ones, but only while the root of each
+    /// tree is recorded, not while the closure runs.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use sled::{transaction::TransactionResult, Config, Transactional};
+    /// # fn main() -> TransactionResult<()> {
+    /// # let config = Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// let from = db.open_tree(b"from")?;
+    /// let to = db.open_tree(b"to")?;
+    /// from.insert(b"balance", b"10")?;
+    ///
+    /// let (a, b) = (&from, &to).read_transaction(|(tx_from, tx_to)| {
+    ///     Ok((tx_from.get(b"balance")?, tx_to.get(b"balance")?))
+    /// })?;
+    ///
+    /// assert_eq!(a, Some(sled::IVec::from(b"10")));
+    /// assert_eq!(b, None);
+    /// # Ok(()) }
+    /// ```
+    fn read_transaction<F, A>(&self, f: F) -> TransactionResult<A, E>
+    where
+        Self::View: ReadOnlyView,
+        F: FnOnce(
+            &<Self::View as ReadOnlyView>::ReadOnly,
+        ) -> ConflictableTransactionResult<A, E>,
+    {
+        let mut tt = self.make_overlay()?;
+        tt.pin_snapshots()?;
+        let view = Self::view_overlay(&tt).read_only();
+
+        match f(&view) {
+            Ok(r) => Ok(r),
+            Err(ConflictableTransactionError::Abort(e)) => {
+                Err(TransactionError::Abort(e))
+            }
+            Err(ConflictableTransactionError::Conflict) => {
+                Err(tt.conflict_error(1))
+            }
+            Err(ConflictableTransactionError::Storage(other)) => {
+                Err(TransactionError::Storage(other))
             }
         }
     }
@@ -495,9 +1342,7 @@ impl<E> Transactional<E> for &Tree {
     type View = TransactionalTree;
 
     fn make_overlay(&self) -> Result<TransactionalTrees> {
-        Ok(TransactionalTrees {
-            inner: vec![TransactionalTree::from_tree(self)],
-        })
+        Ok(TransactionalTrees::new(vec![TransactionalTree::from_tree(self)]))
     }
 
     fn view_overlay(overlay: &TransactionalTrees) -> Self::View {
@@ -509,9 +1354,7 @@ impl<E> Transactional<E> for &&Tree {
     type View = TransactionalTree;
 
     fn make_overlay(&self) -> Result<TransactionalTrees> {
-        Ok(TransactionalTrees {
-            inner: vec![TransactionalTree::from_tree(*self)],
-        })
+        Ok(TransactionalTrees::new(vec![TransactionalTree::from_tree(self)]))
     }
 
     fn view_overlay(overlay: &TransactionalTrees) -> Self::View {
@@ -523,9 +1366,7 @@ impl<E> Transactional<E> for Tree {
     type View = TransactionalTree;
 
     fn make_overlay(&self) -> Result<TransactionalTrees> {
-        Ok(TransactionalTrees {
-            inner: vec![TransactionalTree::from_tree(self)],
-        })
+        Ok(TransactionalTrees::new(vec![TransactionalTree::from_tree(self)]))
     }
 
     fn view_overlay(overlay: &TransactionalTrees) -> Self::View {
@@ -548,12 +1389,9 @@ impl<E> Transactional<E> for [Tree] {
             ));
         }
 
-        Ok(TransactionalTrees {
-            inner: self
-                .iter()
-                .map(|t| TransactionalTree::from_tree(t))
-                .collect(),
-        })
+        Ok(TransactionalTrees::new(
+            self.iter().map(TransactionalTree::from_tree).collect(),
+        ))
     }
 
     fn view_overlay(overlay: &TransactionalTrees) -> Self::View {
@@ -576,12 +1414,33 @@ impl<E> Transactional<E> for [&Tree] {
             ));
         }
 
-        Ok(TransactionalTrees {
-            inner: self
-                .iter()
-                .map(|&t| TransactionalTree::from_tree(t))
-                .collect(),
-        })
+        Ok(TransactionalTrees::new(
+            self.iter().map(|&t| TransactionalTree::from_tree(t)).collect(),
+        ))
+    }
+
+    fn view_overlay(overlay: &TransactionalTrees) -> Self::View {
+        overlay.inner.clone()
+    }
+}
+
+impl<E> Transactional<E> for Vec<Tree> {
+    type View = Vec<TransactionalTree>;
+
+    fn make_overlay(&self) -> Result<TransactionalTrees> {
+        <[Tree] as Transactional<E>>::make_overlay(self)
+    }
+
+    fn view_overlay(overlay: &TransactionalTrees) -> Self::View {
+        overlay.inner.clone()
+    }
+}
+
+impl<E> Transactional<E> for &[Tree] {
+    type View = Vec<TransactionalTree>;
+
+    fn make_overlay(&self) -> Result<TransactionalTrees> {
+        <[Tree] as Transactional<E>>::make_overlay(self)
     }
 
     fn view_overlay(overlay: &TransactionalTrees) -> Self::View {
@@ -589,6 +1448,66 @@ impl<E> Transactional<E> for [&Tree] {
     }
 }
 
+/// Trees keyed by their names, so that the set of trees in a
+/// transaction can be chosen at runtime. The closure receives
+/// each `TransactionalTree` under the name of its `Tree`.
+///
+/// # Examples
+///
+/// ```
+/// # use sled::{transaction::TransactionResult, Config, IVec, Transactional};
+/// # fn main() -> TransactionResult<()> {
+/// use std::collections::HashMap;
+///
+/// # let config = Config::new().temporary(true);
+/// # let db = config.open()?;
+/// let mut tenants = HashMap::new();
+/// for name in &["tenant_a", "tenant_b"] {
+///     let tree = db.open_tree(name)?;
+///     tenants.insert(tree.name(), tree);
+/// }
+///
+/// tenants.transaction(|tx_tenants| {
+///     for tx_tenant in tx_tenants.values() {
+///         tx_tenant.insert(b"plan", b"free")?;
+///     }
+///     tx_tenants[b"tenant_a".as_ref()].insert(b"plan", b"paid")?;
+///     Ok(())
+/// })?;
+///
+/// let tenant_a = &tenants[b"tenant_a".as_ref()];
+/// assert_eq!(tenant_a.get(b"plan")?, Some(IVec::from(b"paid")));
+/// # Ok(()) }
+/// ```
+impl<E, S> Transactional<E> for HashMap<IVec, Tree, S>
+where
+    S: BuildHasher,
+{
+    type View = HashMap<IVec, TransactionalTree>;
+
+    fn make_overlay(&self) -> Result<TransactionalTrees> {
+        let mut trees = Vec::with_capacity(self.len());
+        for (name, tree) in self {
+            if *name != tree.tree_id {
+                return Err(Error::Unsupported(
+                    "trees in a transaction must be keyed by their names"
+                        .into(),
+                ));
+            }
+            trees.push(tree);
+        }
+        <[&Tree] as Transactional<E>>::make_overlay(&trees)
+    }
+
+    fn view_overlay(overlay: &TransactionalTrees) -> Self::View {
+        overlay
+            .inner
+            .iter()
+            .map(|tree| (tree.tree.tree_id.clone(), tree.clone()))
+            .collect()
+    }
+}
+
 macro_rules! repeat_type {
     ($t:ty, ($literal:literal)) => {
         ($t,)
@@ -626,13 +1545,11 @@ macro_rules! impl_transactional_tuple_trees {
                     ));
                 }
 
-                Ok(TransactionalTrees {
-                    inner: vec![
-                        $(
-                            TransactionalTree::from_tree(self.$indices)
-                        ),+
-                    ],
-                })
+                Ok(TransactionalTrees::new(vec![
+                    $(
+                        TransactionalTree::from_tree(self.$indices)
+                    ),+
+                ]))
             }
 
             fn view_overlay(overlay: &TransactionalTrees) -> Self::View {
@@ -643,6 +1560,19 @@ macro_rules! impl_transactional_tuple_trees {
                 )
             }
         }
+
+        impl ReadOnlyView for repeat_type!(TransactionalTree, ($($indices),+)) {
+            type ReadOnly =
+                repeat_type!(ReadOnlyTransactionalTree, ($($indices),+));
+
+            fn read_only(&self) -> Self::ReadOnly {
+                (
+                    $(
+                        self.$indices.read_only()
+                    ),+,
+                )
+            }
+        }
     };
 }
 
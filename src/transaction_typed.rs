@@ -0,0 +1,345 @@
+use std::{marker::PhantomData, ops::Deref};
+
+use crate::{
+    batch_typed::TypedBatch,
+    encoding::Encoder,
+    transaction::{
+        Transactional, TransactionalTree, TransactionalTrees,
+        UnabortableTransactionResult,
+    },
+    IVec, Result, Tree, TypedTree,
+};
+
+/// A `TransactionalTree` that encodes keys and values with the
+/// encodings of the `TypedTree` it was created from, by
+/// `TypedTree::transaction` or by running a transaction on a
+/// tuple of `TypedTree`s.
+///
+/// # Examples
+///
+/// ```
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// use sled::{
+///     transaction::ConflictableTransactionError, IntegerEncoding,
+///     StringEncoding, Transactional,
+/// };
+///
+/// let db = sled::Config::default().temporary(true).open()?;
+/// let names = db
+///     .open_tree("id_to_name")?
+///     .with_encodings::<IntegerEncoding<u128>, StringEncoding>();
+/// let ids = db
+///     .open_tree("name_to_id")?
+///     .with_encodings::<StringEncoding, IntegerEncoding<u128>>();
+///
+/// // atomically move an item between trees with different encodings
+/// names.insert(1, "January")?;
+/// (&names, &ids).transaction(|(tx_names, tx_ids)| {
+///     let name = tx_names.remove(1)?.unwrap();
+///     tx_ids.insert(name.decode().unwrap(), 1)?;
+///     Ok::<_, ConflictableTransactionError>(())
+/// })?;
+///
+/// assert!(names.get(1)?.is_none());
+/// assert_eq!(ids.get("January")?.unwrap().decode()?, 1);
+/// # Ok(()) }
+/// ```
+pub struct TypedTransactionalTree<K, V> {
+    tree: TransactionalTree,
+    _k: PhantomData<K>,
+    _v: PhantomData<V>,
+}
+
+impl<K, V> Clone for TypedTransactionalTree<K, V> {
+    fn clone(&self) -> Self {
+        TypedTransactionalTree::new(self.tree.clone())
+    }
+}
+
+impl<K, V> Deref for TypedTransactionalTree<K, V> {
+    type Target = TransactionalTree;
+
+    fn deref(&self) -> &Self::Target {
+        &self.tree
+    }
+}
+
+impl<K, V> TypedTransactionalTree<K, V> {
+    fn new(tree: TransactionalTree) -> Self {
+        Self { tree, _k: PhantomData, _v: PhantomData }
+    }
+}
+
+impl<K, V> TypedTransactionalTree<K, V>
+where
+    for<'a> K: Encoder<'a>,
+    for<'a> V: Encoder<'a>,
+{
+    /// Set a key to a new value
+    pub fn insert<'a, 'b>(
+        &self,
+        key: <K as Encoder<'a>>::In,
+        value: <V as Encoder<'b>>::In,
+    ) -> UnabortableTransactionResult<Option<IVec<V>>> {
+        self.tree
+            .insert(
+                IVec::<K>::encode(key).into_encoding::<()>(),
+                IVec::<V>::encode(value).into_encoding::<()>(),
+            )
+            .map(|res| res.map(IVec::into_encoding))
+    }
+
+    /// Remove a key
+    pub fn remove<'a>(
+        &self,
+        key: <K as Encoder<'a>>::In,
+    ) -> UnabortableTransactionResult<Option<IVec<V>>> {
+        self.tree
+            .remove(IVec::<K>::encode(key).into_encoding::<()>())
+            .map(|res| res.map(IVec::into_encoding))
+    }
+
+    /// Get the value associated with a key
+    pub fn get<'a>(
+        &self,
+        key: <K as Encoder<'a>>::In,
+    ) -> UnabortableTransactionResult<Option<IVec<V>>> {
+        self.tree.get(K::encode(key)).map(|res| res.map(IVec::into_encoding))
+    }
+
+    /// Returns the first key and value in the `Tree`, or
+    /// `None` if it is empty.
+    pub fn first(
+        &self,
+    ) -> UnabortableTransactionResult<Option<(IVec<K>, IVec<V>)>> {
+        self.tree
+            .first()
+            .map(|res| res.map(|(k, v)| (k.into_encoding(), v.into_encoding())))
+    }
+
+    /// Returns the last key and value in the `Tree`, or
+    /// `None` if it is empty.
+    pub fn last(
+        &self,
+    ) -> UnabortableTransactionResult<Option<(IVec<K>, IVec<V>)>> {
+        self.tree
+            .last()
+            .map(|res| res.map(|(k, v)| (k.into_encoding(), v.into_encoding())))
+    }
+
+    /// Retrieve the key and value before the provided key,
+    /// if one exists.
+    pub fn get_lt<'a>(
+        &self,
+        key: <K as Encoder<'a>>::In,
+    ) -> UnabortableTransactionResult<Option<(IVec<K>, IVec<V>)>> {
+        self.tree
+            .get_lt(K::encode(key))
+            .map(|res| res.map(|(k, v)| (k.into_encoding(), v.into_encoding())))
+    }
+
+    /// Retrieve the next key and value from the `Tree` after the
+    /// provided key.
+    pub fn get_gt<'a>(
+        &self,
+        key: <K as Encoder<'a>>::In,
+    ) -> UnabortableTransactionResult<Option<(IVec<K>, IVec<V>)>> {
+        self.tree
+            .get_gt(K::encode(key))
+            .map(|res| res.map(|(k, v)| (k.into_encoding(), v.into_encoding())))
+    }
+
+    /// Atomically apply a matching `TypedBatch`.
+    pub fn apply_batch(
+        &self,
+        batch: &TypedBatch<K, V>,
+    ) -> UnabortableTransactionResult<()> {
+        self.tree.apply_batch(&batch.batch)
+    }
+}
+
+impl<E, K, V> Transactional<E> for &TypedTree<K, V> {
+    type View = TypedTransactionalTree<K, V>;
+
+    fn make_overlay(&self) -> Result<TransactionalTrees> {
+        Transactional::<E>::make_overlay(&self.tree)
+    }
+
+    fn view_overlay(overlay: &TransactionalTrees) -> Self::View {
+        TypedTransactionalTree::new(<Tree as Transactional<E>>::view_overlay(
+            overlay,
+        ))
+    }
+}
+
+impl<E, K, V> Transactional<E> for TypedTree<K, V> {
+    type View = TypedTransactionalTree<K, V>;
+
+    fn make_overlay(&self) -> Result<TransactionalTrees> {
+        Transactional::<E>::make_overlay(&self.tree)
+    }
+
+    fn view_overlay(overlay: &TransactionalTrees) -> Self::View {
+        TypedTransactionalTree::new(<Tree as Transactional<E>>::view_overlay(
+            overlay,
+        ))
+    }
+}
+
+// expands to the type of the underlying `Tree` for each index
+macro_rules! tree_ref {
+    ($index:tt) => {
+        &Tree
+    };
+}
+
+// Transactions on tuples of `TypedTree`s, which may each have
+// different encodings, run as transactions on tuples of the
+// underlying `Tree`s.
+macro_rules! impl_transactional_tuple_typed_trees {
+    ($(($index:tt, $k:ident, $v:ident)),+) => {
+        impl<E, $($k, $v),+> Transactional<E>
+            for ($(&TypedTree<$k, $v>,)+)
+        {
+            type View = ($(TypedTransactionalTree<$k, $v>,)+);
+
+            fn make_overlay(&self) -> Result<TransactionalTrees> {
+                let trees = ($(&self.$index.tree,)+);
+                Transactional::<E>::make_overlay(&trees)
+            }
+
+            fn view_overlay(overlay: &TransactionalTrees) -> Self::View {
+                let views =
+                    <($(tree_ref!($index),)+) as Transactional<E>>::view_overlay(
+                        overlay,
+                    );
+                ($(TypedTransactionalTree::new(views.$index),)+)
+            }
+        }
+    };
+}
+
+impl_transactional_tuple_typed_trees!((0, K0, V0));
+impl_transactional_tuple_typed_trees!((0, K0, V0), (1, K1, V1));
+impl_transactional_tuple_typed_trees!((0, K0, V0), (1, K1, V1), (2, K2, V2));
+impl_transactional_tuple_typed_trees!(
+    (0, K0, V0),
+    (1, K1, V1),
+    (2, K2, V2),
+    (3, K3, V3)
+);
+impl_transactional_tuple_typed_trees!(
+    (0, K0, V0),
+    (1, K1, V1),
+    (2, K2, V2),
+    (3, K3, V3),
+    (4, K4, V4)
+);
+impl_transactional_tuple_typed_trees!(
+    (0, K0, V0),
+    (1, K1, V1),
+    (2, K2, V2),
+    (3, K3, V3),
+    (4, K4, V4),
+    (5, K5, V5)
+);
+impl_transactional_tuple_typed_trees!(
+    (0, K0, V0),
+    (1, K1, V1),
+    (2, K2, V2),
+    (3, K3, V3),
+    (4, K4, V4),
+    (5, K5, V5),
+    (6, K6, V6)
+);
+impl_transactional_tuple_typed_trees!(
+    (0, K0, V0),
+    (1, K1, V1),
+    (2, K2, V2),
+    (3, K3, V3),
+    (4, K4, V4),
+    (5, K5, V5),
+    (6, K6, V6),
+    (7, K7, V7)
+);
+impl_transactional_tuple_typed_trees!(
+    (0, K0, V0),
+    (1, K1, V1),
+    (2, K2, V2),
+    (3, K3, V3),
+    (4, K4, V4),
+    (5, K5, V5),
+    (6, K6, V6),
+    (7, K7, V7),
+    (8, K8, V8)
+);
+impl_transactional_tuple_typed_trees!(
+    (0, K0, V0),
+    (1, K1, V1),
+    (2, K2, V2),
+    (3, K3, V3),
+    (4, K4, V4),
+    (5, K5, V5),
+    (6, K6, V6),
+    (7, K7, V7),
+    (8, K8, V8),
+    (9, K9, V9)
+);
+impl_transactional_tuple_typed_trees!(
+    (0, K0, V0),
+    (1, K1, V1),
+    (2, K2, V2),
+    (3, K3, V3),
+    (4, K4, V4),
+    (5, K5, V5),
+    (6, K6, V6),
+    (7, K7, V7),
+    (8, K8, V8),
+    (9, K9, V9),
+    (10, K10, V10)
+);
+impl_transactional_tuple_typed_trees!(
+    (0, K0, V0),
+    (1, K1, V1),
+    (2, K2, V2),
+    (3, K3, V3),
+    (4, K4, V4),
+    (5, K5, V5),
+    (6, K6, V6),
+    (7, K7, V7),
+    (8, K8, V8),
+    (9, K9, V9),
+    (10, K10, V10),
+    (11, K11, V11)
+);
+impl_transactional_tuple_typed_trees!(
+    (0, K0, V0),
+    (1, K1, V1),
+    (2, K2, V2),
+    (3, K3, V3),
+    (4, K4, V4),
+    (5, K5, V5),
+    (6, K6, V6),
+    (7, K7, V7),
+    (8, K8, V8),
+    (9, K9, V9),
+    (10, K10, V10),
+    (11, K11, V11),
+    (12, K12, V12)
+);
+impl_transactional_tuple_typed_trees!(
+    (0, K0, V0),
+    (1, K1, V1),
+    (2, K2, V2),
+    (3, K3, V3),
+    (4, K4, V4),
+    (5, K5, V5),
+    (6, K6, V6),
+    (7, K7, V7),
+    (8, K8, V8),
+    (9, K9, V9),
+    (10, K10, V10),
+    (11, K11, V11),
+    (12, K12, V12),
+    (13, K13, V13)
+);
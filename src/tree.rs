@@ -1,9 +1,11 @@
 use std::{
     borrow::Cow,
+    convert::TryInto,
     fmt::{self, Debug},
     num::NonZeroU64,
     ops::{self, Deref, RangeBounds},
-    sync::atomic::Ordering::SeqCst,
+    sync::atomic::{AtomicBool, Ordering::SeqCst},
+    time::Duration,
 };
 
 use parking_lot::RwLock;
@@ -47,6 +49,26 @@ fn bounds_error() -> Result<()> {
     ))
 }
 
+// Adds to a counter written by `Tree::increment`, which is stored
+// as a little-endian `i64` like the `u64`s of the merge operators
+// in `merge_operators`, and is zero when the key is not present.
+pub(crate) fn add_to_counter(
+    key: &[u8],
+    current: Option<&[u8]>,
+    delta: i64,
+) -> Result<i64> {
+    let count = match current {
+        None => 0,
+        Some(bytes) if bytes.len() == 8 => {
+            i64::from_le_bytes(bytes.try_into().unwrap())
+        }
+        Some(_) => return Err(Error::NotACounter(IVec::from(key))),
+    };
+    count
+        .checked_add(delta)
+        .ok_or_else(|| Error::CounterOverflow(IVec::from(key)))
+}
+
 /// A flash-sympathetic persistent lock-free B+ tree.
 ///
 /// A `Tree` represents a single logical keyspace / namespace / bucket.
@@ -106,6 +128,28 @@ pub struct TreeInner {
     pub(crate) subscribers: Subscribers,
     pub(crate) root: AtomicU64,
     pub(crate) merge_operator: RwLock<Option<Box<dyn MergeOperator>>>,
+    // the persisted name of this tree's merge operator, if nothing
+    // was registered under it when the tree was loaded
+    pub(crate) missing_merge_operator: RwLock<Option<IVec>>,
+    // the internal tree that committed writes are recorded
+    // in, if the change feed is enabled
+    pub(crate) change_feed: Option<Arc<change_feed::ChangeFeed>>,
+    // the internal tree that the deadlines of keys inserted
+    // with `insert_with_ttl` are stored in, once it is created
+    pub(crate) expiry: Option<Arc<meta::LazyTree>>,
+    // set once any key in this tree may have a deadline, so
+    // that trees without any skip looking them up
+    pub(crate) has_ttl: AtomicBool,
+    // the internal tree that the chunks of values written with
+    // `open_blob_writer` are stored in, once it is created
+    pub(crate) blobs: Option<Arc<meta::LazyTree>>,
+    // set once any key in this tree may hold a blob, so that
+    // trees without any skip looking them up
+    pub(crate) has_blobs: AtomicBool,
+    // the items of this tree that are leased by `lease_min_blocking`
+    pub(crate) leases: queue::Leases,
+    // the secondary indexes that are updated with every write
+    pub(crate) indexes: index::Indexes,
 }
 
 impl Drop for TreeInner {
@@ -155,6 +199,8 @@ impl Tree {
         let value = value.into();
         let mut guard = pin();
         let _cc = concurrency_control::read();
+        let _claim = self.indexes.claim(self, key.as_ref(), Some(&value))?;
+        let write_peg = self.write_peg(&guard)?;
         loop {
             trace!("setting key {:?}", key.as_ref());
             if let Ok(res) = self.insert_inner(
@@ -163,11 +209,152 @@ impl Tree {
                 false,
                 &mut guard,
             )? {
+                if let Some(peg) = write_peg {
+                    peg.seal_batch()?;
+                }
                 return Ok(res);
             }
         }
     }
 
+    /// Insert a key to a new value that expires after the provided
+    /// time-to-live, returning the last value if it was set.
+    ///
+    /// Expired keys are never returned by reads. When the `Db` is
+    /// opened with `Config::reap_expired_every_ms`, they are also
+    /// removed in the background as often as it specifies, which
+    /// sends the usual removal `Event` to any `Subscriber`s.
+    /// Calling `insert_with_ttl` again replaces the time-to-live of
+    /// a key, and writing to it in any other way removes it.
+    /// Deadlines are based on the system clock and are stored
+    /// durably, so keys also expire across restarts.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use std::time::Duration;
+    ///
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// db.insert_with_ttl(b"session", b"token", Duration::from_millis(10))?;
+    /// assert!(db.contains_key(b"session")?);
+    ///
+    /// std::thread::sleep(Duration::from_millis(20));
+    /// assert_eq!(db.get(b"session")?, None);
+    /// # Ok(()) }
+    /// ```
+    pub fn insert_with_ttl<K, V>(
+        &self,
+        key: K,
+        value: V,
+        ttl: Duration,
+    ) -> Result<Option<IVec>>
+    where
+        K: AsRef<[u8]>,
+        V: Into<IVec>,
+    {
+        self.context.verify_writable()?;
+        let mut guard = pin();
+        let expiry = self.expiry.as_ref().unwrap().get_or_create(&guard)?;
+        let value = value.into();
+        let expiry_key = ttl::expiry_key(&self.tree_id, key.as_ref());
+        let deadline = ttl::deadline_after(ttl);
+
+        let _cc = concurrency_control::read();
+        let _claim = self.indexes.claim(self, key.as_ref(), Some(&value))?;
+        self.has_ttl.store(true, SeqCst);
+
+        // the value and its deadline are recovered atomically
+        let peg = self.context.pin_log(&guard)?;
+        let last_value = loop {
+            trace!("setting key {:?} with ttl {:?}", key.as_ref(), ttl);
+            if let Ok(res) = self.insert_inner(
+                key.as_ref(),
+                Some(value.clone()),
+                false,
+                &mut guard,
+            )? {
+                break res;
+            }
+        };
+        ttl::set_deadline(&expiry, &expiry_key, &deadline, &mut guard)?;
+        peg.seal_batch()?;
+
+        Ok(last_value)
+    }
+
+    /// Open a `BlobWriter` that streams a large value into the
+    /// provided key in chunks, without holding all of it in memory.
+    ///
+    /// Nothing is visible until `BlobWriter::finish` is called, which
+    /// atomically replaces the value of the key with the length of
+    /// the blob as a big-endian `u64`, and the blob itself is read
+    /// back with `open_blob_reader`. Writing to the key in any other
+    /// way, or removing it, removes the blob.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use std::io::{Read, Write};
+    ///
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// let mut writer = db.open_blob_writer(b"video")?;
+    /// writer.write_all(&[7; 100_000])?;
+    /// writer.finish()?;
+    ///
+    /// let len = db.get(b"video")?.unwrap();
+    /// assert_eq!(&*len, &100_000_u64.to_be_bytes());
+    ///
+    /// let mut reader = db.open_blob_reader(b"video")?.unwrap();
+    /// let mut value = vec![];
+    /// reader.read_to_end(&mut value)?;
+    /// assert_eq!(value, vec![7; 100_000]);
+    /// # Ok(()) }
+    /// ```
+    pub fn open_blob_writer<K: AsRef<[u8]>>(
+        &self,
+        key: K,
+    ) -> Result<BlobWriter> {
+        BlobWriter::new(self, key.as_ref())
+    }
+
+    /// Open a `BlobReader` that streams the blob that was written to
+    /// the provided key with `open_blob_writer` in chunks, which
+    /// also implements `Seek`. Returns `None` if the key does not
+    /// hold a blob.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use std::io::{Read, Seek, SeekFrom, Write};
+    ///
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// let mut writer = db.open_blob_writer(b"log")?;
+    /// writer.write_all(b"first line\nsecond line\n")?;
+    /// writer.finish()?;
+    ///
+    /// let mut reader = db.open_blob_reader(b"log")?.unwrap();
+    /// reader.seek(SeekFrom::Start(11))?;
+    /// let mut line = String::new();
+    /// reader.read_to_string(&mut line)?;
+    /// assert_eq!(line, "second line\n");
+    ///
+    /// db.insert(b"log", b"small")?;
+    /// assert!(db.open_blob_reader(b"log")?.is_none());
+    /// # Ok(()) }
+    /// ```
+    pub fn open_blob_reader<K: AsRef<[u8]>>(
+        &self,
+        key: K,
+    ) -> Result<Option<BlobReader>> {
+        BlobReader::open(self, key.as_ref())
+    }
+
     pub(crate) fn insert_inner(
         &self,
         key: &[u8],
@@ -197,10 +384,14 @@ impl Tree {
 
         let (encoded_key, last_value) = node_view.node_kv_pair(key.as_ref());
         let last_value = last_value.map(IVec::from);
+        let expired = last_value.is_some() && self.is_expired(key)?;
 
         if value == last_value {
-            // short-circuit a no-op set or delete
-            return Ok(Ok(value));
+            // short-circuit a no-op set or delete, which still
+            // removes any time-to-live or blob from the key
+            self.clear_ttl(key, guard)?;
+            self.clear_blob(key, guard)?;
+            return Ok(Ok(if expired { None } else { value }));
         }
 
         let frag = if let Some(value) = value.clone() {
@@ -212,11 +403,21 @@ impl Tree {
             Link::Del(encoded_key)
         };
 
+        let _in_flight = self.begin_change();
         let link =
             self.context.pagecache.link(pid, node_view.0, frag, guard)?;
 
-        if link.is_ok() {
+        if let Ok(new_view) = link {
             // success
+            let lsn = new_view.last_lsn();
+            self.record_change(
+                key,
+                last_value.as_deref(),
+                value.clone(),
+                lsn,
+                guard,
+            )?;
+
             if let Some(Some(res)) = subscriber_reservation.take() {
                 let event = subscriber::Event::single_update(
                     self.clone(),
@@ -227,7 +428,7 @@ impl Tree {
                 res.complete(&event);
             }
 
-            Ok(Ok(last_value))
+            Ok(Ok(if expired { None } else { last_value }))
         } else {
             #[cfg(feature = "metrics")]
             M.tree_looped();
@@ -359,6 +560,77 @@ impl Tree {
         Transactional::transaction(&self, f)
     }
 
+    /// Perform a multi-key serializable transaction like
+    /// `transaction`, retrying the closure only within the limits
+    /// of the provided `TransactionOptions`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use sled::{transaction::{TransactionOptions, TransactionResult}, Config};
+    /// # fn main() -> TransactionResult<()> {
+    /// use std::time::Duration;
+    ///
+    /// # let config = Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// let options = TransactionOptions::default()
+    ///     .backoff(true)
+    ///     .timeout(Duration::from_secs(1));
+    ///
+    /// let old = db.transaction_with_options(&options, |tx| {
+    ///     Ok(tx.insert(b"k1", b"cats")?)
+    /// })?;
+    ///
+    /// assert_eq!(old, None);
+    /// # Ok(()) }
+    /// ```
+    pub fn transaction_with_options<F, A, E>(
+        &self,
+        options: &transaction::TransactionOptions,
+        f: F,
+    ) -> transaction::TransactionResult<A, E>
+    where
+        F: Fn(
+            &transaction::TransactionalTree,
+        ) -> transaction::ConflictableTransactionResult<A, E>,
+    {
+        Transactional::transaction_with_options(&self, options, f)
+    }
+
+    /// Perform a transaction that may only read from this `Tree`,
+    /// running the closure exactly once. See
+    /// `Transactional::read_transaction` for the consistency it
+    /// provides.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # use sled::{transaction::TransactionResult, Config};
+    /// # fn main() -> TransactionResult<()> {
+    /// # let config = Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// db.insert(b"k1", b"cats")?;
+    /// db.insert(b"k2", b"dogs")?;
+    ///
+    /// let pair = db.read_transaction(|tx_db| {
+    ///     Ok((tx_db.get(b"k1")?, tx_db.get(b"k2")?))
+    /// })?;
+    ///
+    /// assert_eq!(pair, (Some(b"cats".into()), Some(b"dogs".into())));
+    /// # Ok(()) }
+    /// ```
+    pub fn read_transaction<F, A, E>(
+        &self,
+        f: F,
+    ) -> transaction::TransactionResult<A, E>
+    where
+        F: FnOnce(
+            &transaction::ReadOnlyTransactionalTree,
+        ) -> transaction::ConflictableTransactionResult<A, E>,
+    {
+        Transactional::read_transaction(&self, f)
+    }
+
     /// Create a new batched update that can be
     /// atomically applied.
     ///
@@ -398,6 +670,8 @@ impl Tree {
         guard: &mut Guard,
     ) -> Result<()> {
         let peg = if transaction_batch.is_none() {
+            // transactions check every tree before committing any
+            self.indexes.check_unique(self, &batch)?;
             Some(self.context.pin_log(guard)?)
         } else {
             None
@@ -405,6 +679,9 @@ impl Tree {
 
         trace!("applying batch {:?}", batch);
 
+        let mut batch = batch;
+        self.remove_ranges_inner(&mut batch, guard)?;
+
         let mut subscriber_reservation = self.subscribers.reserve_batch(&batch);
 
         for (k, v_opt) in &batch.writes {
@@ -509,6 +786,113 @@ impl Tree {
         Ok(ret)
     }
 
+    /// Retrieve the values of many keys at once, returned in the same
+    /// order as the provided keys. The keys are looked up in sorted
+    /// order while reusing a single guard, so that keys which share
+    /// a leaf only descend the `Tree` once, which is much cheaper
+    /// than calling `get` for each of them.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// db.insert(b"a", b"1")?;
+    /// db.insert(b"c", b"3")?;
+    ///
+    /// assert_eq!(
+    ///     db.get_many(&[b"c", b"b", b"a"])?,
+    ///     vec![Some(sled::IVec::from(b"3")), None, Some(sled::IVec::from(b"1"))]
+    /// );
+    /// # Ok(()) }
+    /// ```
+    pub fn get_many<K, I>(&self, keys: I) -> Result<Vec<Option<IVec>>>
+    where
+        K: AsRef<[u8]>,
+        I: IntoIterator<Item = K>,
+    {
+        self.get_many_zero_copy(keys, |value_opt| value_opt.map(IVec::from))
+    }
+
+    /// Pass the result of getting each of many keys' values to a
+    /// closure without making a new allocation, like `get_zero_copy`,
+    /// while looking the keys up like `get_many`. The closure is
+    /// called once per key in sorted order, and what it returns is
+    /// collected in the same order as the provided keys.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// db.insert(b"a", vec![0; 10])?;
+    /// db.insert(b"b", vec![0; 20])?;
+    ///
+    /// let lens = db.get_many_zero_copy(&[b"b", b"z", b"a"], |value_opt| {
+    ///     value_opt.map(<[u8]>::len)
+    /// })?;
+    /// assert_eq!(lens, vec![Some(20), None, Some(10)]);
+    /// # Ok(()) }
+    /// ```
+    pub fn get_many_zero_copy<K, I, B, F>(
+        &self,
+        keys: I,
+        mut f: F,
+    ) -> Result<Vec<B>>
+    where
+        K: AsRef<[u8]>,
+        I: IntoIterator<Item = K>,
+        F: FnMut(Option<&[u8]>) -> B,
+    {
+        let all_keys: Vec<K> = keys.into_iter().collect();
+        let mut order: Vec<usize> = (0..all_keys.len()).collect();
+        order.sort_unstable_by(|a, b| {
+            all_keys[*a].as_ref().cmp(all_keys[*b].as_ref())
+        });
+
+        let guard = pin();
+        let _cc = concurrency_control::read();
+
+        let mut results: Vec<Option<B>> =
+            all_keys.iter().map(|_| None).collect();
+
+        // the leaf that the last key was found in, which is reused
+        // for the following keys as long as they fall within it
+        let mut leaf: Option<View<'_>> = None;
+
+        for idx in order {
+            #[cfg(feature = "metrics")]
+            let _measure = Measure::new(&M.tree_get);
+
+            let key = all_keys[idx].as_ref();
+            trace!("getting key {:?}", key);
+
+            let reusable = if let Some(view) = &leaf {
+                let below_hi =
+                    if let Some(hi) = view.hi() { key < hi } else { true };
+                view.lo() <= key && below_hi
+            } else {
+                false
+            };
+            if !reusable {
+                leaf = Some(self.view_for_key(key, &guard)?);
+            }
+            let view = leaf.as_ref().unwrap();
+
+            let value_opt = view.node_kv_pair(key).1;
+            let ret = if value_opt.is_some() && self.is_expired(key)? {
+                f(None)
+            } else {
+                f(value_opt)
+            };
+            results[idx] = Some(ret);
+        }
+
+        Ok(results.into_iter().map(Option::unwrap).collect())
+    }
+
     pub(crate) fn get_inner(
         &self,
         key: &[u8],
@@ -525,6 +909,10 @@ impl Tree {
         let pair = node_view.node_kv_pair(key.as_ref());
         let val = pair.1.map(IVec::from);
 
+        if val.is_some() && self.is_expired(key)? {
+            return Ok(Ok(None));
+        }
+
         Ok(Ok(val))
     }
 
@@ -544,17 +932,132 @@ impl Tree {
     pub fn remove<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<IVec>> {
         let mut guard = pin();
         let _cc = concurrency_control::read();
+        let write_peg = self.write_peg(&guard)?;
         loop {
             trace!("removing key {:?}", key.as_ref());
 
             if let Ok(res) =
                 self.insert_inner(key.as_ref(), None, false, &mut guard)?
             {
+                if let Some(peg) = write_peg {
+                    peg.seal_batch()?;
+                }
                 return Ok(res);
             }
         }
     }
 
+    /// Atomically remove every key that falls within the provided
+    /// range. Each affected leaf node is rewritten once rather than
+    /// logging a separate deletion for every key, and `Subscriber`s
+    /// watching any of the removed keys receive a single `Event`
+    /// containing all of the removals.
+    ///
+    /// When the change feed is enabled with `Config::change_feed`,
+    /// or the `Tree` has an `Index`, a separate deletion is logged
+    /// for every key instead, so that each removal can be recorded
+    /// in the feed and removed from the index.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// for i in 0..10_u8 {
+    ///     db.insert(&[i], vec![i])?;
+    /// }
+    ///
+    /// db.remove_range::<&[u8], _>(&[2][..]..&[8][..])?;
+    ///
+    /// let remaining: Vec<_> = db.iter().keys().map(|k| k.unwrap()).collect();
+    /// assert_eq!(remaining, vec![vec![0], vec![1], vec![8], vec![9]]);
+    /// # Ok(()) }
+    /// ```
+    pub fn remove_range<K, R>(&self, range: R) -> Result<()>
+    where
+        K: AsRef<[u8]>,
+        R: RangeBounds<K>,
+    {
+        let mut batch = Batch::default();
+        batch.remove_range(range);
+        self.apply_batch(batch)
+    }
+
+    /// Removes the ranges recorded in a `Batch` by rewriting each
+    /// leaf that they overlap, and records the keys that were
+    /// removed as deletions in the `Batch` so that they are
+    /// included in any `Event` generated for it. Point writes
+    /// already present in the `Batch` are left untouched, as they
+    /// logically happened after the range removal.
+    ///
+    /// Must be called while holding the exclusive
+    /// concurrency control lock, and while the log is pinned.
+    pub(crate) fn remove_ranges_inner(
+        &self,
+        batch: &mut Batch,
+        guard: &Guard,
+    ) -> Result<()> {
+        for range in std::mem::take(&mut batch.removed_ranges) {
+            let mut cursor = match range.start_bound() {
+                ops::Bound::Included(lo) | ops::Bound::Excluded(lo) => {
+                    lo.clone()
+                }
+                ops::Bound::Unbounded => IVec::default(),
+            };
+
+            loop {
+                let view = self.view_for_key(&cursor, guard)?;
+
+                let (node, removed) = view.remove_range(&range);
+
+                // when the change feed is enabled, each removal needs
+                // its own LSN, and when the tree is indexed or has keys
+                // with deadlines or blobs, each one needs its entries
+                // elsewhere removed, so the leaf is left alone and the
+                // keys are removed one at a time as part of the batch.
+                let per_key = self.change_feed.is_some()
+                    || !self.indexes.is_empty()
+                    || self.has_ttl.load(SeqCst)
+                    || self.has_blobs.load(SeqCst);
+
+                if per_key {
+                    for key in removed {
+                        batch.writes.entry(key).or_insert(None);
+                    }
+                } else if !removed.is_empty() {
+                    let replace = self.context.pagecache.replace(
+                        view.pid,
+                        view.node_view.0,
+                        &node,
+                        guard,
+                    )?;
+
+                    if replace.is_err() {
+                        // the leaf changed underneath us, so retry it
+                        continue;
+                    }
+
+                    // the keys are already gone from the leaf, so
+                    // they are only reported to subscribers
+                    for key in removed {
+                        if !batch.writes.contains_key(&key) {
+                            batch.removed_keys.insert(key, None);
+                        }
+                    }
+                }
+
+                if view.contains_upper_bound(&range.1) {
+                    break;
+                }
+
+                cursor = IVec::from(view.hi().unwrap());
+            }
+        }
+
+        Ok(())
+    }
+
     /// Compare and swap. Capable of unique creation, conditional modification,
     /// or deletion. If old is `None`, this will only set the value if it
     /// doesn't exist yet. If new is `None`, will delete the value if old is
@@ -621,8 +1124,9 @@ impl Tree {
         #[cfg(feature = "metrics")]
         let _measure = Measure::new(&M.tree_cas);
 
-        let guard = pin();
+        let mut guard = pin();
         let _cc = concurrency_control::read();
+        let write_peg = self.write_peg(&guard)?;
 
         let new = new.map(Into::into);
 
@@ -632,8 +1136,12 @@ impl Tree {
             let View { pid, node_view, .. } =
                 self.view_for_key(key.as_ref(), &guard)?;
 
-            let (encoded_key, current_value) =
+            let (encoded_key, stored_value) =
                 node_view.node_kv_pair(key.as_ref());
+            let mut current_value = stored_value;
+            if current_value.is_some() && self.is_expired(key.as_ref())? {
+                current_value = None;
+            }
             let matches = match (old.as_ref(), &current_value) {
                 (None, None) => true,
                 (Some(o), Some(c)) => o.as_ref() == &**c,
@@ -655,6 +1163,12 @@ impl Tree {
                 return Ok(Ok(()));
             }
 
+            let _claim = self.indexes.claim(
+                self,
+                key.as_ref(),
+                new.as_ref().map(AsRef::as_ref),
+            )?;
+            let last_value = stored_value.map(IVec::from);
             let mut subscriber_reservation = self.subscribers.reserve(&key);
 
             let frag = if let Some(ref new) = new {
@@ -662,10 +1176,23 @@ impl Tree {
             } else {
                 Link::Del(encoded_key)
             };
+            let _in_flight = self.begin_change();
             let link =
                 self.context.pagecache.link(pid, node_view.0, frag, &guard)?;
 
-            if link.is_ok() {
+            if let Ok(new_view) = link {
+                let lsn = new_view.last_lsn();
+                self.record_change(
+                    key.as_ref(),
+                    last_value.as_deref(),
+                    new.clone(),
+                    lsn,
+                    &mut guard,
+                )?;
+                if let Some(peg) = write_peg {
+                    peg.seal_batch()?;
+                }
+
                 if let Some(res) = subscriber_reservation.take() {
                     let event = subscriber::Event::single_update(
                         self.clone(),
@@ -1119,8 +1646,12 @@ impl Tree {
         V: AsRef<[u8]>,
     {
         let _cc = concurrency_control::read();
+        let write_peg = self.write_peg(&pin())?;
         loop {
             if let Ok(merge) = self.merge_inner(key.as_ref(), value.as_ref())? {
+                if let Some(peg) = write_peg {
+                    peg.seal_batch()?;
+                }
                 return Ok(merge);
             }
         }
@@ -1137,6 +1668,8 @@ impl Tree {
 
         let merge_operator_opt = self.merge_operator.read();
 
+        self.verify_merge_operator()?;
+
         if merge_operator_opt.is_none() {
             return Err(Error::Unsupported(
                 "must set a merge operator on this Tree \
@@ -1148,32 +1681,68 @@ impl Tree {
 
         let merge_operator = merge_operator_opt.as_ref().unwrap();
 
+        self.read_modify_write_inner(key, |current_value| {
+            Ok(merge_operator(key, current_value, value).map(IVec::from))
+        })
+    }
+
+    // Replaces the value of a key with the one that the provided
+    // function computes from its current value, directly on the
+    // leaf that the key is found in, calling the function again
+    // if the leaf changes before the new value is linked into it.
+    fn read_modify_write_inner<F>(
+        &self,
+        key: &[u8],
+        mut f: F,
+    ) -> Result<Conflictable<Option<IVec>>>
+    where
+        F: FnMut(Option<&[u8]>) -> Result<Option<IVec>>,
+    {
         loop {
-            let guard = pin();
+            let mut guard = pin();
             let View { pid, node_view, .. } =
                 self.view_for_key(key.as_ref(), &guard)?;
 
-            let (encoded_key, current_value) =
+            let (encoded_key, stored_value) =
                 node_view.node_kv_pair(key.as_ref());
-            let tmp = current_value.as_ref().map(AsRef::as_ref);
-            let new = merge_operator(key, tmp, value).map(IVec::from);
+            let mut current_value = stored_value;
+            if current_value.is_some() && self.is_expired(key)? {
+                current_value = None;
+            }
+            let new = f(current_value)?;
 
             if new.as_ref().map(AsRef::as_ref) == current_value {
                 // short-circuit no-op write
                 return Ok(Ok(new));
             }
 
+            let _claim = self.indexes.claim(
+                self,
+                key,
+                new.as_ref().map(AsRef::as_ref),
+            )?;
+            let last_value = stored_value.map(IVec::from);
             let mut subscriber_reservation = self.subscribers.reserve(&key);
 
-            let frag = if let Some(ref new) = new {
-                Link::Set(encoded_key, new.clone())
+            let frag = if let Some(ref new_value) = new {
+                Link::Set(encoded_key, new_value.clone())
             } else {
                 Link::Del(encoded_key)
             };
+            let _in_flight = self.begin_change();
             let link =
                 self.context.pagecache.link(pid, node_view.0, frag, &guard)?;
 
-            if link.is_ok() {
+            if let Ok(new_view) = link {
+                let lsn = new_view.last_lsn();
+                self.record_change(
+                    key,
+                    last_value.as_deref(),
+                    new.clone(),
+                    lsn,
+                    &mut guard,
+                )?;
+
                 if let Some(res) = subscriber_reservation.take() {
                     let event = subscriber::Event::single_update(
                         self.clone(),
@@ -1191,6 +1760,58 @@ impl Tree {
         }
     }
 
+    /// Atomically add `delta` to the counter stored under the key,
+    /// returning its new value. Counters are stored as 8-byte
+    /// little-endian `i64`s and start at zero when the key is not
+    /// present.
+    ///
+    /// Returns `Error::NotACounter` if the existing value is not
+    /// 8 bytes long, and `Error::CounterOverflow` if the counter
+    /// would overflow, in which case the value is left unchanged.
+    ///
+    /// Each increment reads the counter and links its new value
+    /// onto the leaf that holds it, retrying if the leaf changes
+    /// in between, so it never rewrites the whole leaf. It does
+    /// not store the delta as an unresolved merge, because the new
+    /// value has to be read to be returned and checked for
+    /// overflow either way.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// assert_eq!(db.increment(b"hits", 5)?, 5);
+    /// assert_eq!(db.increment(b"hits", -2)?, 3);
+    /// assert_eq!(db.get(b"hits")?, Some(sled::IVec::from(&3_i64.to_le_bytes())));
+    ///
+    /// db.insert(b"name", b"not a counter")?;
+    /// assert_eq!(
+    ///     db.increment(b"name", 1),
+    ///     Err(sled::Error::NotACounter(sled::IVec::from(b"name")))
+    /// );
+    /// # Ok(()) }
+    /// ```
+    pub fn increment<K: AsRef<[u8]>>(&self, key: K, delta: i64) -> Result<i64> {
+        let _cc = concurrency_control::read();
+        let write_peg = self.write_peg(&pin())?;
+        let mut count = 0;
+        loop {
+            if let Ok(_new) =
+                self.read_modify_write_inner(key.as_ref(), |current_value| {
+                    count = add_to_counter(key.as_ref(), current_value, delta)?;
+                    Ok(Some(IVec::from(&count.to_le_bytes())))
+                })?
+            {
+                if let Some(peg) = write_peg {
+                    peg.seal_batch()?;
+                }
+                return Ok(count);
+            }
+        }
+    }
+
     /// Sets a merge operator for use with the `merge` function.
     ///
     /// Merge state directly into a given key's value using the
@@ -1253,6 +1874,171 @@ impl Tree {
     ) {
         let mut mo_write = self.merge_operator.write();
         *mo_write = Some(Box::new(merge_operator));
+        *self.missing_merge_operator.write() = None;
+    }
+
+    /// Sets the merge operator to the one registered under `name`
+    /// with `Config::merge_operator`. Unlike `set_merge_operator`,
+    /// the name is persisted, and the same merge operator is set
+    /// each time this `Tree` is opened afterwards. If no merge
+    /// operator is registered under `name` when the database is
+    /// opened again, opening this `Tree` with `Db::open_tree` and
+    /// merging into it return `Error::Unsupported`, while the rest
+    /// of the database can be used as usual. Calling this with a
+    /// `name` that is not registered also returns
+    /// `Error::Unsupported`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// fn concatenate_merge(
+    ///   _key: &[u8],
+    ///   old_value: Option<&[u8]>,
+    ///   merged_bytes: &[u8]
+    /// ) -> Option<Vec<u8>> {
+    ///   let mut ret = old_value
+    ///     .map(|ov| ov.to_vec())
+    ///     .unwrap_or_else(|| vec![]);
+    ///
+    ///   ret.extend_from_slice(merged_bytes);
+    ///
+    ///   Some(ret)
+    /// }
+    ///
+    /// # let path = std::env::temp_dir().join("sled_named_merge_operator_doctest");
+    /// # let _ = std::fs::remove_dir_all(&path);
+    /// let config = sled::Config::new()
+    ///     .path(&path)
+    ///     .merge_operator("concatenate", concatenate_merge);
+    ///
+    /// {
+    ///     let db = config.open()?;
+    ///     db.set_named_merge_operator("concatenate")?;
+    ///     db.merge(b"k1", vec![0])?;
+    /// }
+    ///
+    /// // no need to set the merge operator again after reopening
+    /// let db = config.open()?;
+    /// db.merge(b"k1", vec![1])?;
+    /// assert_eq!(db.get(b"k1")?, Some(sled::IVec::from(vec![0, 1])));
+    /// # drop(db);
+    /// # std::fs::remove_dir_all(&path)?;
+    /// # Ok(()) }
+    /// ```
+    pub fn set_named_merge_operator(&self, name: &str) -> Result<()> {
+        self.context.verify_writable()?;
+
+        let name_bytes = name.as_bytes();
+        let merge_operator = self
+            .context
+            .merge_operators
+            .get(name_bytes)
+            .ok_or_else(|| meta::unregistered_merge_operator(name_bytes))?;
+
+        let guard = pin();
+        self.context.pagecache.set_merge_operator_in_meta(
+            &self.tree_id,
+            name_bytes,
+            &guard,
+        )?;
+
+        let mut mo_write = self.merge_operator.write();
+        *mo_write = Some(merge_operator);
+        *self.missing_merge_operator.write() = None;
+        Ok(())
+    }
+
+    // Returns an error naming the merge operator that was persisted
+    // for this tree if nothing was registered under that name when
+    // it was loaded, and no merge operator has been set since.
+    pub(crate) fn verify_merge_operator(&self) -> Result<()> {
+        if let Some(name) = &*self.missing_merge_operator.read() {
+            Err(meta::unregistered_merge_operator(name))
+        } else {
+            Ok(())
+        }
+    }
+
+    /// Creates a secondary `Index` of this `Tree` called `name`,
+    /// which stores each key under the index key that `extractor`
+    /// returns for it and its value, if any. The entries of the
+    /// index are kept in a separate internal `Tree`, and are
+    /// updated atomically with every write to this one, including
+    /// batches and transactions.
+    ///
+    /// Like `set_merge_operator`, the extractor is not persisted,
+    /// so the index must be created again each time this `Tree` is
+    /// opened. Creating it rebuilds its entries from every key in
+    /// this `Tree`, blocking writers until it is done, and replaces
+    /// any index with the same name, which returns errors from then
+    /// on. If creating it fails, any index with the same name is
+    /// left as it was.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// use sled::IVec;
+    ///
+    /// let by_len = db.create_index(b"by_len", |_key: &[u8], value: &[u8]| {
+    ///     Some((value.len() as u64).to_be_bytes().to_vec())
+    /// })?;
+    ///
+    /// db.insert(b"a", b"yo")?;
+    /// db.insert(b"b", b"hey")?;
+    /// assert_eq!(by_len.get(2_u64.to_be_bytes()).next(), Some(Ok((IVec::from(b"a"), IVec::from(b"yo")))));
+    ///
+    /// db.insert(b"a", b"hey")?;
+    /// assert_eq!(by_len.get(2_u64.to_be_bytes()).next(), None);
+    /// assert_eq!(by_len.get(3_u64.to_be_bytes()).count(), 2);
+    /// # Ok(()) }
+    /// ```
+    pub fn create_index<N, F>(&self, name: N, extractor: F) -> Result<Index>
+    where
+        N: AsRef<[u8]>,
+        F: IndexExtractor + 'static,
+    {
+        index::create(self, name.as_ref(), Box::new(extractor), false)
+    }
+
+    /// Creates a secondary `Index` of this `Tree` like
+    /// `create_index`, which never stores two keys under the same
+    /// index key. Any write that would do so, or a transaction that
+    /// contains one, is rejected with `Error::UniqueViolation`
+    /// without changing anything, as is creating the index if the
+    /// keys already in this `Tree` would do so.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// let by_email = db.create_unique_index(b"by_email", |_key: &[u8], value: &[u8]| {
+    ///     Some(value.to_vec())
+    /// })?;
+    ///
+    /// db.insert(b"alice", b"alice@example.com")?;
+    /// assert!(db.insert(b"mallory", b"alice@example.com").is_err());
+    /// assert_eq!(db.get(b"mallory")?, None);
+    ///
+    /// let (user, _email) = by_email.get(b"alice@example.com").next().unwrap()?;
+    /// assert_eq!(user, b"alice");
+    /// # Ok(()) }
+    /// ```
+    pub fn create_unique_index<N, F>(
+        &self,
+        name: N,
+        extractor: F,
+    ) -> Result<Index>
+    where
+        N: AsRef<[u8]>,
+        F: IndexExtractor + 'static,
+    {
+        index::create(self, name.as_ref(), Box::new(extractor), true)
     }
 
     /// Create a double-ended iterator over the tuples of keys and
@@ -1513,6 +2299,135 @@ impl Tree {
         }
     }
 
+    /// Atomically removes the minimum item whose key starts with the
+    /// provided prefix, waiting for one to be inserted if there is
+    /// none. Returns `None` if no item appears before the timeout
+    /// passes, or if the `Db` shuts down.
+    ///
+    /// Waiting parks on a `Subscriber` for the prefix rather than
+    /// polling, so this can be used with keys from `Db::generate_id`
+    /// as a durable work queue. Items that are leased with
+    /// `lease_min_blocking` are skipped.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use std::time::Duration;
+    ///
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// let producer = db.clone();
+    /// let thread = std::thread::spawn(move || {
+    ///     let id = producer.generate_id().unwrap();
+    ///     let mut key = b"jobs/".to_vec();
+    ///     key.extend_from_slice(&id.to_be_bytes());
+    ///     producer.insert(key, b"resize image").unwrap();
+    /// });
+    ///
+    /// let (_key, job) =
+    ///     db.pop_min_blocking(b"jobs/", Duration::from_secs(10))?.unwrap();
+    /// assert_eq!(job, b"resize image");
+    ///
+    /// assert_eq!(db.pop_min_blocking(b"jobs/", Duration::from_millis(10))?, None);
+    /// # thread.join().unwrap();
+    /// # Ok(()) }
+    /// ```
+    pub fn pop_min_blocking<P: AsRef<[u8]>>(
+        &self,
+        prefix: P,
+        timeout: Duration,
+    ) -> Result<Option<(IVec, IVec)>> {
+        queue::wait_for(self, prefix.as_ref(), timeout, || {
+            queue::pop_min(self, prefix.as_ref())
+        })
+    }
+
+    /// Asynchronously removes the minimum item whose key starts with
+    /// the provided prefix, waiting for one to be inserted if there
+    /// is none, like `pop_min_blocking` without a timeout. Returns
+    /// `None` if the `Db` shuts down.
+    ///
+    /// Items that are leased with `lease_min_blocking` are skipped,
+    /// and an item whose lease expires is only picked up once the
+    /// prefix is written to again.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # async fn foo() -> sled::Result<()> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// while let Some((_key, job)) = db.pop_min_async(b"jobs/").await? {
+    ///     /* run it */
+    /// }
+    /// # Ok(()) }
+    /// ```
+    pub async fn pop_min_async<P: AsRef<[u8]>>(
+        &self,
+        prefix: P,
+    ) -> Result<Option<(IVec, IVec)>> {
+        // subscribe before the first attempt, so that no write that
+        // happens after it is missed
+        let mut subscriber = self.watch_prefix(prefix.as_ref());
+        loop {
+            if let Some(item) = queue::pop_min(self, prefix.as_ref())? {
+                return Ok(Some(item));
+            }
+            if (&mut subscriber).await.is_none() {
+                return queue::pop_min(self, prefix.as_ref());
+            }
+        }
+    }
+
+    /// Leases the minimum item whose key starts with the provided
+    /// prefix, waiting for one to be inserted like `pop_min_blocking`
+    /// if there is none, but without removing it from the `Tree`.
+    ///
+    /// The leased item is hidden from every other lease and from
+    /// `pop_min_blocking` and `pop_min_async` until `Lease::complete`
+    /// removes it, or the lease is released or dropped, or the lease
+    /// duration passes, so that the item of a worker that crashes or
+    /// hangs is handed out again. Other reads and writes, including
+    /// `pop_min` and `pop_max`, still see it. Leases are only kept in
+    /// memory, and are all released when the `Db` is reopened.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use std::time::Duration;
+    ///
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// db.insert(b"jobs/1", b"send email")?;
+    /// let timeout = Duration::from_millis(10);
+    /// let lease_duration = Duration::from_secs(60);
+    ///
+    /// let lease = db.lease_min_blocking(b"jobs/", timeout, lease_duration)?.unwrap();
+    /// assert_eq!(lease.value(), b"send email");
+    /// assert!(db.lease_min_blocking(b"jobs/", timeout, lease_duration)?.is_none());
+    ///
+    /// // the worker failed, so the item is handed out again
+    /// lease.release();
+    /// let lease = db.lease_min_blocking(b"jobs/", timeout, lease_duration)?.unwrap();
+    ///
+    /// // the worker succeeded, so the item is removed
+    /// assert!(lease.complete()?);
+    /// assert!(db.get(b"jobs/1")?.is_none());
+    /// # Ok(()) }
+    /// ```
+    pub fn lease_min_blocking<P: AsRef<[u8]>>(
+        &self,
+        prefix: P,
+        timeout: Duration,
+        lease: Duration,
+    ) -> Result<Option<Lease>> {
+        queue::wait_for(self, prefix.as_ref(), timeout, || {
+            queue::lease_min(self, prefix.as_ref(), lease)
+        })
+    }
+
     /// Returns the number of elements in this tree.
     ///
     /// Beware: performs a full O(n) scan under the hood.
@@ -1553,6 +2468,37 @@ impl Tree {
         self.tree_id.clone()
     }
 
+    /// Take a consistent, read-only, point-in-time snapshot of
+    /// this `Tree`. Writes that happen after the snapshot is
+    /// taken are not visible through it. Use `Db::snapshot`
+    /// to capture several trees at the same instant.
+    ///
+    /// Taking a snapshot only blocks writers while the root
+    /// of the tree is recorded, and does not copy any data.
+    /// Pages that are rewritten while the snapshot is alive
+    /// keep their previous version in memory until it is
+    /// dropped.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// # let config = sled::Config::new().temporary(true);
+    /// # let db = config.open()?;
+    /// let tree = db.open_tree(b"reports")?;
+    /// tree.insert(b"a", vec![0])?;
+    ///
+    /// let snapshot = tree.snapshot()?;
+    /// tree.insert(b"b", vec![1])?;
+    ///
+    /// assert_eq!(snapshot.len(), 1);
+    /// assert_eq!(tree.len(), 2);
+    /// # Ok(()) }
+    /// ```
+    pub fn snapshot(&self) -> Result<TreeSnapshot> {
+        TreeSnapshot::capture(self)
+    }
+
     /// Switch to the typed API. See `[TypedTree]` for details and examples.
     ///
     /// # Examples
@@ -1592,6 +2538,121 @@ impl Tree {
         Ok(hasher.finalize())
     }
 
+    // Pins the log for a single write when the change feed is
+    // enabled, keys in this tree may have a time-to-live or hold
+    // a blob or it has indexes, so that the write and its record
+    // in the feed, the removal of its deadline or blob or its
+    // index entries are recovered atomically. Batches and
+    // transactions already pin the log for all of their writes.
+    fn write_peg(&self, guard: &Guard) -> Result<Option<RecoveryGuard<'_>>> {
+        if self.change_feed.is_some()
+            || self.has_ttl.load(SeqCst)
+            || self.has_blobs.load(SeqCst)
+            || !self.indexes.is_empty()
+        {
+            Ok(Some(self.context.pin_log(guard)?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    // Registers a write that is about to be committed with the
+    // change feed, if it is enabled, which must last until the
+    // write is recorded by `record_change`.
+    fn begin_change(&self) -> Option<change_feed::InFlight<'_>> {
+        self.change_feed.as_ref().map(|change_feed| change_feed.begin())
+    }
+
+    // Records a write that was committed at the provided LSN
+    // in the change feed, if it is enabled, moves the entries
+    // of the key in each index from its last value to the new
+    // one and removes any time-to-live or blob from the key.
+    fn record_change(
+        &self,
+        key: &[u8],
+        last_value: Option<&[u8]>,
+        value: Option<IVec>,
+        lsn: Lsn,
+        guard: &mut Guard,
+    ) -> Result<()> {
+        self.indexes.update(key, last_value, value.as_deref(), guard)?;
+        if let Some(change_feed) = &self.change_feed {
+            let feed_key = change_feed::feed_key(lsn);
+            let record = change_feed::encode(&self.tree_id, key, value);
+            while change_feed
+                .tree
+                .insert_inner(&feed_key, Some(record.clone()), true, guard)?
+                .is_err()
+            {}
+        }
+        self.clear_ttl(key, guard)?;
+        self.clear_blob(key, guard)
+    }
+
+    // Returns `true` if the key was inserted with `insert_with_ttl`
+    // and its deadline has passed, even if the reaper has not
+    // removed it yet.
+    pub(crate) fn is_expired(&self, key: &[u8]) -> Result<bool> {
+        if !self.has_ttl.load(SeqCst) {
+            return Ok(false);
+        }
+        let expiry = if let Some(expiry) = self.expiry_tree() {
+            expiry
+        } else {
+            return Ok(false);
+        };
+        let expiry_key = ttl::expiry_key(&self.tree_id, key);
+        match expiry.get_inner(&expiry_key, &mut pin())? {
+            Ok(Some(deadline)) => ttl::is_expired(&deadline),
+            _ => Ok(false),
+        }
+    }
+
+    fn clear_ttl(&self, key: &[u8], guard: &mut Guard) -> Result<()> {
+        if !self.has_ttl.load(SeqCst) {
+            return Ok(());
+        }
+        if let Some(expiry) = self.expiry_tree() {
+            let expiry_key = ttl::expiry_key(&self.tree_id, key);
+            ttl::clear_deadline(&expiry, &expiry_key, guard)?;
+        }
+        Ok(())
+    }
+
+    // Returns the internal tree that deadlines are stored in,
+    // if any key was ever inserted with a time-to-live.
+    pub(crate) fn expiry_tree(&self) -> Option<Tree> {
+        self.expiry.as_ref().and_then(|expiry| expiry.tree())
+    }
+
+    // Returns the internal tree that blobs are stored in, if any
+    // blob was ever written.
+    pub(crate) fn blobs_tree(&self) -> Option<Tree> {
+        self.blobs.as_ref().and_then(|blobs| blobs.tree())
+    }
+
+    // Removes the record of the blob that the key holds, if any,
+    // along with its chunks. A `BlobWriter` records the blob
+    // again after replacing the value of the key.
+    fn clear_blob(&self, key: &[u8], guard: &mut Guard) -> Result<()> {
+        if !self.has_blobs.load(SeqCst) {
+            return Ok(());
+        }
+        let blobs = self.blobs_tree().unwrap();
+        let owner_key = blob::owner_key(&self.tree_id, key);
+        let owner_opt = loop {
+            if let Ok(owner_opt) = blobs.get_inner(&owner_key, guard)? {
+                break owner_opt;
+            }
+        };
+        if let Some(owner) = owner_opt {
+            let (blob_id, _len) = blob::decode_owner(&owner)?;
+            while blobs.insert_inner(&owner_key, None, true, guard)?.is_err() {}
+            blob::remove_chunks(&blobs, blob_id, guard)?;
+        }
+        Ok(())
+    }
+
     fn split_node<'g>(
         &self,
         view: &View<'g>,
@@ -1743,7 +2804,7 @@ impl Tree {
             if let Some(node_view) = &node_view_opt {
                 let size = node_view.0.log_size();
                 let view = View { node_view: *node_view, pid, size };
-                if view.merging_child.is_some() {
+                if view.merging_child.is_some() && !self.context.read_only {
                     self.merge_node(
                         &view,
                         view.merging_child.unwrap().get(),
@@ -1758,6 +2819,106 @@ impl Tree {
         }
     }
 
+    // Removes the root of a tree from the meta page, returning the
+    // leftmost page of each level of the tree, which are passed to
+    // `gc_pages` once no thread can be using them anymore.
+    pub(crate) fn unlink_tree(
+        &self,
+        name: &[u8],
+        guard: &Guard,
+    ) -> Result<Vec<PageId>> {
+        let mut root_id =
+            Some(self.context.pagecache.meta_pid_for_name(name, guard)?);
+
+        let leftmost_chain = self.leftmost_chain(root_id.unwrap(), guard)?;
+
+        loop {
+            let res = self
+                .context
+                .pagecache
+                .cas_root_in_meta(name, root_id, None, guard)?;
+
+            if let Err(actual_root) = res {
+                root_id = actual_root;
+            } else {
+                break;
+            }
+        }
+
+        Ok(leftmost_chain)
+    }
+
+    // Remove all pages for this tree from the underlying
+    // PageCache. This will leave orphans behind if
+    // the tree crashes during gc.
+    pub(crate) fn gc_pages(
+        &self,
+        mut leftmost_chain: Vec<PageId>,
+    ) -> Result<()> {
+        let mut guard = pin();
+
+        let mut ops = 0;
+        while let Some(mut pid) = leftmost_chain.pop() {
+            loop {
+                ops += 1;
+                if ops % 64 == 0 {
+                    // we re-pin here to avoid memory blow-ups during
+                    // long-running tree removals.
+                    guard = pin();
+                }
+                let cursor_view =
+                    if let Some(view) = self.view_for_pid(pid, &guard)? {
+                        view
+                    } else {
+                        trace!(
+                            "encountered Free node pid {} while GC'ing tree",
+                            pid
+                        );
+                        break;
+                    };
+
+                let ret = self.context.pagecache.free(
+                    pid,
+                    cursor_view.node_view.0,
+                    &guard,
+                )?;
+
+                if ret.is_ok() {
+                    let next_pid = if let Some(next_pid) = cursor_view.next {
+                        next_pid
+                    } else {
+                        break;
+                    };
+                    assert_ne!(pid, next_pid.get());
+                    pid = next_pid.get();
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    // Returns the leftmost page of each level of the tree whose
+    // root is `root_id`, starting with the root itself.
+    pub(crate) fn leftmost_chain(
+        &self,
+        root_id: PageId,
+        guard: &Guard,
+    ) -> Result<Vec<PageId>> {
+        let mut leftmost_chain: Vec<PageId> = vec![root_id];
+        let mut cursor = root_id;
+        while let Some(view) = self.view_for_pid(cursor, guard)? {
+            if view.is_index {
+                let leftmost_child = view.iter_index_pids().next().unwrap();
+                leftmost_chain.push(leftmost_child);
+                cursor = leftmost_child;
+            } else {
+                break;
+            }
+        }
+        Ok(leftmost_chain)
+    }
+
     // Returns the traversal path, completing any observed
     // partially complete splits or merges along the way.
     //
@@ -1789,8 +2950,12 @@ impl Tree {
         let mut unsplit_parent = None;
         let mut took_leftmost_branch = false;
 
-        // only merge or split nodes a few times
-        let mut smo_budget = 3_u8;
+        // only merge or split nodes a few times, and never
+        // in read-only mode, where nodes are read as they
+        // were recovered, including any incomplete merges
+        // or splits.
+        let read_only = self.context.read_only;
+        let mut smo_budget = if read_only { 0 } else { 3_u8 };
 
         #[cfg(feature = "testing")]
         let mut path = vec![];
@@ -1837,7 +3002,9 @@ impl Tree {
             path.push((cursor, view.clone()));
 
             // When we encounter a merge intention, we collaboratively help out
-            if view.merging_child.is_some() {
+            if read_only {
+                // incomplete merges are left as they are
+            } else if view.merging_child.is_some() {
                 self.merge_node(
                     &view,
                     view.merging_child.unwrap().get(),
@@ -1879,9 +3046,15 @@ impl Tree {
                     .get();
                 trace!("seeking right on undershot node, from {} to {}", cursor, right_sibling);
                 cursor = right_sibling;
-                if unsplit_parent.is_none() && parent_view.is_some() {
+                if unsplit_parent.is_none()
+                    && parent_view.is_some()
+                    && !read_only
+                {
                     unsplit_parent = parent_view.clone();
-                } else if parent_view.is_none() && view.lo().is_empty() {
+                } else if parent_view.is_none()
+                    && view.lo().is_empty()
+                    && !read_only
+                {
                     assert!(unsplit_parent.is_none());
                     assert_eq!(view.pid, root_pid);
                     // we have found a partially-split root
@@ -2,20 +2,26 @@
 
 use std::{
     fmt::{self, Debug, Display},
-    ops::{Deref, RangeBounds},
+    ops::{Bound, Deref, RangeBounds},
 };
 
 use crate::{
     batch_typed::TypedBatch,
     encoding::{Decoder, Encoder},
+    iter_typed::TypedIter,
+    subscriber_typed::TypedSubscriber,
+    transaction::{
+        ConflictableTransactionResult, TransactionResult, Transactional,
+    },
+    transaction_typed::TypedTransactionalTree,
     tree::{CompareAndSwapError, Tree},
-    Error, IVec, Iter, Result, Subscriber,
+    Error, IVec, Result,
 };
 
 /// A wrapper around regular `[Tree]`s with a different, typed API.
 ///
 pub struct TypedTree<K, V> {
-    tree: Tree,
+    pub(crate) tree: Tree,
     _k: std::marker::PhantomData<K>,
     _v: std::marker::PhantomData<V>,
 }
@@ -57,9 +63,39 @@ where
             .map(|res| res.map(|ivec| ivec.into_encoding()))
     }
 
-    /// TODO
-    pub fn transaction() {
-        todo!()
+    /// Perform a multi-key serializable transaction, encoding and
+    /// decoding keys and values with the encodings of this tree.
+    /// Transactions across several `TypedTree`s, which may have
+    /// different encodings, can be run on a tuple of them with
+    /// `Transactional::transaction`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// use sled::transaction::ConflictableTransactionError;
+    ///
+    /// let db = sled::Config::default().temporary(true).open()?;
+    /// let tree = db
+    ///     .open_tree("id_to_name")?
+    ///     .with_encodings::<sled::IntegerEncoding<u128>, sled::StringEncoding>();
+    ///
+    /// tree.transaction(|tx| {
+    ///     tx.insert(1, "January")?;
+    ///     tx.insert(2, "February")?;
+    ///     Ok::<_, ConflictableTransactionError>(())
+    /// })?;
+    ///
+    /// assert_eq!(tree.get(2)?.unwrap().decode()?, "February");
+    /// # Ok(()) }
+    /// ```
+    pub fn transaction<F, A, E>(&self, f: F) -> TransactionResult<A, E>
+    where
+        F: Fn(
+            &TypedTransactionalTree<K, V>,
+        ) -> ConflictableTransactionResult<A, E>,
+    {
+        Transactional::transaction(&self, f)
     }
 
     /// Create a new TypedBatch that matches the encodings of this tree
@@ -178,9 +214,38 @@ where
         }
     }
 
-    /// TODO
-    pub fn watch_prefix<P: AsRef<[u8]>>(&self, _prefix: P) -> Subscriber {
-        todo!()
+    /// Subscribe to changes of keys whose encoding starts with
+    /// `prefix`, decoding the keys and values of the events. An
+    /// empty prefix subscribes to all changes of this tree.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let db = sled::Config::default().temporary(true).open()?;
+    /// let tree = db
+    ///     .open_tree("id_to_name")?
+    ///     .with_encodings::<sled::IntegerEncoding<u128>, sled::StringEncoding>();
+    ///
+    /// let subscriber = tree.watch_prefix(vec![]);
+    ///
+    /// tree.insert(1, "January")?;
+    ///
+    /// let event = subscriber.next_timeout(std::time::Duration::from_secs(1))?;
+    /// let changes = event.iter().collect::<Result<Vec<_>, _>>()?;
+    /// assert_eq!(changes, vec![(1, Some("January"))]);
+    /// # Ok(()) }
+    /// ```
+    pub fn watch_prefix<P: AsRef<[u8]>>(
+        &self,
+        prefix: P,
+    ) -> TypedSubscriber<K, V> {
+        let prefix_ref = prefix.as_ref();
+        TypedSubscriber::new(
+            self.tree.watch_prefix(prefix_ref),
+            self.tree.tree_id.clone(),
+            IVec::from(prefix_ref),
+        )
     }
 
     /// contains_key
@@ -220,19 +285,75 @@ where
         todo!()
     }
 
-    /// TODO
-    pub fn iter(&self) -> Iter {
-        todo!()
+    /// Create a double-ended iterator over the keys and values
+    /// of this tree, in the order of their encoded keys.
+    pub fn iter(&self) -> TypedIter<K, V> {
+        TypedIter::new(self.tree.iter())
     }
 
-    /// TODO
-    pub fn range<R>(&self, _range: ()) -> Iter {
-        todo!()
+    /// Create a double-ended iterator over the keys and values
+    /// whose keys fall within the specified range. The bounds are
+    /// encoded with the key encoding, so the range only matches the
+    /// order of the unencoded keys if that encoding preserves it.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let db = sled::Config::default().temporary(true).open()?;
+    /// let tree = db
+    ///     .open_tree("id_to_name")?
+    ///     .with_encodings::<sled::IntegerEncoding<u128>, sled::StringEncoding>();
+    ///
+    /// tree.insert(1, "January")?;
+    /// tree.insert(2, "February")?;
+    /// tree.insert(3, "March")?;
+    ///
+    /// let mut names = tree.range(1..3).values();
+    /// assert_eq!(names.next().unwrap()?.decode()?, "January");
+    /// assert_eq!(names.next_back().unwrap()?.decode()?, "February");
+    /// assert!(names.next().is_none());
+    /// # Ok(()) }
+    /// ```
+    pub fn range<'a, R>(&self, range: R) -> TypedIter<K, V>
+    where
+        R: RangeBounds<<K as Encoder<'a>>::In>,
+        <K as Encoder<'a>>::In: Clone,
+    {
+        let lo = encode_bound::<K>(range.start_bound());
+        let hi = encode_bound::<K>(range.end_bound());
+        TypedIter::new(self.tree.range::<IVec, _>((lo, hi)))
     }
 
-    /// TODO: is the encoder useful here, or do we expect the user to pass in &[u8]?
-    pub fn scan_prefix(&self, _prefix: ()) -> Iter {
-        todo!()
+    /// Create a double-ended iterator over the keys and values
+    /// whose encoded keys start with the encoding of `prefix`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let db = sled::Config::default().temporary(true).open()?;
+    /// let tree = db
+    ///     .open_tree("name_to_id")?
+    ///     .with_encodings::<sled::StringEncoding, sled::IntegerEncoding<u128>>();
+    ///
+    /// tree.insert("June", 6)?;
+    /// tree.insert("July", 7)?;
+    /// tree.insert("March", 3)?;
+    ///
+    /// let months: Vec<u128> = tree
+    ///     .scan_prefix("Ju")
+    ///     .values()
+    ///     .map(|res| Ok(res?.decode()?))
+    ///     .collect::<Result<_, Box<dyn std::error::Error>>>()?;
+    /// assert_eq!(months, vec![7, 6]);
+    /// # Ok(()) }
+    /// ```
+    pub fn scan_prefix<'a>(
+        &self,
+        prefix: <K as Encoder<'a>>::In,
+    ) -> TypedIter<K, V> {
+        TypedIter::new(self.tree.scan_prefix(K::encode(prefix)))
     }
 
     /// Return the first value
@@ -264,6 +385,22 @@ where
     }
 }
 
+fn encode_bound<'a, K>(bound: Bound<&<K as Encoder<'a>>::In>) -> Bound<IVec>
+where
+    K: Encoder<'a>,
+    <K as Encoder<'a>>::In: Clone,
+{
+    match bound {
+        Bound::Included(key) => {
+            Bound::Included(IVec::from(K::encode(key.clone()).as_ref()))
+        }
+        Bound::Excluded(key) => {
+            Bound::Excluded(IVec::from(K::encode(key.clone()).as_ref()))
+        }
+        Bound::Unbounded => Bound::Unbounded,
+    }
+}
+
 /// Typed Compare and swap result.
 pub type TypedCompareAndSwapResult<K, V> =
     Result<std::result::Result<(), TypedCompareAndSwapError<K, V>>>;
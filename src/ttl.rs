@@ -0,0 +1,338 @@
+use std::{
+    convert::TryInto,
+    thread,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use crate::*;
+
+/// The name of the internal tree that stores deadlines.
+pub(crate) const EXPIRY_TREE_ID: &[u8] = b"__sled__expiry";
+
+// The number of expired keys that the reaper removes
+// in a single transaction.
+const REAP_BATCH_SIZE: usize = 64;
+
+// The expiry tree stores the deadline of each key under
+// `DEADLINE_PREFIX`, followed by the length of the name of the
+// `Tree` that the key belongs to, that name and the key itself, so
+// that the deadlines of each `Tree` share a prefix. Each deadline
+// is stored as big-endian milliseconds since the unix epoch. Every
+// key is also stored under `EXPIRATION_PREFIX`, followed by its
+// deadline and the rest of its key under `DEADLINE_PREFIX`, so
+// that the reaper only has to scan the keys that have expired.
+const DEADLINE_PREFIX: u8 = 0;
+const EXPIRATION_PREFIX: u8 = 1;
+
+pub(crate) fn expiry_key(tree_id: &[u8], key: &[u8]) -> Vec<u8> {
+    let mut ret = deadline_prefix(tree_id);
+    ret.extend_from_slice(key);
+    ret
+}
+
+pub(crate) fn deadline_prefix(tree_id: &[u8]) -> Vec<u8> {
+    let mut ret = vec![DEADLINE_PREFIX];
+    ret.extend_from_slice(&tree_prefix(tree_id));
+    ret
+}
+
+pub(crate) fn tree_prefix(tree_id: &[u8]) -> Vec<u8> {
+    let mut ret = Vec::with_capacity(8 + tree_id.len());
+    ret.extend_from_slice(&(tree_id.len() as u64).to_be_bytes());
+    ret.extend_from_slice(tree_id);
+    ret
+}
+
+fn expiration_key(deadline: &[u8], expiry_key: &[u8]) -> Vec<u8> {
+    let mut ret = Vec::with_capacity(deadline.len() + expiry_key.len());
+    ret.push(EXPIRATION_PREFIX);
+    ret.extend_from_slice(deadline);
+    ret.extend_from_slice(&expiry_key[1..]);
+    ret
+}
+
+// Splits a key from under `EXPIRATION_PREFIX` into the deadline
+// and the key that the deadline is stored under.
+fn split_expiration_key(expiration_key: &[u8]) -> Result<(IVec, Vec<u8>)> {
+    if expiration_key.len() < 9 {
+        return Err(Error::corruption(None));
+    }
+    let (deadline, rest) = expiration_key[1..].split_at(8);
+    let mut expiry_key = vec![DEADLINE_PREFIX];
+    expiry_key.extend_from_slice(rest);
+    Ok((deadline.into(), expiry_key))
+}
+
+// Splits a key from under `DEADLINE_PREFIX` into the name of
+// the `Tree` and the key that it belongs to.
+pub(crate) fn split_expiry_key(expiry_key: &[u8]) -> Result<(&[u8], &[u8])> {
+    if expiry_key.len() < 9 {
+        return Err(Error::corruption(None));
+    }
+    let (len_bytes, rest) = expiry_key[1..].split_at(8);
+    let len =
+        usize::try_from(u64::from_be_bytes(len_bytes.try_into().unwrap()))
+            .map_err(|_| Error::corruption(None))?;
+    if len > rest.len() {
+        return Err(Error::corruption(None));
+    }
+    Ok(rest.split_at(len))
+}
+
+// Sets the deadline of the key stored under `expiry_key`,
+// replacing any previous one. Called while the log is pinned,
+// so that the deadline is recovered atomically with the key.
+pub(crate) fn set_deadline(
+    expiry: &Tree,
+    expiry_key: &[u8],
+    deadline: &IVec,
+    guard: &mut Guard,
+) -> Result<()> {
+    if let Some(last) = insert(expiry, expiry_key, Some(deadline), guard)? {
+        insert(expiry, &expiration_key(&last, expiry_key), None, guard)?;
+    }
+    let expiration = expiration_key(deadline, expiry_key);
+    insert(expiry, &expiration, Some(&IVec::default()), guard)?;
+    Ok(())
+}
+
+// Removes the deadline of the key stored under `expiry_key`,
+// if it has one.
+pub(crate) fn clear_deadline(
+    expiry: &Tree,
+    expiry_key: &[u8],
+    guard: &mut Guard,
+) -> Result<()> {
+    if let Some(last) = insert(expiry, expiry_key, None, guard)? {
+        insert(expiry, &expiration_key(&last, expiry_key), None, guard)?;
+    }
+    Ok(())
+}
+
+fn insert(
+    expiry: &Tree,
+    key: &[u8],
+    value: Option<&IVec>,
+    guard: &mut Guard,
+) -> Result<Option<IVec>> {
+    loop {
+        if let Ok(last) =
+            expiry.insert_inner(key, value.cloned(), true, guard)?
+        {
+            return Ok(last);
+        }
+    }
+}
+
+pub(crate) fn deadline_after(ttl: Duration) -> IVec {
+    let ttl_ms = u64::try_from(ttl.as_millis()).unwrap_or(u64::max_value());
+    let deadline = now().saturating_add(ttl_ms);
+    IVec::from(&deadline.to_be_bytes())
+}
+
+pub(crate) fn is_expired(deadline: &[u8]) -> Result<bool> {
+    is_expired_at(deadline, now())
+}
+
+// Returns `true` if the deadline had passed at `at`, which is in
+// milliseconds since the unix epoch like the deadline itself.
+pub(crate) fn is_expired_at(deadline: &[u8], at: u64) -> Result<bool> {
+    let deadline_bytes: [u8; 8] =
+        deadline.try_into().map_err(|_| Error::corruption(None))?;
+    Ok(u64::from_be_bytes(deadline_bytes) <= at)
+}
+
+pub(crate) fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |since_epoch| {
+        u64::try_from(since_epoch.as_millis()).unwrap_or(u64::max_value())
+    })
+}
+
+// Returns `true` if any key of the provided `Tree` has a deadline.
+pub(crate) fn has_deadlines(
+    expiry_opt: Option<&Tree>,
+    tree_id: &[u8],
+) -> Result<bool> {
+    if let Some(expiry) = expiry_opt {
+        let first = expiry.scan_prefix(deadline_prefix(tree_id)).next();
+        Ok(first.transpose()?.is_some())
+    } else {
+        Ok(false)
+    }
+}
+
+// Removes the deadlines of every key of a dropped `Tree`.
+pub(crate) fn forget_tree(expiry: &Tree, tree_id: &[u8]) -> Result<()> {
+    let mut batch = Batch::default();
+    for res in expiry.scan_prefix(deadline_prefix(tree_id)) {
+        let (expiry_key, deadline) = res?;
+        batch.remove(expiration_key(&deadline, &expiry_key));
+        batch.remove(expiry_key);
+    }
+    if batch.is_empty() { Ok(()) } else { expiry.apply_batch(batch) }
+}
+
+/// Periodically removes keys whose time-to-live has run out
+/// from every `Tree` in a `Db`, until dropped.
+#[derive(Debug)]
+pub(crate) struct Reaper {
+    shutdown: Arc<Mutex<bool>>,
+    sc: Arc<Condvar>,
+    join_handle: Mutex<Option<thread::JoinHandle<()>>>,
+}
+
+impl Reaper {
+    pub(crate) fn new(
+        expiry: Arc<meta::LazyTree>,
+        default: Tree,
+        tenants: Arc<RwLock<FastMap8<IVec, Tree>>>,
+        reap_every_ms: u64,
+    ) -> Self {
+        #[allow(clippy::mutex_atomic)] // mutex used in CondVar below
+        let shutdown = Arc::new(Mutex::new(false));
+        let sc = Arc::new(Condvar::new());
+
+        let join_handle = thread::Builder::new()
+            .name("expired key reaper".to_owned())
+            .spawn({
+                let shutdown = shutdown.clone();
+                let sc = sc.clone();
+                move || {
+                    run(
+                        &shutdown,
+                        &sc,
+                        &expiry,
+                        &default,
+                        &tenants,
+                        reap_every_ms,
+                    )
+                }
+            })
+            .unwrap();
+
+        Self { shutdown, sc, join_handle: Mutex::new(Some(join_handle)) }
+    }
+}
+
+fn run(
+    shutdown: &Arc<Mutex<bool>>,
+    sc: &Arc<Condvar>,
+    expiry: &meta::LazyTree,
+    default: &Tree,
+    tenants: &RwLock<FastMap8<IVec, Tree>>,
+    reap_every_ms: u64,
+) {
+    let reap_every = Duration::from_millis(reap_every_ms);
+    let mut shutdown = shutdown.lock();
+    while !*shutdown {
+        let _ = sc.wait_for(&mut shutdown, reap_every);
+        if *shutdown {
+            break;
+        }
+        // there is nothing to reap until the first key
+        // is inserted with a time-to-live
+        if let Some(expiry_tree) = expiry.tree() {
+            if let Err(e) = reap(&expiry_tree, default, tenants) {
+                error!(
+                    "failed to remove expired keys from reaper thread: {}",
+                    e
+                );
+            }
+        }
+    }
+}
+
+impl Drop for Reaper {
+    fn drop(&mut self) {
+        *self.shutdown.lock() = true;
+        let _notified = self.sc.notify_all();
+
+        if let Some(join_handle) = self.join_handle.lock().take() {
+            if let Err(e) = join_handle.join() {
+                error!("error joining reaper thread: {:?}", e);
+            }
+        }
+    }
+}
+
+fn reap(
+    expiry: &Tree,
+    default: &Tree,
+    tenants: &RwLock<FastMap8<IVec, Tree>>,
+) -> Result<()> {
+    let mut expired: BTreeMap<IVec, Vec<(IVec, IVec)>> = BTreeMap::new();
+
+    // only the keys whose deadline is not after now are scanned
+    let mut end = vec![EXPIRATION_PREFIX];
+    end.extend_from_slice(&now().saturating_add(1).to_be_bytes());
+
+    for res in expiry.range(&[EXPIRATION_PREFIX][..]..&end[..]).keys() {
+        let (deadline, expiry_key) = split_expiration_key(&res?)?;
+        let (tree_id, _key) = split_expiry_key(&expiry_key)?;
+        expired
+            .entry(tree_id.into())
+            .or_insert_with(Vec::new)
+            .push((expiry_key.into(), deadline));
+    }
+
+    for (tree_id, deadlines) in expired {
+        let tree_opt = if tree_id == default.tree_id {
+            Some(default.clone())
+        } else {
+            tenants.read().get(&tree_id).cloned()
+        };
+
+        for chunk in deadlines.chunks(REAP_BATCH_SIZE) {
+            remove_expired(expiry, tree_opt.as_ref(), chunk)?;
+        }
+    }
+
+    Ok(())
+}
+
+// Removes the provided keys from `tree`, whose deadlines were read
+// from the expiry tree, unless they were written to again since.
+// Deadlines that no longer belong to a key are removed on their own.
+fn remove_expired(
+    expiry: &Tree,
+    tree_opt: Option<&Tree>,
+    deadlines: &[(IVec, IVec)],
+) -> Result<()> {
+    // writers are blocked until the keys are removed, so that
+    // the deadlines can't change after they are checked
+    let _cc = concurrency_control::write();
+    let mut guard = pin();
+
+    let mut batch = Batch::default();
+    for (expiry_key, deadline) in deadlines {
+        let is_current = loop {
+            if let Ok(current) = expiry.get_inner(expiry_key, &mut guard)? {
+                break current.as_ref() == Some(deadline);
+            }
+        };
+
+        match tree_opt {
+            Some(_) if is_current => {
+                let (_tree_id, key) = split_expiry_key(expiry_key)?;
+                batch.remove(key);
+            }
+            None if is_current => {
+                // the tree was dropped after its deadlines were read
+                clear_deadline(expiry, expiry_key, &mut guard)?;
+            }
+            _ => {
+                // the key was given a new deadline concurrently
+                let stale = expiration_key(deadline, expiry_key);
+                insert(expiry, &stale, None, &mut guard)?;
+            }
+        }
+    }
+
+    // removing each key also removes its deadline
+    match tree_opt {
+        Some(tree) if !batch.is_empty() => {
+            tree.apply_batch_inner(batch, None, &mut guard)
+        }
+        _ => Ok(()),
+    }
+}
@@ -1007,6 +1007,1775 @@ fn tree_range() {
     assert_eq!(r.next(), None);
 }
 
+#[test]
+#[cfg_attr(miri, ignore)]
+fn tree_remove_range() -> Result<()> {
+    common::setup_logger();
+
+    let config = Config::new().temporary(true).flush_every_ms(Some(1));
+    let t = config.open()?;
+
+    for i in 0..N {
+        t.insert(kv(i), kv(i))?;
+    }
+
+    let sub = t.watch_prefix(b"");
+
+    t.remove_range(kv(10)..kv(N - 10))?;
+
+    // every removal arrives in a single event
+    let event = sub.next_timeout(Duration::from_secs(5)).unwrap();
+    let removed: Vec<_> =
+        event.iter().map(|(_, k, v)| (k.clone(), v.clone())).collect();
+    assert_eq!(removed.len(), N - 20);
+    assert!(removed.iter().all(|(_, v)| v.is_none()));
+    assert!(sub.next_timeout(Duration::from_millis(50)).is_err());
+
+    assert_eq!(t.len(), 20);
+    assert_eq!(t.get(kv(9))?, Some(IVec::from(kv(9))));
+    assert_eq!(t.get(kv(10))?, None);
+    assert_eq!(t.get(kv(N - 11))?, None);
+    assert_eq!(t.get(kv(N - 10))?, Some(IVec::from(kv(N - 10))));
+
+    // removals are durable
+    drop(event);
+    drop(sub);
+    drop(t);
+    let t = config.open()?;
+    assert_eq!(t.len(), 20);
+
+    // point writes after a range removal in a batch survive it
+    let mut batch = Batch::default();
+    batch.insert(kv(1), b"gone".to_vec());
+    batch.remove_range::<Vec<u8>, _>(..kv(5));
+    batch.insert(kv(2), b"kept".to_vec());
+    assert_eq!(batch.get(kv(1)), Some(None));
+    t.apply_batch(batch)?;
+
+    let keys: Vec<_> = t.iter().keys().take(3).map(|k| k.unwrap()).collect();
+    assert_eq!(keys, vec![kv(2), kv(5), kv(6)]);
+    assert_eq!(t.get(kv(2))?, Some(IVec::from(b"kept")));
+
+    // range removals in a transaction are visible to later reads
+    let mut batch = Batch::default();
+    batch.remove_range(kv(N - 10)..);
+    t.transaction::<_, _, ()>(|tx| {
+        tx.apply_batch(&batch)?;
+        assert_eq!(tx.get(kv(N - 1))?, None);
+        tx.insert(kv(N - 1), kv(N - 1))?;
+        Ok(())
+    })
+    .unwrap();
+
+    assert_eq!(t.len(), 7);
+    assert_eq!(t.last()?.unwrap().0, kv(N - 1));
+
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn tree_snapshot() -> Result<()> {
+    common::setup_logger();
+
+    let config = Config::new().temporary(true).flush_every_ms(Some(1));
+    let db = config.open()?;
+    let t1 = db.open_tree(b"1")?;
+    let t2 = db.open_tree(b"2")?;
+
+    for i in 0..N {
+        t1.insert(kv(i), kv(i))?;
+    }
+
+    let snapshot = db.snapshot()?;
+    let s1 = snapshot.open_tree(b"1")?;
+    assert!(snapshot.open_tree(b"3").is_err());
+
+    t1.remove_range(kv(0)..kv(N / 2))?;
+    t1.insert(b"new", b"new".to_vec())?;
+    t2.insert(b"new", b"new".to_vec())?;
+
+    assert_eq!(s1.len(), N);
+    assert_eq!(s1.get(kv(0))?, Some(IVec::from(kv(0))));
+    assert_eq!(s1.get(b"new")?, None);
+    assert!(snapshot.open_tree(b"2")?.is_empty());
+
+    let forward: Vec<_> = s1.iter().keys().map(|k| k.unwrap()).collect();
+    let mut backward: Vec<_> =
+        s1.iter().keys().rev().map(|k| k.unwrap()).collect();
+    backward.reverse();
+    assert_eq!(forward.len(), N);
+    assert_eq!(forward, backward);
+
+    let mut r = s1.range(kv(5)..kv(8));
+    assert_eq!(r.next().unwrap()?.0, kv(5));
+    assert_eq!(r.next_back().unwrap()?.0, kv(7));
+    assert_eq!(r.next().unwrap()?.0, kv(6));
+    assert!(r.next().is_none());
+    assert!(r.next_back().is_none());
+
+    assert_eq!(s1.scan_prefix(&kv(0)[..2]).count(), 256);
+    assert_eq!(s1.first()?.unwrap().0, kv(0));
+    assert_eq!(s1.last()?.unwrap().0, kv(N - 1));
+
+    // pages that are split, merged or freed after a snapshot
+    // was taken still show their old contents through it
+    let s1 = t1.snapshot()?;
+    let before: Vec<_> = s1.iter().collect::<Result<_>>()?;
+    for i in 0..N {
+        t1.insert(kv(i), vec![0; 64])?;
+    }
+    for i in 0..N {
+        t1.remove(kv(i))?;
+    }
+    assert_eq!(s1.iter().collect::<Result<Vec<_>>>()?, before);
+    assert_eq!(s1.iter().rev().count(), before.len());
+    drop(s1);
+
+    // writers running concurrently with snapshots never
+    // produce a snapshot where the two trees disagree
+    let db = Arc::new(db);
+    let writer = {
+        let db = db.clone();
+        std::thread::spawn(move || {
+            let t1 = db.open_tree(b"1").unwrap();
+            let t2 = db.open_tree(b"2").unwrap();
+            for i in 0..N {
+                (&t1, &t2)
+                    .transaction(|(tx1, tx2)| {
+                        tx1.insert(b"counter", kv(i))?;
+                        tx2.insert(b"counter", kv(i))?;
+                        Ok::<_, ConflictableTransactionError<()>>(())
+                    })
+                    .unwrap();
+            }
+        })
+    };
+
+    for _ in 0..N / 10 {
+        let snapshot = db.snapshot()?;
+        let c1 = snapshot.open_tree(b"1")?.get(b"counter")?;
+        let c2 = snapshot.open_tree(b"2")?.get(b"counter")?;
+        assert_eq!(c1, c2);
+    }
+
+    writer.join().unwrap();
+
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn tree_transactional_range() -> TransactionResult<()> {
+    common::setup_logger();
+
+    let config = Config::new().temporary(true).flush_every_ms(Some(1));
+    let db = config.open()?;
+
+    for i in 0..10_u8 {
+        db.insert(&[i], vec![i])?;
+    }
+
+    db.transaction::<_, _, ()>(|tx| {
+        tx.remove(&[3])?;
+        tx.insert(&[4], vec![40])?;
+        tx.insert(&[20], vec![20])?;
+        let mut batch = Batch::default();
+        batch.remove_range::<&[u8], _>(&[6][..]..&[8][..]);
+        tx.apply_batch(&batch)?;
+
+        let keys: Vec<_> =
+            tx.range::<&[u8], _>(..).map(|r| r.unwrap().0).collect();
+        let expected: Vec<IVec> = vec![0_u8, 1, 2, 4, 5, 8, 9, 20]
+            .into_iter()
+            .map(|i| IVec::from(&[i]))
+            .collect();
+        assert_eq!(keys, expected);
+
+        let mut rev: Vec<_> =
+            tx.range::<&[u8], _>(..).rev().map(|r| r.unwrap().0).collect();
+        rev.reverse();
+        assert_eq!(rev, expected);
+
+        let mut r = tx.range::<&[u8], _>(&[2][..]..=&[8][..]);
+        assert_eq!(r.next().unwrap()?.0, [2]);
+        assert_eq!(r.next_back().unwrap()?.0, [8]);
+        assert_eq!(r.next().unwrap()?, (IVec::from(&[4]), IVec::from(&[40])));
+        assert_eq!(r.next_back().unwrap()?.0, [5]);
+        assert!(r.next().is_none());
+        assert!(r.next_back().is_none());
+
+        assert_eq!(tx.first()?.unwrap().0, [0]);
+        assert_eq!(tx.last()?.unwrap().0, [20]);
+        assert_eq!(tx.get_lt(&[4])?.unwrap().0, [2]);
+        assert_eq!(tx.get_gt(&[5])?.unwrap().0, [8]);
+        assert_eq!(tx.get_gt(&[20])?, None);
+        assert_eq!(tx.scan_prefix(&[9]).count(), 1);
+
+        Ok(())
+    })?;
+
+    // concurrently claiming the next free slot never hands
+    // out the same slot twice
+    let slots = db.open_tree(b"slots")?;
+    let threads: Vec<_> = (0..N_THREADS)
+        .map(|_| {
+            let slots = slots.clone();
+            std::thread::spawn(move || {
+                for _ in 0..N_PER_THREAD / 10 {
+                    slots
+                        .transaction(|tx| {
+                            let next = tx.last()?.map_or(0, |(k, _)| {
+                                let mut buf = [0; 8];
+                                buf.copy_from_slice(&k);
+                                u64::from_be_bytes(buf) + 1
+                            });
+                            let old = tx.insert(&next.to_be_bytes(), vec![])?;
+                            assert!(old.is_none());
+                            Ok::<_, ConflictableTransactionError<()>>(())
+                        })
+                        .unwrap();
+                }
+            })
+        })
+        .collect();
+
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    assert_eq!(slots.len(), N_THREADS * N_PER_THREAD / 10);
+
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn tree_change_feed() -> Result<()> {
+    common::setup_logger();
+
+    // the change feed must be enabled explicitly
+    let config = Config::new().temporary(true);
+    let db = config.open()?;
+    match db.changes_since(0) {
+        Err(Error::Unsupported(_)) => {}
+        _ => panic!("changes_since should fail without a change feed"),
+    }
+    drop(db);
+
+    let config =
+        Config::new().temporary(true).flush_every_ms(Some(1)).change_feed(true);
+    let db = config.open()?;
+    let tree = db.open_tree(b"tree")?;
+    assert!(db.open_tree(b"__sled__change_feed").is_err());
+
+    db.insert(b"a", b"1".to_vec())?;
+    tree.insert(b"b", b"2".to_vec())?;
+    tree.compare_and_swap(b"b", Some(b"2"), Some(b"3".to_vec()))?.unwrap();
+    db.remove(b"a")?;
+
+    let mut batch = Batch::default();
+    batch.insert(b"c".to_vec(), b"4".to_vec());
+    batch.insert(b"d".to_vec(), b"5".to_vec());
+    tree.apply_batch(batch)?;
+
+    (&*db, &tree)
+        .transaction(|(tx_db, tx_tree)| {
+            tx_db.insert(b"e", b"6")?;
+            tx_tree.remove(b"b")?;
+            Ok::<_, ConflictableTransactionError<()>>(())
+        })
+        .unwrap();
+
+    tree.remove_range::<&[u8], _>(..)?;
+
+    let changes = db.changes_since(0)?.collect::<Result<Vec<_>>>()?;
+    let described: Vec<_> = changes
+        .iter()
+        .map(|c| (c.tree.to_vec(), c.key.to_vec(), c.value.clone()))
+        .collect();
+    let default_name = db.name().to_vec();
+    let expected = [
+        (default_name.clone(), b"a".to_vec(), Some(IVec::from(b"1"))),
+        (b"tree".to_vec(), b"b".to_vec(), Some(IVec::from(b"2"))),
+        (b"tree".to_vec(), b"b".to_vec(), Some(IVec::from(b"3"))),
+        (default_name.clone(), b"a".to_vec(), None),
+        (b"tree".to_vec(), b"c".to_vec(), Some(IVec::from(b"4"))),
+        (b"tree".to_vec(), b"d".to_vec(), Some(IVec::from(b"5"))),
+    ];
+    assert_eq!(&described[..6], &expected[..]);
+
+    // transactions and range removals are recorded per key,
+    // in whichever order the keys were written
+    let mut rest = described[6..].to_vec();
+    rest.sort();
+    assert_eq!(
+        rest,
+        vec![
+            (default_name, b"e".to_vec(), Some(IVec::from(b"6"))),
+            (b"tree".to_vec(), b"b".to_vec(), None),
+            (b"tree".to_vec(), b"c".to_vec(), None),
+            (b"tree".to_vec(), b"d".to_vec(), None),
+        ]
+    );
+    assert!(changes.windows(2).all(|w| w[0].lsn < w[1].lsn));
+
+    // resuming from a saved cursor continues where it left off
+    let cursor = changes[3].lsn;
+    let resumed = db.changes_since(cursor)?.collect::<Result<Vec<_>>>()?;
+    assert_eq!(&resumed[..], &changes[4..]);
+
+    // the feed survives restarts
+    drop(tree);
+    drop(db);
+    let db = config.open()?;
+    let recovered = db.changes_since(0)?.collect::<Result<Vec<_>>>()?;
+    assert_eq!(recovered, changes);
+
+    let last = changes.last().unwrap().lsn;
+    db.insert(b"f", b"7".to_vec())?;
+    let after = db.changes_since(last)?.collect::<Result<Vec<_>>>()?;
+    assert_eq!(after.len(), 1);
+    assert_eq!(after[0].key, b"f");
+
+    // truncated changes are no longer returned
+    db.truncate_changes(cursor)?;
+    let remaining = db.changes_since(0)?.collect::<Result<Vec<_>>>()?;
+    assert_eq!(remaining[0], changes[4]);
+    assert_eq!(remaining.len(), changes.len() - 3);
+
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn tree_change_feed_concurrent() -> Result<()> {
+    common::setup_logger();
+
+    const WRITERS: usize = 4;
+    const WRITES: usize = 500;
+
+    let config = Config::new().temporary(true).change_feed(true);
+    let db = config.open()?;
+
+    let done = Arc::new(AtomicUsize::new(0));
+    let writers: Vec<_> = (0..WRITERS)
+        .map(|w| {
+            let db = db.clone();
+            let done = done.clone();
+            std::thread::spawn(move || {
+                for i in 0..WRITES {
+                    let key = (w * WRITES + i).to_be_bytes();
+                    db.insert(key, vec![]).unwrap();
+                }
+                done.fetch_add(1, SeqCst);
+            })
+        })
+        .collect();
+
+    // a consumer that resumes from its cursor must never skip a
+    // write whose record was inserted after a later one's
+    let mut cursor = 0;
+    let mut seen = vec![];
+    loop {
+        let finished = done.load(SeqCst) == WRITERS;
+        for change in db.changes_since(cursor)? {
+            let change = change?;
+            assert!(change.lsn > cursor);
+            cursor = change.lsn;
+            seen.push(change.key);
+        }
+        if finished {
+            break;
+        }
+    }
+
+    for writer in writers {
+        writer.join().unwrap();
+    }
+
+    seen.sort();
+    seen.dedup();
+    assert_eq!(seen.len(), WRITERS * WRITES);
+
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn tree_backup() -> Result<()> {
+    common::setup_logger();
+
+    let mut backup_path = std::env::temp_dir();
+    backup_path.push("test_tree_backup");
+    let _ = std::fs::remove_dir_all(&backup_path);
+
+    let config = Config::new().temporary(true).flush_every_ms(Some(1));
+    let db = config.open()?;
+    let hot = db.open_tree(b"hot")?;
+
+    for i in 0..N {
+        db.insert(kv(i), kv(i))?;
+    }
+    // large values are stored in the heap
+    db.insert(b"large", vec![7; 64 * 1024])?;
+
+    // keep writing while the backup runs
+    let done = Arc::new(AtomicUsize::new(0));
+    let writer = {
+        let done = done.clone();
+        let hot = hot.clone();
+        std::thread::spawn(move || {
+            let mut i = 0_u64;
+            while done.load(SeqCst) == 0 {
+                hot.insert(i.to_be_bytes(), vec![1; (i % 512) as usize])
+                    .unwrap();
+                i += 1;
+            }
+        })
+    };
+
+    let mut progress = vec![];
+    let manifest =
+        db.backup_to_with_progress(&backup_path, |p| progress.push(p))?;
+
+    done.store(1, SeqCst);
+    writer.join().unwrap();
+
+    let last = progress.last().unwrap();
+    assert_eq!(last.bytes_copied, last.bytes_total);
+    assert!(
+        progress.windows(2).all(|w| w[0].bytes_copied <= w[1].bytes_copied)
+    );
+    assert!(manifest.files.iter().any(|f| f.name == "db"));
+    assert_eq!(BackupManifest::read(&backup_path)?, manifest);
+    manifest.verify(&backup_path)?;
+
+    // a backup that panics part of the way through does not
+    // keep reclamation paused for later backups
+    let mut panicked_path = backup_path.clone();
+    panicked_path.set_extension("panicked");
+    let _ = std::fs::remove_dir_all(&panicked_path);
+    let panicked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(
+        || db.backup_to_with_progress(&panicked_path, |_| panic!("progress")),
+    ));
+    assert!(panicked.is_err());
+    std::fs::remove_dir_all(&panicked_path)?;
+    db.backup_to(&panicked_path)?.verify(&panicked_path)?;
+    std::fs::remove_dir_all(&panicked_path)?;
+
+    // backups refuse to overwrite existing data
+    match db.backup_to(&backup_path) {
+        Err(Error::Unsupported(_)) => {}
+        other => panic!("expected Error::Unsupported, got {:?}", other),
+    }
+
+    // damaged backups fail verification
+    let db_path = backup_path.join("db");
+    let original = std::fs::read(&db_path)?;
+    let mut damaged = original.clone();
+    damaged[SEG_HEADER_LEN] ^= 1;
+    std::fs::write(&db_path, damaged)?;
+    match manifest.verify(&backup_path) {
+        Err(Error::Corruption { .. }) => {}
+        other => panic!("expected Error::Corruption, got {:?}", other),
+    }
+    std::fs::write(&db_path, original)?;
+
+    let backup = Config::new().path(&backup_path).open()?;
+    for i in 0..N {
+        assert_eq!(backup.get(kv(i))?, Some(IVec::from(kv(i))));
+    }
+    assert_eq!(backup.get(b"large")?, Some(IVec::from(vec![7; 64 * 1024])));
+
+    // writes that raced with the backup are recovered as a prefix
+    let backup_hot = backup.open_tree(b"hot")?;
+    for (expected, kv_res) in backup_hot.iter().enumerate() {
+        let (k, v) = kv_res?;
+        let expected = expected as u64;
+        assert_eq!(&*k, &expected.to_be_bytes());
+        assert_eq!(v.len(), (expected % 512) as usize);
+    }
+    drop(backup_hot);
+    drop(backup);
+
+    std::fs::remove_dir_all(&backup_path)?;
+
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn tree_backup_incremental() -> Result<()> {
+    common::setup_logger();
+
+    let mut dir = std::env::temp_dir();
+    dir.push("test_tree_backup_incremental");
+    let _ = std::fs::remove_dir_all(&dir);
+    let full_path = dir.join("full");
+    let incremental_paths =
+        [dir.join("incremental_1"), dir.join("incremental_2")];
+    let restore_path = dir.join("restored");
+
+    let config = Config::new()
+        .temporary(true)
+        .flush_every_ms(Some(1))
+        .segment_size(4096);
+    let db = config.open()?;
+
+    for i in 0..N {
+        db.insert(kv(i), kv(i))?;
+    }
+    db.insert(b"large", vec![1; 64 * 1024])?;
+    let full = db.backup_to(&full_path)?;
+
+    // overwrite, remove, and add keys between backups
+    let mut previous = full;
+    for (round, path) in incremental_paths.iter().enumerate() {
+        let round = round as u8 + 2;
+        for i in 0..N / 10 {
+            db.insert(kv(i), vec![round; 8])?;
+        }
+        db.remove(kv(N - 1 - round as usize))?;
+        db.insert(vec![round; 3], vec![round; 3])?;
+        db.insert(b"large", vec![round; 64 * 1024])?;
+
+        let mut progress = vec![];
+        let incremental =
+            db.backup_incremental_with_progress(&previous, path, |p| {
+                progress.push(p)
+            })?;
+        assert_eq!(incremental.previous_stable_lsn, Some(previous.stable_lsn));
+        assert_eq!(BackupManifest::read(path)?, incremental);
+
+        let last = progress.last().unwrap();
+        assert_eq!(last.bytes_copied, last.bytes_total);
+
+        // only the most recent log segments were copied
+        let log = incremental.files.iter().find(|f| f.name == "db").unwrap();
+        let ranges = log.ranges.as_ref().unwrap();
+        let copied: u64 = ranges.iter().map(|r| r.end - r.start).sum();
+        assert!(copied < log.len / 2);
+
+        previous = incremental;
+    }
+
+    // incremental backups must be restored on top of their predecessor
+    match restore_backup(&[&full_path, &incremental_paths[1]], &restore_path) {
+        Err(Error::Unsupported(_)) => {}
+        other => panic!("expected Error::Unsupported, got {:?}", other),
+    }
+
+    restore_backup(
+        &[&full_path, &incremental_paths[0], &incremental_paths[1]],
+        &restore_path,
+    )?;
+
+    let restored =
+        Config::new().path(&restore_path).segment_size(4096).open()?;
+    let expected: Vec<_> = db.iter().collect::<Result<_>>()?;
+    let actual: Vec<_> = restored.iter().collect::<Result<_>>()?;
+    assert_eq!(actual, expected);
+    assert_eq!(restored.get(b"large")?, Some(IVec::from(vec![3; 64 * 1024])));
+    drop(restored);
+
+    std::fs::remove_dir_all(&dir)?;
+
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn tree_read_only() -> Result<()> {
+    common::setup_logger();
+
+    // the contents of every file in a directory and its subdirectories
+    fn dir_contents(
+        path: &std::path::Path,
+    ) -> Vec<(std::path::PathBuf, Vec<u8>)> {
+        let mut contents = vec![];
+        for entry in std::fs::read_dir(path).unwrap() {
+            let entry_path = entry.unwrap().path();
+            if entry_path.is_dir() {
+                contents.append(&mut dir_contents(&entry_path));
+            } else {
+                let bytes = std::fs::read(&entry_path).unwrap();
+                contents.push((entry_path, bytes));
+            }
+        }
+        contents.sort();
+        contents
+    }
+
+    let mut path = std::env::temp_dir();
+    path.push("test_tree_read_only");
+    let _ = std::fs::remove_dir_all(&path);
+
+    // there is nothing to open yet
+    match Config::new().path(&path).read_only(true).open() {
+        Err(Error::Unsupported(_)) => {}
+        other => panic!("expected Error::Unsupported, got {:?}", other),
+    }
+    assert!(!path.exists());
+
+    // keep the writer from changing any files in the background
+    let writer = Config::new()
+        .path(&path)
+        .flush_every_ms(None)
+        .snapshot_after_ops(1_000_000)
+        .open()?;
+    let other = writer.open_tree(b"other")?;
+    for i in 0..N {
+        writer.insert(kv(i), kv(i))?;
+    }
+    other.insert(b"large", vec![1; 64 * 1024])?;
+    writer.flush()?;
+
+    let before = dir_contents(&path);
+
+    // the writer keeps the database open, and its lock
+    let reader = Config::new().path(&path).read_only(true).open()?;
+    assert!(reader.was_recovered());
+
+    let expected: Vec<_> = writer.iter().collect::<Result<_>>()?;
+    let actual: Vec<_> = reader.iter().collect::<Result<_>>()?;
+    assert_eq!(actual, expected);
+
+    let reader_other = reader.open_tree(b"other")?;
+    assert_eq!(
+        reader_other.get(b"large")?,
+        Some(IVec::from(vec![1; 64 * 1024]))
+    );
+
+    match reader.open_tree(b"missing") {
+        Err(Error::CollectionNotFound(name)) => assert_eq!(name, b"missing"),
+        other => panic!("expected Error::CollectionNotFound, got {:?}", other),
+    }
+
+    // every write is rejected
+    let unsupported = |res: Result<()>| match res {
+        Err(Error::Unsupported(_)) => {}
+        other => panic!("expected Error::Unsupported, got {:?}", other),
+    };
+    unsupported(reader.insert(b"a", b"a").map(drop));
+    unsupported(reader.remove(kv(0)).map(drop));
+    unsupported(
+        reader.compare_and_swap(kv(0), Some(kv(0)), None::<IVec>).map(drop),
+    );
+    unsupported(
+        reader_other.update_and_fetch(b"large", |_| None::<IVec>).map(drop),
+    );
+    unsupported(reader.apply_batch(Batch::default()));
+    unsupported(reader.remove_range(kv(0)..kv(10)));
+    unsupported(reader.pop_min().map(drop));
+    unsupported(reader.generate_id().map(drop));
+    unsupported(reader.drop_tree(b"other").map(drop));
+    match reader.transaction(|tx| {
+        tx.insert(b"a", b"a")?;
+        Ok::<_, ConflictableTransactionError<()>>(())
+    }) {
+        Err(TransactionError::Storage(Error::Unsupported(_))) => {}
+        other => panic!("expected Error::Unsupported, got {:?}", other),
+    }
+
+    // reads are still fine, even inside of transactions
+    let read = reader
+        .transaction(|tx| {
+            Ok::<_, ConflictableTransactionError<()>>(tx.get(kv(1))?)
+        })
+        .unwrap();
+    assert_eq!(read, Some(IVec::from(kv(1))));
+    assert_eq!(reader.len(), N);
+
+    drop(reader_other);
+    drop(reader);
+
+    // nothing was written to the directory
+    assert_eq!(dir_contents(&path), before);
+
+    // and the writer is unaffected
+    writer.insert(b"a", b"a")?;
+    assert_eq!(writer.get(b"a")?, Some(IVec::from(b"a")));
+
+    drop(other);
+    drop(writer);
+    std::fs::remove_dir_all(&path)?;
+
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn tree_ttl() -> Result<()> {
+    common::setup_logger();
+
+    let path = "test_tree_ttl";
+    let _ = std::fs::remove_dir_all(path);
+
+    // expired keys are hidden from reads before they are reaped
+    let db = Config::new().path(path).reap_expired_every_ms(None).open()?;
+    let t = db.open_tree(b"sessions")?;
+    let short = Duration::from_millis(50);
+    let long = Duration::from_secs(3600);
+
+    t.insert_with_ttl(b"a", b"a", short)?;
+    t.insert_with_ttl(b"b", b"b", short)?;
+    t.insert_with_ttl(b"c", b"c", short)?;
+    t.insert(b"d", b"d")?;
+
+    // other writes remove the ttl, and it can be replaced
+    t.insert(b"b", b"b2")?;
+    t.insert_with_ttl(b"c", b"c2", long)?;
+    assert_eq!(t.get(b"a")?, Some(IVec::from(b"a")));
+
+    std::thread::sleep(Duration::from_millis(100));
+
+    assert_eq!(t.get(b"a")?, None);
+    assert!(!t.contains_key(b"a")?);
+    let keys: Vec<IVec> = t.iter().keys().collect::<Result<_>>()?;
+    assert_eq!(
+        keys,
+        vec![IVec::from(b"b"), IVec::from(b"c"), IVec::from(b"d")]
+    );
+    assert_eq!(t.iter().rev().count(), 3);
+    assert_eq!(t.first()?.unwrap().0, IVec::from(b"b"));
+    let read = t
+        .transaction(|tx| {
+            Ok::<_, ConflictableTransactionError<()>>(tx.get(b"a")?)
+        })
+        .unwrap();
+    assert_eq!(read, None);
+    let (read, keys, last) = t
+        .read_transaction(|tx| {
+            let keys: Vec<IVec> =
+                tx.range::<&[u8], _>(..).keys().collect::<Result<_>>()?;
+            Ok::<_, ConflictableTransactionError<()>>((
+                tx.get(b"a")?,
+                keys,
+                tx.get_lt(b"b")?,
+            ))
+        })
+        .unwrap();
+    assert_eq!(read, None);
+    assert_eq!(
+        keys,
+        vec![IVec::from(b"b"), IVec::from(b"c"), IVec::from(b"d")]
+    );
+    assert_eq!(last, None);
+    let tree_snapshot = t.snapshot()?;
+    assert_eq!(tree_snapshot.get(b"a")?, None);
+    assert_eq!(tree_snapshot.len(), 3);
+    assert_eq!(tree_snapshot.iter().rev().count(), 3);
+    let db_snapshot = db.snapshot()?.open_tree(b"sessions")?;
+    assert_eq!(db_snapshot.get(b"a")?, None);
+    let keys: Vec<IVec> = db_snapshot.iter().keys().collect::<Result<_>>()?;
+    assert_eq!(
+        keys,
+        vec![IVec::from(b"b"), IVec::from(b"c"), IVec::from(b"d")]
+    );
+    drop(tree_snapshot);
+    drop(db_snapshot);
+
+    // an expired key is treated as if it was already removed
+    assert_eq!(
+        t.compare_and_swap(b"a", None as Option<&[u8]>, Some(b"a2"))?,
+        Ok(())
+    );
+    assert_eq!(t.get(b"a")?, Some(IVec::from(b"a2")));
+    t.insert_with_ttl(b"e", b"e", Duration::from_millis(1))?;
+    std::thread::sleep(Duration::from_millis(10));
+    assert_eq!(t.insert(b"e", b"e2")?, None);
+    assert_eq!(t.get(b"e")?, Some(IVec::from(b"e2")));
+
+    // deadlines survive restarts, and expired keys are reaped
+    t.insert_with_ttl(b"f", b"f", Duration::from_millis(500))?;
+    drop(t);
+    drop(db);
+
+    let db = Config::new().path(path).reap_expired_every_ms(Some(10)).open()?;
+    let t = db.open_tree(b"sessions")?;
+    let sub = t.watch_prefix(b"f");
+    assert_eq!(t.get(b"c")?, Some(IVec::from(b"c2")));
+
+    let event = sub.next_timeout(Duration::from_secs(5)).unwrap();
+    let removed: Vec<_> =
+        event.iter().map(|(_, k, v)| (k.clone(), v.clone())).collect();
+    assert_eq!(removed, vec![(IVec::from(b"f"), None)]);
+    assert_eq!(t.get(b"f")?, None);
+    assert_eq!(t.len(), 5);
+
+    drop(t);
+    drop(db);
+    std::fs::remove_dir_all(path)?;
+
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn tree_named_merge_operator() -> Result<()> {
+    common::setup_logger();
+
+    fn concatenate(
+        _key: &[u8],
+        old: Option<&[u8]>,
+        merged: &[u8],
+    ) -> Option<Vec<u8>> {
+        let mut ret = old.map(<[u8]>::to_vec).unwrap_or_default();
+        ret.extend_from_slice(merged);
+        Some(ret)
+    }
+
+    let path = "test_tree_named_merge_operator";
+    let _ = std::fs::remove_dir_all(path);
+
+    let config =
+        || Config::new().path(path).merge_operator("concatenate", concatenate);
+
+    let db = config().open()?;
+    let t = db.open_tree(b"log")?;
+    if let Err(Error::Unsupported(_)) = t.set_named_merge_operator("missing") {
+    } else {
+        panic!("set a merge operator that was never registered");
+    }
+    t.set_named_merge_operator("concatenate")?;
+    t.merge(b"k", vec![0])?;
+    db.set_named_merge_operator("concatenate")?;
+    drop(t);
+    drop(db);
+
+    // the merge operator is set again when the tree is opened
+    let db = config().open()?;
+    let t = db.open_tree(b"log")?;
+    t.merge(b"k", vec![1])?;
+    assert_eq!(t.get(b"k")?, Some(IVec::from(vec![0, 1])));
+    drop(t);
+    drop(db);
+
+    // unless it is no longer registered, in which case only opening
+    // that tree and merging into it fail
+    let db = Config::new().path(path).open()?;
+    db.insert(b"k", vec![3])?;
+    if let Err(Error::Unsupported(_)) = db.merge(b"k", vec![5]) {
+    } else {
+        panic!("merged into a tree whose merge operator is not registered");
+    }
+    let other = db.open_tree(b"other")?;
+    other.insert(b"k", vec![4])?;
+    if let Err(Error::Unsupported(_)) = db.open_tree(b"log") {
+    } else {
+        panic!("opened a tree whose merge operator is not registered");
+    }
+    assert_eq!(db.tree_names().len(), 3);
+    drop(other);
+    drop(db);
+
+    let db = Config::new().path(path).read_only(true).open()?;
+    assert_eq!(db.get(b"k")?, Some(IVec::from(vec![3])));
+    assert!(db.open_tree(b"log").is_err());
+    drop(db);
+
+    // dropping the tree forgets its merge operator
+    let db = config().open()?;
+    assert!(db.drop_tree(b"log")?);
+    drop(db);
+
+    let db = Config::new().path(path).open()?;
+    let t = db.open_tree(b"log")?;
+    assert!(t.merge(b"k", vec![2]).is_err());
+    drop(t);
+    drop(db);
+    std::fs::remove_dir_all(path)?;
+
+    Ok(())
+}
+
+// indexes keys by the first byte of their values
+fn first_byte(_key: &[u8], value: &[u8]) -> Option<Vec<u8>> {
+    value.get(..1).map(<[u8]>::to_vec)
+}
+
+fn indexed_keys(iter: IndexIter) -> Result<Vec<IVec>> {
+    iter.keys().collect()
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn tree_index() -> Result<()> {
+    common::setup_logger();
+
+    let path = "test_tree_index";
+    let _ = std::fs::remove_dir_all(path);
+
+    let db = Config::new().path(path).open()?;
+    let t = db.open_tree(b"users")?;
+    t.insert(b"a", vec![1, 0])?;
+    t.insert(b"b", vec![])?;
+
+    // keys that are already present are indexed
+    let index = t.create_index(b"by_first_byte", first_byte)?;
+    assert_eq!(indexed_keys(index.get([1]))?, vec![IVec::from(b"a")]);
+    assert_eq!(index.range::<&[u8], _>(..).count(), 1);
+
+    // every kind of write updates the index
+    t.insert(b"b", vec![2])?;
+    t.insert(b"c", vec![1])?;
+    t.insert(b"a", vec![3])?;
+    assert_eq!(
+        index.get([1]).next(),
+        Some(Ok((IVec::from(b"c"), IVec::from(vec![1]))))
+    );
+    assert_eq!(
+        t.compare_and_swap(b"c", Some(vec![1]), Some(vec![2]))?,
+        Ok(())
+    );
+    t.set_merge_operator(|_key: &[u8], _old: Option<&[u8]>, new: &[u8]| {
+        Some(new.to_vec())
+    });
+    t.merge(b"d", vec![0, 0])?;
+    t.remove(b"b")?;
+
+    let mut batch = Batch::default();
+    batch.insert(b"e", vec![2]);
+    batch.remove(b"a");
+    t.apply_batch(batch)?;
+
+    t.transaction(|tx| {
+        tx.insert(b"f", vec![3])?;
+        tx.insert(b"d", vec![3])?;
+        Ok::<_, ConflictableTransactionError<()>>(())
+    })
+    .unwrap();
+
+    assert_eq!(indexed_keys(index.get([0]))?, Vec::<IVec>::new());
+    assert_eq!(indexed_keys(index.get([1]))?, Vec::<IVec>::new());
+    assert_eq!(
+        indexed_keys(index.get([2]))?,
+        vec![IVec::from(b"c"), IVec::from(b"e")]
+    );
+    assert_eq!(
+        indexed_keys(index.range([2]..))?,
+        vec![
+            IVec::from(b"c"),
+            IVec::from(b"e"),
+            IVec::from(b"d"),
+            IVec::from(b"f")
+        ]
+    );
+    assert_eq!(
+        index.range([2]..[3]).keys().rev().collect::<Result<Vec<_>>>()?,
+        vec![IVec::from(b"e"), IVec::from(b"c")]
+    );
+
+    // range removals remove the entries of each removed key
+    t.remove_range::<&[u8], _>(&b"d"[..]..&b"f"[..])?;
+    assert_eq!(
+        indexed_keys(index.range::<&[u8], _>(..))?,
+        vec![IVec::from(b"c"), IVec::from(b"f")]
+    );
+
+    // index keys containing zero bytes are ordered like any other
+    let zeroes = t.create_index(b"zeroes", |key: &[u8], _value: &[u8]| {
+        Some(key.iter().map(|_| 0).collect())
+    })?;
+    t.insert(vec![0; 2], vec![])?;
+    t.insert(vec![0; 3], vec![])?;
+    assert_eq!(
+        indexed_keys(zeroes.range(vec![0]..vec![0; 3]))?,
+        vec![IVec::from(b"c"), IVec::from(b"f"), IVec::from(vec![0; 2])]
+    );
+    drop(index);
+    drop(zeroes);
+    drop(t);
+    drop(db);
+
+    // creating the index again after a restart indexes the
+    // writes that happened while it was not created
+    let db = Config::new().path(path).open()?;
+    let t = db.open_tree(b"users")?;
+    t.insert(b"g", vec![2])?;
+    let index = t.create_index(b"by_first_byte", first_byte)?;
+    assert_eq!(
+        indexed_keys(index.get([2]))?,
+        vec![IVec::from(b"c"), IVec::from(b"g")]
+    );
+
+    // the internal trees of indexes are hidden, and dropped with
+    // the indexed tree
+    assert_eq!(db.tree_names().len(), 2);
+    assert!(db.drop_tree(b"users")?);
+    assert!(index.get([2]).next().unwrap().is_err());
+    let t = db.open_tree(b"users")?;
+    let index = t.create_index(b"by_first_byte", first_byte)?;
+    assert_eq!(index.range::<&[u8], _>(..).count(), 0);
+
+    drop(index);
+    drop(t);
+    drop(db);
+    std::fs::remove_dir_all(path)?;
+
+    Ok(())
+}
+
+#[test]
+#[cfg_attr(miri, ignore)]
+fn tree_unique_index() -> Result<()> {
+    common::setup_logger();
+
+    let config = Config::new().temporary(true);
+    let db = config.open()?;
+    let t = db.open_tree(b"users")?;
+    let other = db.open_tree(b"other")?;
+
+    t.insert(b"a", vec![1])?;
+    t.insert(b"b", vec![1])?;
+    let duplicates = t.create_index(b"by_first_byte", first_byte)?;
+    if let Err(Error::UniqueViolation { index, key }) =
+        t.create_unique_index(b"by_first_byte", first_byte)
+    {
+        assert_eq!(index, IVec::from(b"by_first_byte"));
+        assert_eq!(key, IVec::from(vec![1]));
+    } else {
+        panic!("created a unique index of duplicate keys");
+    }
+
+    // the index that failed to replace the one with the same name
+    // leaves it as it was
+    assert_eq!(
+        indexed_keys(duplicates.get([1]))?,
+        vec![IVec::from(b"a"), IVec::from(b"b")]
+    );
+
+    t.insert(b"b", vec![2])?;
+    assert_eq!(indexed_keys(duplicates.get([2]))?, vec![IVec::from(b"b")]);
+    let index = t.create_unique_index(b"by_first_byte", first_byte)?;
+    let violation = Error::UniqueViolation {
+        index: IVec::from(b"by_first_byte"),
+        key: IVec::from(vec![1]),
+    };
+
+    // single-key writes that would duplicate an index key fail
+    assert_eq!(t.insert(b"c", vec![1]), Err(violation.clone()));
+    assert_eq!(
+        t.compare_and_swap(b"b", Some(vec![2]), Some(vec![1])),
+        Err(violation.clone())
+    );
+    assert_eq!(t.get(b"b")?, Some(IVec::from(vec![2])));
+    assert_eq!(t.get(b"c")?, None);
+
+    // but keys can be rewritten under their own index key
+    t.insert(b"a", vec![1, 1])?;
+
+    // batches that duplicate an index key fail as a whole
+    let mut batch = Batch::default();
+    batch.insert(b"c", vec![3]);
+    batch.insert(b"d", vec![3]);
+    assert_eq!(
+        t.apply_batch(batch),
+        Err(Error::UniqueViolation {
+            index: IVec::from(b"by_first_byte"),
+            key: IVec::from(vec![3]),
+        })
+    );
+    assert_eq!(t.get(b"c")?, None);
+
+    // while those that move an index key to another key succeed
+    let mut batch = Batch::default();
+    batch.remove(b"a");
+    batch.insert(b"c", vec![1]);
+    t.apply_batch(batch)?;
+    let mut batch = Batch::default();
+    batch.remove_range::<&[u8], _>(&b"b"[..]..&b"c"[..]);
+    batch.insert(b"d", vec![2]);
+    t.apply_batch(batch)?;
+    assert_eq!(indexed_keys(index.get([1]))?, vec![IVec::from(b"c")]);
+    assert_eq!(indexed_keys(index.get([2]))?, vec![IVec::from(b"d")]);
+
+    // transactions that duplicate an index key change nothing
+    let res = (&t, &other).transaction(|(tx, tx_other)| {
+        tx_other.insert(b"x", b"x")?;
+        tx.insert(b"e", vec![2])?;
+        Ok::<_, ConflictableTransactionError<()>>(())
+    });
+    if let Err(TransactionError::Storage(Error::UniqueViolation {
+        key, ..
+    })) = res
+    {
+        assert_eq!(key, IVec::from(vec![2]));
+    } else {
+        panic!("committed a transaction that duplicated an index key");
+    }
+    assert_eq!(other.get(b"x")?, None);
+    assert_eq!(t.get(b"e")?, None);
+
+    // keys that expired no longer hold their index keys
+    t.insert_with_ttl(b"f", vec![4], Duration::from_millis(1))?;
+    std::thread::sleep(Duration::from_millis(10));
+    t.insert(b"g", vec![4])?;
+    assert_eq!(indexed_keys(index.get([4]))?, vec![IVec::from(b"g")]);
+
+    // concurrent writers can't claim the same index key
+    let claimed = Arc::new(AtomicUsize::new(0));
+    let barrier = Arc::new(Barrier::new(N_THREADS));
+    let threads: Vec<_> = (0..N_THREADS)
+        .map(|i| {
+            let (t, claimed, barrier) =
+                (t.clone(), claimed.clone(), barrier.clone());
+            std::thread::spawn(move || {
+                barrier.wait();
+                match t.insert(kv(i), vec![5]) {
+                    Ok(_) => {
+                        claimed.fetch_add(1, SeqCst);
+                    }
+                    Err(Error::UniqueViolation { .. }) => {}
+                    Err(e) => panic!("{:?}", e),
+                }
+            })
+        })
+        .collect();
+
+    for thread in threads {
+        thread.join().unwrap();
+    }
+
+    assert_eq!(claimed.load(SeqCst), 1);
+    assert_eq!(index.get([5]).count(), 1);
+
+    Ok(())
+}
+
+#[test]
+fn tree_blobs() -> Result<()> {
+    use std::io::{Read, Seek, SeekFrom, Write};
+
+    common::setup_logger();
+
+    let path = "test_tree_blobs";
+    let _ = std::fs::remove_dir_all(path);
+
+    let db = Config::new().path(path).open()?;
+    let t = db.open_tree(b"files")?;
+
+    // spans several chunks, the last of which is partial
+    let value: Vec<u8> = (0..250_000_u32).map(|i| (i % 251) as u8).collect();
+
+    let mut writer = t.open_blob_writer(b"big")?;
+    for part in value.chunks(10_000) {
+        writer.write_all(part)?;
+    }
+
+    // nothing is visible before the blob is finished
+    assert_eq!(t.get(b"big")?, None);
+    assert!(t.open_blob_reader(b"big")?.is_none());
+    writer.finish()?;
+
+    assert_eq!(t.get(b"big")?, Some(IVec::from(&250_000_u64.to_be_bytes())));
+    let mut reader = t.open_blob_reader(b"big")?.unwrap();
+    assert_eq!(reader.len(), 250_000);
+    let mut read = vec![];
+    reader.read_to_end(&mut read)?;
+    assert_eq!(read, value);
+
+    // seeking across chunk boundaries
+    let mut buf = [0; 100];
+    reader.seek(SeekFrom::Start(61_400))?;
+    reader.read_exact(&mut buf)?;
+    assert_eq!(&buf[..], &value[61_400..61_500]);
+    reader.seek(SeekFrom::End(-50))?;
+    assert_eq!(reader.read(&mut buf)?, 50);
+    assert_eq!(&buf[..50], &value[249_950..]);
+    reader.seek(SeekFrom::Current(-200_000))?;
+    reader.read_exact(&mut buf)?;
+    assert_eq!(&buf[..], &value[50_000..50_100]);
+    assert!(reader.seek(SeekFrom::Current(-1_000_000)).is_err());
+
+    // an empty blob
+    t.open_blob_writer(b"empty")?.finish()?;
+    let mut empty = t.open_blob_reader(b"empty")?.unwrap();
+    assert!(empty.is_empty());
+    assert_eq!(empty.read(&mut buf)?, 0);
+
+    // a blob that is never finished leaves nothing behind
+    let mut abandoned = t.open_blob_writer(b"abandoned")?;
+    abandoned.write_all(&value)?;
+    drop(abandoned);
+    assert_eq!(t.get(b"abandoned")?, None);
+
+    // plain values are not blobs, and replacing a blob with one
+    // removes it, which readers of the old blob notice
+    t.insert(b"small", b"small")?;
+    assert!(t.open_blob_reader(b"small")?.is_none());
+
+    let mut stale = t.open_blob_reader(b"big")?.unwrap();
+    t.insert(b"big", b"replaced")?;
+    assert!(t.open_blob_reader(b"big")?.is_none());
+    assert!(stale.read(&mut buf).is_err());
+
+    // blobs replace each other
+    let mut writer = t.open_blob_writer(b"big")?;
+    writer.write_all(&value[..70_000])?;
+    writer.finish()?;
+    let mut writer = t.open_blob_writer(b"big")?;
+    writer.write_all(&value[..80_000])?;
+    writer.finish()?;
+
+    // and they survive restarts
+    drop((reader, empty, stale));
+    drop(t);
+    drop(db);
+    let db = Config::new().path(path).open()?;
+    let t = db.open_tree(b"files")?;
+
+    let mut read = vec![];
+    t.open_blob_reader(b"big")?.unwrap().read_to_end(&mut read)?;
+    assert_eq!(read, &value[..80_000]);
+
+    t.remove(b"big")?;
+    assert!(t.open_blob_reader(b"big")?.is_none());
+    assert!(t.open_blob_reader(b"empty")?.is_some());
+
+    // dropping a tree removes its blobs
+    drop(t);
+    assert!(db.drop_tree(b"files")?);
+    let t = db.open_tree(b"files")?;
+    assert!(t.open_blob_reader(b"empty")?.is_none());
+    assert!(db.open_tree(b"__sled__blobs").is_err());
+
+    drop(t);
+    drop(db);
+    let _ = std::fs::remove_dir_all(path);
+
+    Ok(())
+}
+
+#[test]
+fn tree_get_many() -> Result<()> {
+    common::setup_logger();
+
+    let config = Config::new().temporary(true);
+    let t = config.open()?;
+
+    // enough keys to span many leaves
+    for i in (0..N).step_by(2) {
+        t.insert(kv(i), kv(i))?;
+    }
+
+    let keys: Vec<Vec<u8>> = (0..N).rev().chain(vec![7, 4, 7]).map(kv).collect();
+    let expected: Vec<Option<IVec>> =
+        keys.iter().map(|k| t.get(k)).collect::<Result<_>>()?;
+    assert_eq!(t.get_many(&keys)?, expected);
+    assert_eq!(t.get_many(Vec::<Vec<u8>>::new())?, vec![]);
+
+    let lens = t.get_many_zero_copy(&keys, |v| v.map(<[u8]>::len))?;
+    let expected_lens: Vec<Option<usize>> =
+        expected.iter().map(|v| v.as_ref().map(|v| v.len())).collect();
+    assert_eq!(lens, expected_lens);
+
+    // expired keys are not returned
+    t.insert_with_ttl(kv(0), kv(0), Duration::from_millis(1))?;
+    std::thread::sleep(Duration::from_millis(10));
+    assert_eq!(t.get_many(&[kv(0), kv(2)])?, vec![None, Some(IVec::from(kv(2)))]);
+
+    Ok(())
+}
+
+#[test]
+fn tree_increment() -> TransactionResult<()> {
+    common::setup_logger();
+
+    let config = Config::new().temporary(true);
+    let t = Arc::new(config.open()?);
+
+    assert_eq!(t.increment(b"c", 5)?, 5);
+    assert_eq!(t.increment(b"c", -7)?, -2);
+    assert_eq!(t.get(b"c")?, Some(IVec::from(&(-2_i64).to_le_bytes())));
+
+    // malformed values and overflow are rejected without writing
+    t.insert(b"text", b"text")?;
+    assert_eq!(
+        t.increment(b"text", 1),
+        Err(Error::NotACounter(IVec::from(b"text")))
+    );
+    t.insert(b"max", &i64::max_value().to_le_bytes())?;
+    assert_eq!(
+        t.increment(b"max", 1),
+        Err(Error::CounterOverflow(IVec::from(b"max")))
+    );
+    assert_eq!(t.get(b"max")?, Some(IVec::from(&i64::max_value().to_le_bytes())));
+
+    // concurrent increments are never lost
+    let threads: Vec<_> = (0..N_THREADS)
+        .map(|_| {
+            let t = t.clone();
+            std::thread::spawn(move || {
+                for _ in 0..N_PER_THREAD {
+                    t.increment(b"hot", 1).unwrap();
+                }
+            })
+        })
+        .collect();
+    for thread in threads {
+        thread.join().unwrap();
+    }
+    assert_eq!(t.increment(b"hot", 0)?, N as i64);
+
+    // transactions see their own increments
+    let count = t.transaction(|tx| {
+        tx.increment(b"c", 10)?;
+        Ok(tx.increment(b"c", 10)?)
+    })?;
+    assert_eq!(count, 18);
+    assert_eq!(t.increment(b"c", 0)?, 18);
+
+    Ok(())
+}
+
+#[test]
+fn tree_transaction_options() -> TransactionResult<()> {
+    let config = Config::new().temporary(true);
+    let db = config.open()?;
+    let t = db.open_tree(b"options")?;
+
+    let counter = AtomicUsize::new(0);
+    let attempts = &counter;
+    let conflict_until = |limit: usize| {
+        move |tx: &TransactionalTree| {
+            let attempt = attempts.fetch_add(1, SeqCst) + 1;
+            if attempt < limit {
+                return Err(tx.conflict(b"k"));
+            }
+            tx.insert(b"k", &attempt.to_be_bytes()[..])?;
+            Ok(attempt)
+        }
+    };
+
+    // the limits are not reached by a closure that succeeds in time
+    let options = TransactionOptions::default().max_retries(3);
+    assert_eq!(t.transaction_with_options(&options, conflict_until(4))?, 4);
+
+    attempts.store(0, SeqCst);
+    let res = t.transaction_with_options(&options, conflict_until(5));
+    let conflict = TransactionError::<Error>::Conflict {
+        attempts: 4,
+        tree: Some(IVec::from(b"options")),
+        key: Some(IVec::from(b"k")),
+    };
+    assert_eq!(
+        res,
+        Err(TransactionError::Conflict {
+            attempts: 4,
+            tree: Some(IVec::from(b"options")),
+            key: Some(IVec::from(b"k")),
+        })
+    );
+    assert_eq!(
+        conflict.to_string(),
+        "Transaction gave up after 4 conflicting attempts, \
+         the last on key [107] of tree [111, 112, 116, 105, 111, 110, 115]"
+    );
+    assert_eq!(attempts.load(SeqCst), 4);
+
+    // with a timeout, the closure is retried until time runs out
+    attempts.store(0, SeqCst);
+    let options = TransactionOptions::default()
+        .backoff(true)
+        .timeout(Duration::from_millis(50));
+    let res = (&t, &*db).transaction_with_options(&options, |(tx, _)| {
+        conflict_until(1_000_000_000)(tx)
+    });
+    match res {
+        Err(TransactionError::Conflict { attempts: n, tree, key }) => {
+            assert_eq!(n, attempts.load(SeqCst));
+            assert_eq!(tree, Some(IVec::from(b"options")));
+            assert_eq!(key, Some(IVec::from(b"k")));
+        }
+        other => panic!("expected a conflict, got {:?}", other),
+    }
+
+    // without limits, the closure is retried until it succeeds
+    attempts.store(0, SeqCst);
+    let default = TransactionOptions::default();
+    assert_eq!(t.transaction_with_options(&default, conflict_until(20))?, 20);
+    assert_eq!(t.get(b"k")?, Some(IVec::from(&20_usize.to_be_bytes())));
+
+    Ok(())
+}
+
+#[test]
+fn tree_read_transaction() -> TransactionResult<(), &'static str> {
+    let config = Config::new().temporary(true);
+    let db = config.open()?;
+    let a = db.open_tree(b"a")?;
+    let b = db.open_tree(b"b")?;
+    a.insert(b"count", &10_u64.to_be_bytes())?;
+    b.insert(b"count", &0_u64.to_be_bytes())?;
+
+    let count = |value: Option<IVec>| {
+        let mut buf = [0; 8];
+        buf.copy_from_slice(&value.unwrap());
+        u64::from_be_bytes(buf)
+    };
+
+    // transactions moving counts between the trees are never
+    // observed halfway by a read transaction
+    let moves = 200;
+    let mover = {
+        let (a, b) = (a.clone(), b.clone());
+        std::thread::spawn(move || {
+            for i in 0..moves {
+                let (from, to) = if i % 2 == 0 { (&a, &b) } else { (&b, &a) };
+                (from, to)
+                    .transaction(|(tx_from, tx_to)| {
+                        let moved = count(tx_from.get(b"count")?);
+                        tx_from.insert(b"count", &0_u64.to_be_bytes())?;
+                        let sum = count(tx_to.get(b"count")?) + moved;
+                        tx_to.insert(b"count", &sum.to_be_bytes())?;
+                        Ok::<_, ConflictableTransactionError<()>>(())
+                    })
+                    .unwrap();
+            }
+        })
+    };
+    for _ in 0..moves {
+        let total = (&a, &b).read_transaction(|(tx_a, tx_b)| {
+            Ok(count(tx_a.get(b"count")?) + count(tx_b.get(b"count")?))
+        })?;
+        assert_eq!(total, 10);
+    }
+    mover.join().unwrap();
+
+    // single-key writes are not blocked but are not visible, even
+    // to keys that had not been read yet, and ranges work as in
+    // other transactions
+    a.insert(b"x", b"1")?;
+    let (first, items) = a.read_transaction(|tx_a| {
+        let first = tx_a.first()?.unwrap();
+        let x = tx_a.get(b"x")?;
+        let writer = a.clone();
+        std::thread::spawn(move || {
+            writer.insert(b"x", b"2")?;
+            writer.insert(b"y", b"1")
+        })
+        .join()
+        .unwrap()?;
+        assert_eq!(tx_a.get(b"x")?, x);
+        assert_eq!(tx_a.get(b"y")?, None);
+        assert_eq!(tx_a.get_gt(b"x")?, None);
+        let items: Vec<_> =
+            tx_a.range::<&[u8], _>(..).map(|item| item.unwrap().0).collect();
+        Ok((first, items))
+    })?;
+    assert_eq!(first.0, IVec::from(b"count"));
+    assert_eq!(items, vec![IVec::from(b"count"), IVec::from(b"x")]);
+    assert_eq!(a.get(b"x")?, Some(IVec::from(b"2")));
+    assert_eq!(a.get(b"y")?, Some(IVec::from(b"1")));
+
+    let res = a.read_transaction(|_| abort::<(), _>("nope"));
+    assert_eq!(res, Err(TransactionError::Abort("nope")));
+
+    Ok(())
+}
+
+#[test]
+fn tree_transaction_savepoints() -> TransactionResult<()> {
+    let config = Config::new().temporary(true);
+    let db = config.open()?;
+    let a = db.open_tree(b"a")?;
+    let b = db.open_tree(b"b")?;
+    b.insert(b"r1", b"kept")?;
+    b.insert(b"r2", b"kept")?;
+
+    (&a, &b).transaction(|(tx_a, tx_b)| {
+        tx_a.insert(b"k", b"1")?;
+        let outer = tx_b.savepoint();
+
+        tx_a.insert(b"k", b"2")?;
+        let mut batch = Batch::default();
+        batch.remove_range(b"r".as_ref()..);
+        tx_b.apply_batch(&batch)?;
+        let inner = tx_a.savepoint();
+
+        tx_a.insert(b"k", b"3")?;
+        tx_b.insert(b"new", b"x")?;
+        inner.rollback_to();
+        assert_eq!(tx_a.get(b"k")?, Some(IVec::from(b"2")));
+        assert_eq!(tx_b.get(b"new")?, None);
+        assert_eq!(tx_b.first()?, None);
+
+        // rolling back to an earlier savepoint undoes writes to
+        // every tree, and savepoints can be rolled back to again
+        outer.rollback_to();
+        assert_eq!(tx_a.get(b"k")?, Some(IVec::from(b"1")));
+        assert_eq!(tx_b.scan_prefix(b"r").count(), 2);
+        tx_b.remove(b"r1")?;
+        outer.rollback_to();
+        Ok(())
+    })?;
+
+    assert_eq!(a.get(b"k")?, Some(IVec::from(b"1")));
+    assert_eq!(b.len(), 2);
+    assert_eq!(b.get(b"new")?, None);
+
+    Ok(())
+}
+
+#[test]
+fn tree_transactional_read_modify_write() -> TransactionResult<()> {
+    fn concatenate(
+        _key: &[u8],
+        old: Option<&[u8]>,
+        merged: &[u8],
+    ) -> Option<Vec<u8>> {
+        let mut ret = old.map(<[u8]>::to_vec).unwrap_or_default();
+        ret.extend_from_slice(merged);
+        Some(ret)
+    }
+
+    let config = Config::new().temporary(true);
+    let db = config.open()?;
+    let t = db.open_tree(b"rmw")?;
+    t.insert(b"cas", b"a")?;
+
+    let unmerged: TransactionResult<()> = t.transaction(|tx| {
+        tx.merge(b"log", b"x")?;
+        Ok(())
+    });
+    if let Err(TransactionError::Storage(Error::Unsupported(_))) = unmerged {
+    } else {
+        panic!("merged without a merge operator");
+    }
+    t.set_merge_operator(concatenate);
+
+    t.transaction(|tx| {
+        // compare and swap sees the writes of the transaction
+        let failed = tx.compare_and_swap(b"cas", Some(b"b"), Some(b"c"))?;
+        assert_eq!(
+            failed,
+            Err(CompareAndSwapError {
+                current: Some(IVec::from(b"a")),
+                proposed: Some(IVec::from(b"c")),
+            })
+        );
+        tx.compare_and_swap(b"cas", Some(b"a"), Some(b"b"))?.unwrap();
+        tx.compare_and_swap(b"cas", Some(b"b"), None as Option<&[u8]>)?
+            .unwrap();
+        tx.compare_and_swap(b"cas", None as Option<&[u8]>, Some(b"d"))?
+            .unwrap();
+
+        assert_eq!(tx.merge(b"log", b"1")?, Some(IVec::from(b"1")));
+        assert_eq!(tx.merge(b"log", b"2")?, Some(IVec::from(b"12")));
+
+        let bump = |old: Option<&[u8]>| {
+            let mut new = old.map(<[u8]>::to_vec).unwrap_or_default();
+            new.push(b'+');
+            Some(new)
+        };
+        assert_eq!(tx.update_and_fetch(b"up", bump)?, Some(IVec::from(b"+")));
+        assert_eq!(tx.fetch_and_update(b"up", bump)?, Some(IVec::from(b"+")));
+        assert_eq!(
+            tx.fetch_and_update(b"up", |_| None as Option<IVec>)?,
+            Some(IVec::from(b"++"))
+        );
+        Ok(())
+    })?;
+
+    assert_eq!(t.get(b"cas")?, Some(IVec::from(b"d")));
+    assert_eq!(t.get(b"log")?, Some(IVec::from(b"12")));
+    assert_eq!(t.get(b"up")?, None);
+
+    Ok(())
+}
+
+#[test]
+fn tree_dynamic_transactions() -> TransactionResult<()> {
+    let config = Config::new().temporary(true);
+    let db = config.open()?;
+    let trees: Vec<Tree> = (0..4)
+        .map(|i| db.open_tree(format!("tenant_{}", i)))
+        .collect::<Result<_>>()?;
+
+    trees.transaction(|tx_trees| {
+        for (i, tx_tree) in tx_trees.iter().enumerate() {
+            tx_tree.insert(b"id", &*i.to_string())?;
+        }
+        Ok(())
+    })?;
+    let chosen: &[Tree] = &trees[1..3];
+    chosen.transaction(|tx_trees| {
+        tx_trees[1].insert(b"id", tx_trees[0].remove(b"id")?.unwrap())?;
+        Ok(())
+    })?;
+    assert_eq!(trees[1].get(b"id")?, None);
+    assert_eq!(trees[2].get(b"id")?, Some(IVec::from(b"1")));
+
+    let by_name: std::collections::HashMap<IVec, Tree> =
+        trees.iter().map(|tree| (tree.name(), tree.clone())).collect();
+    by_name.transaction(|tx_trees| {
+        assert_eq!(tx_trees.len(), 4);
+        tx_trees[b"tenant_3".as_ref()].remove(b"id")?;
+        Ok(())
+    })?;
+    assert_eq!(trees[3].get(b"id")?, None);
+    let ids = by_name.read_transaction(|tx_trees| {
+        Ok(tx_trees[b"tenant_0".as_ref()].get(b"id")?)
+    })?;
+    assert_eq!(ids, Some(IVec::from(b"0")));
+
+    let mut misnamed = by_name.clone();
+    misnamed.insert(IVec::from(b"other"), trees[0].clone());
+    let res: TransactionResult<()> = misnamed.transaction(|_| Ok(()));
+    if let Err(TransactionError::Storage(Error::Unsupported(_))) = res {
+    } else {
+        panic!("ran a transaction on a tree keyed by another name");
+    }
+
+    // the closure still runs when no trees are chosen
+    let none: Vec<Tree> = vec![];
+    let len = none.transaction(|tx_trees| Ok(tx_trees.len()))?;
+    assert_eq!(len, 0);
+    let len = none.read_transaction(|tx_trees| Ok(tx_trees.len()))?;
+    assert_eq!(len, 0);
+    let options = TransactionOptions::default().max_retries(1);
+    let res: TransactionResult<()> = none
+        .transaction_with_options(&options, |_| {
+            Err(ConflictableTransactionError::Conflict)
+        });
+    assert_eq!(
+        res,
+        Err(TransactionError::Conflict { attempts: 2, tree: None, key: None })
+    );
+    let none_by_name: std::collections::HashMap<IVec, Tree> =
+        std::collections::HashMap::new();
+    let len = none_by_name.transaction(|tx_trees| Ok(tx_trees.len()))?;
+    assert_eq!(len, 0);
+
+    Ok(())
+}
+
+#[test]
+fn tree_queue() -> Result<()> {
+    use std::{
+        future::Future,
+        pin::Pin,
+        task::{Context, Poll, RawWaker, RawWakerVTable, Waker},
+        time::Instant,
+    };
+
+    common::setup_logger();
+
+    let config = Config::new().temporary(true);
+    let t = config.open()?;
+    let short = Duration::from_millis(10);
+    let long = Duration::from_secs(60);
+
+    // times out when nothing appears under the prefix
+    t.insert(b"other", b"other")?;
+    assert_eq!(t.pop_min_blocking(b"q/", short)?, None);
+
+    // wakes up as soon as an item is inserted
+    let producer = t.clone();
+    let thread = std::thread::spawn(move || {
+        std::thread::sleep(Duration::from_millis(50));
+        producer.insert(b"q/1", b"1").unwrap();
+    });
+    let (key, value) = t.pop_min_blocking(b"q/", long)?.unwrap();
+    assert_eq!((key.as_ref(), value.as_ref()), (&b"q/1"[..], &b"1"[..]));
+    thread.join().unwrap();
+    assert_eq!(t.get(b"q/1")?, None);
+    assert_eq!(t.get(b"other")?, Some(IVec::from(b"other")));
+
+    // leased items are hidden until the lease is released or dropped
+    t.insert(b"q/1", b"1")?;
+    t.insert(b"q/2", b"2")?;
+    let first = t.lease_min_blocking(b"q/", short, long)?.unwrap();
+    assert_eq!(first.key(), b"q/1");
+    let second = t.lease_min_blocking(b"q/", short, long)?.unwrap();
+    assert_eq!(second.key(), b"q/2");
+    assert!(t.lease_min_blocking(b"q/", short, long)?.is_none());
+    assert_eq!(t.pop_min_blocking(b"q/", short)?, None);
+
+    first.release();
+    drop(second);
+    let first = t.lease_min_blocking(b"q/", short, long)?.unwrap();
+    assert_eq!(first.key(), b"q/1");
+    assert!(first.complete()?);
+    assert_eq!(t.get(b"q/1")?, None);
+
+    // an expired lease is handed out again to a waiting worker, and
+    // can no longer be completed by the worker that held it
+    let expiring =
+        t.lease_min_blocking(b"q/", short, Duration::from_millis(50))?.unwrap();
+    assert_eq!(expiring.key(), b"q/2");
+    let start = Instant::now();
+    let again = t.lease_min_blocking(b"q/", long, long)?.unwrap();
+    assert!(start.elapsed() < Duration::from_secs(10));
+    assert_eq!(again.key(), b"q/2");
+    assert!(!expiring.complete()?);
+    assert!(again.complete()?);
+    assert!(t.scan_prefix(b"q/").next().is_none());
+
+    // concurrent pops and leases never take the same item twice
+    for i in 0..200_u64 {
+        let mut key = b"q/".to_vec();
+        key.extend_from_slice(&i.to_be_bytes());
+        t.insert(key, b"job")?;
+    }
+    let workers: Vec<_> = (0..4)
+        .map(|worker| {
+            let t = t.clone();
+            std::thread::spawn(move || {
+                let mut taken = vec![];
+                loop {
+                    let key = if worker % 2 == 0 {
+                        t.pop_min_blocking(b"q/", short).unwrap().map(|kv| kv.0)
+                    } else {
+                        let lease = t.lease_min_blocking(b"q/", short, long);
+                        lease.unwrap().map(|leased| {
+                            let key = leased.key().clone();
+                            assert!(leased.complete().unwrap());
+                            key
+                        })
+                    };
+                    match key {
+                        Some(key) => taken.push(key),
+                        None => return taken,
+                    }
+                }
+            })
+        })
+        .collect();
+    let mut taken: Vec<IVec> =
+        workers.into_iter().flat_map(|w| w.join().unwrap()).collect();
+    taken.sort();
+    taken.dedup();
+    assert_eq!(taken.len(), 200);
+    assert!(t.scan_prefix(b"q/").next().is_none());
+
+    // the async version waits on the same subscriber machinery
+    fn noop_raw_waker() -> RawWaker {
+        fn clone(_: *const ()) -> RawWaker {
+            noop_raw_waker()
+        }
+        fn noop(_: *const ()) {}
+        static VTABLE: RawWakerVTable =
+            RawWakerVTable::new(clone, noop, noop, noop);
+        RawWaker::new(std::ptr::null(), &VTABLE)
+    }
+    let waker = unsafe { Waker::from_raw(noop_raw_waker()) };
+    let mut cx = Context::from_waker(&waker);
+
+    let mut popping = Box::pin(t.pop_min_async(b"q/"));
+    assert!(Pin::new(&mut popping).poll(&mut cx).is_pending());
+    t.insert(b"q/3", b"3")?;
+    match Pin::new(&mut popping).poll(&mut cx) {
+        Poll::Ready(Ok(Some((key, _value)))) => assert_eq!(key, b"q/3"),
+        other => panic!("expected an item, got {:?}", other),
+    }
+    assert_eq!(t.get(b"q/3")?, None);
+
+    Ok(())
+}
+
 #[test]
 #[cfg_attr(miri, ignore)]
 fn recover_tree() {
@@ -169,6 +169,329 @@ fn test_typed_update_fetch(
     Ok(())
 }
 
-// TODO: Iterators
-// TODO: Transactions
-// TODO: Subscribers and Events
+#[test]
+fn test_typed_transactions(
+) -> std::result::Result<(), Box<dyn std::error::Error>> {
+    use sled::{
+        transaction::{abort, ConflictableTransactionError},
+        Transactional,
+    };
+
+    let db = sled::Config::default().temporary(true).open()?;
+
+    let names = db
+        .open_tree("id_to_name")?
+        .with_encodings::<sled::IntegerEncoding<u128>, sled::StringEncoding>();
+    let ids = db
+        .open_tree("name_to_id")?
+        .with_encodings::<sled::StringEncoding, sled::IntegerEncoding<u128>>();
+
+    names.insert(1, "January")?;
+
+    names.transaction(|tx| {
+        assert!(tx.insert(2, "Febuary")?.is_none());
+        assert_eq!(
+            tx.insert(2, "February")?.unwrap().decode().unwrap(),
+            "Febuary"
+        );
+        assert_eq!(tx.get(2)?.unwrap().decode().unwrap(), "February");
+
+        let mut batch = names.make_batch();
+        batch.insert(3, "March");
+        tx.apply_batch(&batch)?;
+
+        assert_eq!(tx.first()?.unwrap().0.decode().unwrap(), 1);
+        assert_eq!(tx.last()?.unwrap().1.decode().unwrap(), "March");
+        assert_eq!(tx.get_lt(3)?.unwrap().0.decode().unwrap(), 2);
+        assert_eq!(tx.get_gt(1)?.unwrap().1.decode().unwrap(), "February");
+        Ok::<_, ConflictableTransactionError>(())
+    })?;
+    assert_eq!(names.len(), 3);
+
+    // aborted transactions write nothing
+    let res = names.transaction(|tx| {
+        tx.remove(1)?;
+        abort::<(), _>("changed my mind")
+    });
+    assert!(res.is_err());
+    assert_eq!(names.get(1)?.unwrap().decode()?, "January");
+
+    // transactions can span trees with different encodings
+    (&names, &ids).transaction(|(tx_names, tx_ids)| {
+        let name = tx_names.remove(1)?.unwrap();
+        tx_ids.insert(name.decode().unwrap(), 1)?;
+        Ok::<_, ConflictableTransactionError>(())
+    })?;
+    assert!(names.get(1)?.is_none());
+    assert_eq!(ids.get("January")?.unwrap().decode()?, 1);
+
+    Ok(())
+}
+
+#[test]
+fn test_typed_iterators(
+) -> std::result::Result<(), Box<dyn std::error::Error>> {
+    let db = sled::Config::default().temporary(true).open()?;
+
+    let names = db
+        .open_tree("id_to_name")?
+        .with_encodings::<sled::IntegerEncoding<u128>, sled::StringEncoding>();
+    let ids = db
+        .open_tree("name_to_id")?
+        .with_encodings::<sled::StringEncoding, sled::IntegerEncoding<u128>>();
+
+    for (id, name) in
+        [(1, "January"), (2, "February"), (3, "March"), (4, "April")].iter()
+    {
+        names.insert(*id, name)?;
+        ids.insert(name, *id)?;
+    }
+
+    let mut iter = names.iter();
+    let (key, value) = iter.next().unwrap()?;
+    assert_eq!(key.decode()?, 1);
+    assert_eq!(value.decode()?, "January");
+    let (key, value) = iter.next_back().unwrap()?;
+    assert_eq!(key.decode()?, 4);
+    assert_eq!(value.decode()?, "April");
+    assert_eq!(iter.count(), 2);
+
+    let keys: Vec<u128> = names
+        .range(2..=3)
+        .keys()
+        .map(|res| res.unwrap().decode().unwrap())
+        .collect();
+    assert_eq!(keys, vec![2, 3]);
+
+    let keys: Vec<u128> = names
+        .range(..3)
+        .keys()
+        .rev()
+        .map(|res| res.unwrap().decode().unwrap())
+        .collect();
+    assert_eq!(keys, vec![2, 1]);
+
+    assert_eq!(names.range(3..).last().unwrap()?.1.decode()?, "April");
+    assert!(names.range(5..).next().is_none());
+
+    let mut iter = ids.range("F".."K");
+    assert_eq!(iter.next().unwrap()?.0.decode()?, "February");
+    assert_eq!(iter.next().unwrap()?.0.decode()?, "January");
+    assert!(iter.next().is_none());
+
+    let mut iter = ids.scan_prefix("Ma").values();
+    assert_eq!(iter.next().unwrap()?.decode()?, 3);
+    assert!(iter.next().is_none());
+
+    // bytes that don't match the encoding are decoding errors
+    (*ids).insert(vec![0xff], vec![1, 2, 3])?;
+    let (key, value) = ids.iter().next_back().unwrap()?;
+    assert!(key.decode().is_err());
+    assert!(value.decode().is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_typed_ordered_encodings(
+) -> std::result::Result<(), Box<dyn std::error::Error>> {
+    use sled::{BoolEncoding, BytesEncoding, FloatEncoding, IntegerEncoding};
+
+    let db = sled::Config::default().temporary(true).open()?;
+
+    // (tenant_id, timestamp, uuid) composite keys
+    let events = db.open_tree("events")?.with_encodings::<(
+        IntegerEncoding<u64>,
+        IntegerEncoding<i64>,
+        BytesEncoding,
+    ), FloatEncoding<f64>>();
+
+    // timestamps are never earlier than this
+    let earliest = -1_000_000;
+    let uuid_a = [0_u8; 16];
+    let uuid_b = [0xff_u8; 16];
+    events.insert((2, -5, &uuid_b), 1.5)?;
+    events.insert((1, 7, &uuid_a), -0.25)?;
+    events.insert((2, -5, &uuid_a), 3.0)?;
+    events.insert((2, earliest, &uuid_b), -8.0)?;
+    events.insert((2, 3, &uuid_a), 0.0)?;
+    events.insert((3, -100, &uuid_a), 100.0)?;
+
+    let tenant_2: Vec<(i64, Vec<u8>, f64)> = events
+        .range((2, earliest, &[][..])..(3, earliest, &[][..]))
+        .map(|res| {
+            let (key, value) = res.unwrap();
+            let (tenant, timestamp, uuid) = key.decode().unwrap();
+            assert_eq!(tenant, 2);
+            (timestamp, uuid, value.decode().unwrap())
+        })
+        .collect();
+    assert_eq!(
+        tenant_2,
+        vec![
+            (earliest, uuid_b.to_vec(), -8.0),
+            (-5, uuid_a.to_vec(), 3.0),
+            (-5, uuid_b.to_vec(), 1.5),
+            (3, uuid_a.to_vec(), 0.0),
+        ]
+    );
+
+    // events of tenant 2 at negative timestamps, newest first
+    let mut iter =
+        events.range((2, earliest, &[][..])..(2, 0, &[][..])).keys().rev();
+    assert_eq!(iter.next().unwrap()?.decode()?.2, uuid_b.to_vec());
+    assert_eq!(iter.next().unwrap()?.decode()?.2, uuid_a.to_vec());
+    assert_eq!(iter.next().unwrap()?.decode()?.1, earliest);
+    assert!(iter.next().is_none());
+
+    let flags = db
+        .open_tree("flags")?
+        .with_encodings::<(BoolEncoding, IntegerEncoding<i8>), BoolEncoding>();
+    flags.insert((true, -1), false)?;
+    flags.insert((false, 1), true)?;
+    flags.insert((false, -1), true)?;
+    assert_eq!(flags.first()?.unwrap().0.decode()?, (false, -1));
+    assert_eq!(flags.last()?.unwrap().0.decode()?, (true, -1));
+
+    // bytes that don't match the encoding are decoding errors
+    (*flags).insert(vec![2, 0], vec![])?;
+    assert_eq!(
+        flags.last()?.unwrap().0.decode().unwrap_err(),
+        sled::DecodeError::InvalidByte(2)
+    );
+
+    Ok(())
+}
+
+#[cfg(feature = "serde")]
+#[test]
+fn test_typed_serde() -> std::result::Result<(), Box<dyn std::error::Error>> {
+    use serde_crate::{Deserialize, Serialize};
+    use sled::{IntegerEncoding, SerdeEncoding};
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    #[serde(crate = "serde_crate")]
+    struct Month {
+        name: String,
+        days: u8,
+        holidays: Vec<String>,
+    }
+
+    let db = sled::Config::default().temporary(true).open()?;
+
+    let months = db
+        .open_tree("months")?
+        .with_encodings::<IntegerEncoding<u8>, SerdeEncoding<Month>>();
+
+    let january = Month {
+        name: "January".to_string(),
+        days: 31,
+        holidays: vec!["New Year's Day".to_string()],
+    };
+    let february =
+        Month { name: "February".to_string(), days: 28, holidays: vec![] };
+
+    assert!(months.insert(1, &january)?.is_none());
+    assert!(months.insert(2, &february)?.is_none());
+
+    assert_eq!(months.get(1)?.unwrap().decode()?, january);
+    assert_eq!(months.last()?.unwrap().1.decode()?.name, "February");
+
+    let mut batch = months.make_batch();
+    batch.insert(2, &Month { days: 29, ..february });
+    months.apply_batch(batch)?;
+    assert_eq!(months.get(2)?.unwrap().decode()?.days, 29);
+
+    // bytes that don't match the encoding are decoding errors
+    (*months).insert(vec![3], vec![1, 2, 3])?;
+    assert!(months.get(3)?.unwrap().decode().is_err());
+
+    Ok(())
+}
+
+#[test]
+fn test_typed_subscribers(
+) -> std::result::Result<(), Box<dyn std::error::Error>> {
+    use std::{
+        future::Future,
+        pin::Pin,
+        task::{Context, Poll, RawWaker, RawWakerVTable, Waker},
+    };
+
+    use sled::{
+        transaction::ConflictableTransactionError, IntegerEncoding,
+        StringEncoding, Transactional, TypedEventError,
+    };
+
+    let db = sled::Config::default().temporary(true).open()?;
+
+    let names = db
+        .open_tree("id_to_name")?
+        .with_encodings::<IntegerEncoding<u128>, StringEncoding>();
+    let ids = db
+        .open_tree("name_to_id")?
+        .with_encodings::<StringEncoding, IntegerEncoding<u128>>();
+
+    let mut all_names = names.watch_prefix(vec![]);
+    let mut ju_ids = ids.watch_prefix("Ju");
+
+    names.insert(1, "January")?;
+    let event = all_names.next().unwrap();
+    let changes = event.iter().collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(changes, vec![(1, Some("January"))]);
+
+    names.remove(1)?;
+    let event = all_names.next().unwrap();
+    let changes = event.iter().collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(changes, vec![(1, None)]);
+
+    // only the changes of the watched tree and prefix are decoded
+    (&names, &ids).transaction(|(tx_names, tx_ids)| {
+        tx_names.insert(6, "June")?;
+        tx_ids.insert("June", 6)?;
+        tx_ids.insert("March", 3)?;
+        Ok::<_, ConflictableTransactionError>(())
+    })?;
+    let event = ju_ids.next().unwrap();
+    let changes = event.iter().collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(changes, vec![("June", Some(6))]);
+    assert_eq!(event.into_event().iter().count(), 3);
+    let event = all_names.next().unwrap();
+    let changes = event.iter().collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(changes, vec![(6, Some("June"))]);
+
+    // bytes that don't match the encoding are decoding errors
+    (*ids).insert("Jul", vec![7])?;
+    let event = ju_ids.next().unwrap();
+    let changes: Vec<_> = event.iter().collect();
+    assert_eq!(
+        changes,
+        vec![Err(TypedEventError::Value(sled::DecodeError::UnexpectedEnd))]
+    );
+
+    // `TypedSubscriber` is also a `Future`
+    fn noop_raw_waker() -> RawWaker {
+        fn clone(_: *const ()) -> RawWaker {
+            noop_raw_waker()
+        }
+        fn noop(_: *const ()) {}
+        static VTABLE: RawWakerVTable =
+            RawWakerVTable::new(clone, noop, noop, noop);
+        RawWaker::new(std::ptr::null(), &VTABLE)
+    }
+    let waker = unsafe { Waker::from_raw(noop_raw_waker()) };
+    let mut cx = Context::from_waker(&waker);
+
+    let mut subscriber = names.watch_prefix(vec![]);
+    assert!(Pin::new(&mut subscriber).poll(&mut cx).is_pending());
+    names.insert(2, "February")?;
+    match Pin::new(&mut subscriber).poll(&mut cx) {
+        Poll::Ready(Some(event)) => {
+            let changes = event.iter().collect::<Result<Vec<_>, _>>()?;
+            assert_eq!(changes, vec![(2, Some("February"))]);
+        }
+        _ => panic!("expected an event"),
+    }
+
+    Ok(())
+}